@@ -0,0 +1,724 @@
+use std::ffi::{c_char, c_uint, c_void, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use jboy::cheats::{Cheat, CheatList};
+use jboy::emulation::CYCLES_PER_FRAME;
+use jboy::frontend::{self, AudioSink, Framebuffer, InputSource, VideoSink};
+use jboy::gameboy::{GameBoy, SAMPLE_RATE, CHANNELS};
+use jboy::graphics::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use jboy::joypad;
+use jboy::palettes::{Palette, Palettes};
+use jboy::speed::CLOCK_SPEED;
+
+/*
+ *  libretro
+ *  The emulator as a libretro core. Cargo names the library
+ *  libjboy_libretro.so (jboy_libretro.dll, libjboy_libretro.dylib), which
+ *  goes in RetroArch's cores folder as it is. Everything here follows
+ *  libretro.h. Frontends may call in from more than one thread, so the
+ *  machine and its callbacks live behind mutexes. A panic can't unwind into
+ *  the frontend, so every entry point runs inside guard, and one that
+ *  panics stops the core and asks the frontend to shut it down
+ */
+
+pub const API_VERSION: c_uint = 1;
+
+const ENVIRONMENT_SHUTDOWN: c_uint = 7;
+const ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const ENVIRONMENT_SET_INPUT_DESCRIPTORS: c_uint = 11;
+const ENVIRONMENT_SET_MEMORY_MAPS: c_uint = 36 | 0x10000;
+
+const PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const REGION_NTSC: c_uint = 0;
+const DEVICE_JOYPAD: c_uint = 1;
+
+pub const MEMORY_SAVE_RAM: c_uint = 0;
+pub const MEMORY_SYSTEM_RAM: c_uint = 2;
+pub const MEMORY_VIDEO_RAM: c_uint = 3;
+
+const MEMDESC_CONST: u64 = 1 << 0;
+const MEMDESC_SYSTEM_RAM: u64 = 1 << 2;
+const MEMDESC_SAVE_RAM: u64 = 1 << 3;
+const MEMDESC_VIDEO_RAM: u64 = 1 << 4;
+
+/// The joypad's buttons by libretro id, which has Y at 1 and A at 8
+const BUTTONS: [(c_uint, u8, &CStr); 8] = [
+    (0, joypad::B, c"B"),
+    (2, joypad::SELECT, c"Select"),
+    (3, joypad::START, c"Start"),
+    (4, joypad::UP, c"Up"),
+    (5, joypad::DOWN, c"Down"),
+    (6, joypad::LEFT, c"Left"),
+    (7, joypad::RIGHT, c"Right"),
+    (8, joypad::A, c"A"),
+];
+
+/*
+ *  Memory Map
+ *  Where each part of the address space is, as (address, where its bytes
+ *  are, length, flags). Achievement tools like rcheevos read memory
+ *  through these, by the gameboy's own addresses. Echo ram shows wram again
+ */
+pub const MEMORY_MAP: [(usize, usize, usize, u64); 8] = [
+    (0x0000, 0x0000, 0x8000, MEMDESC_CONST),
+    (0x8000, 0x8000, 0x2000, MEMDESC_VIDEO_RAM),
+    (0xA000, 0xA000, 0x2000, MEMDESC_SAVE_RAM),
+    (0xC000, 0xC000, 0x2000, MEMDESC_SYSTEM_RAM),
+    (0xE000, 0xC000, 0x1E00, MEMDESC_SYSTEM_RAM),
+    (0xFE00, 0xFE00, 0x00A0, 0),
+    (0xFF00, 0xFF00, 0x0080, 0),
+    (0xFF80, 0xFF80, 0x0080, MEMDESC_SYSTEM_RAM),
+];
+
+pub type EnvironmentFn = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type VideoRefreshFn = unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type AudioSampleFn = unsafe extern "C" fn(left: i16, right: i16);
+pub type AudioSampleBatchFn = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type InputPollFn = unsafe extern "C" fn();
+pub type InputStateFn = unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[repr(C)]
+pub struct SystemInfo {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    pub geometry: GameGeometry,
+    pub timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
+
+#[repr(C)]
+pub struct Variable {
+    pub key: *const c_char,
+    pub value: *const c_char,
+}
+
+#[repr(C)]
+pub struct InputDescriptor {
+    pub port: c_uint,
+    pub device: c_uint,
+    pub index: c_uint,
+    pub id: c_uint,
+    pub description: *const c_char,
+}
+
+#[repr(C)]
+pub struct MemoryDescriptor {
+    pub flags: u64,
+    pub ptr: *mut c_void,
+    pub offset: usize,
+    pub start: usize,
+    pub select: usize,
+    pub disconnect: usize,
+    pub len: usize,
+    pub addrspace: *const c_char,
+}
+
+#[repr(C)]
+pub struct MemoryMap {
+    pub descriptors: *const MemoryDescriptor,
+    pub num_descriptors: c_uint,
+}
+
+const PALETTE_KEY: &CStr = c"jboy_palette";
+
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+impl Callbacks {
+
+    /// Asks the frontend something, false if it can't answer or there's no frontend yet
+    fn environment(&self, cmd: c_uint, data: *mut c_void) -> bool {
+        match self.environment {
+            Some(environment) => unsafe { environment(cmd, data) },
+            None => false,
+        }
+    }
+
+}
+
+/// One loaded game. The GameBoy is boxed so the memory map stays put until unload
+struct Core {
+    gameboy: Box<GameBoy>,
+    palettes: Palettes,
+    video: Vec<u32>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+/// Set once an entry point panicked, nothing runs after that
+static DEAD: AtomicBool = AtomicBool::new(false);
+
+/// Locks a mutex, even one a panic left poisoned since guard stops the core then anyway
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn callbacks() -> Callbacks {
+    *lock(&CALLBACKS)
+}
+
+fn set_callback(set: impl FnOnce(&mut Callbacks)) {
+    set(&mut lock(&CALLBACKS));
+}
+
+/// Runs f on the loaded game, None if there isn't one
+fn with_core<T>(f: impl FnOnce(&mut Core) -> T) -> Option<T> {
+    lock(&CORE).as_mut().map(f)
+}
+
+/*
+ *  Runs the body of an entry point. Once the core is dead, or if the body
+ *  panics, the entry point returns fallback instead. The machine is kept
+ *  after a panic, the frontend may still hold pointers into its memory
+ */
+fn guard<T>(fallback: T, body: impl FnOnce() -> T) -> T {
+    if DEAD.load(Ordering::SeqCst) {
+        return fallback;
+    }
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(value) => value,
+        Err(_) => {
+            DEAD.store(true, Ordering::SeqCst);
+            callbacks().environment(ENVIRONMENT_SHUTDOWN, std::ptr::null_mut());
+            fallback
+        },
+    }
+}
+
+/// True once an entry point panicked
+pub fn is_dead() -> bool {
+    DEAD.load(Ordering::SeqCst)
+}
+
+/// Cartridge ram from the header's ram size, only the first bank as there's no mbc
+pub fn save_ram_size(rom: &[u8]) -> usize {
+    match rom.get(0x149) {
+        None | Some(0) => 0,
+        Some(1) => 0x800,
+        Some(_) => 0x2000,
+    }
+}
+
+/// The frontend's half of a frame, the callbacks and where to draw
+struct Retro<'a> {
+    callbacks: Callbacks,
+    palette: &'a Palette,
+    video: &'a mut Vec<u32>,
+}
+
+impl VideoSink for Retro<'_> {
+    fn present(&mut self, frame: &Framebuffer) {
+        *self.video = frame.iter()
+            .flatten()
+            .map(|p| {
+                let [r, g, b] = p.color(self.palette);
+                ((r as u32) << 16) | ((g as u32) << 8) | b as u32
+            })
+            .collect();
+        if let Some(video_refresh) = self.callbacks.video_refresh {
+            unsafe {
+                video_refresh(self.video.as_ptr() as *const c_void, SCREEN_WIDTH as c_uint, SCREEN_HEIGHT as c_uint, SCREEN_WIDTH * 4);
+            }
+        }
+    }
+}
+
+impl AudioSink for Retro<'_> {
+    fn queue(&mut self, samples: &[i16]) {
+        if let Some(audio_sample_batch) = self.callbacks.audio_sample_batch {
+            unsafe {
+                audio_sample_batch(samples.as_ptr(), samples.len() / CHANNELS as usize);
+            }
+        }
+    }
+}
+
+impl InputSource for Retro<'_> {
+    fn poll(&mut self) {
+        if let Some(input_poll) = self.callbacks.input_poll {
+            unsafe { input_poll() };
+        }
+    }
+
+    fn buttons(&self) -> u8 {
+        let input_state = match self.callbacks.input_state {
+            Some(input_state) => input_state,
+            None => return 0,
+        };
+        BUTTONS.iter()
+            .filter(|(id, _, _)| unsafe { input_state(0, DEVICE_JOYPAD, 0, *id) } != 0)
+            .fold(0, |mask, (_, button, _)| mask | button)
+    }
+}
+
+/// Picks the palette chosen in the core options, if the frontend has one
+fn update_palette(callbacks: &Callbacks, palettes: &mut Palettes) {
+    let mut variable = Variable { key: PALETTE_KEY.as_ptr(), value: std::ptr::null() };
+    if callbacks.environment(ENVIRONMENT_GET_VARIABLE, &mut variable as *mut Variable as *mut c_void) && !variable.value.is_null() {
+        let name = unsafe { CStr::from_ptr(variable.value) }.to_string_lossy();
+        palettes.select(&name);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+/// # Safety
+/// info must point to a SystemInfo
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    guard((), || {
+        *info = SystemInfo {
+            library_name: c"JBoy".as_ptr(),
+            library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+            valid_extensions: c"gb|dmg".as_ptr(),
+            need_fullpath: false,
+            block_extract: false,
+        };
+    })
+}
+
+/// # Safety
+/// info must point to a SystemAvInfo
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    guard((), || {
+        *info = SystemAvInfo {
+            geometry: GameGeometry {
+                base_width: SCREEN_WIDTH as c_uint,
+                base_height: SCREEN_HEIGHT as c_uint,
+                max_width: SCREEN_WIDTH as c_uint,
+                max_height: SCREEN_HEIGHT as c_uint,
+                aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+            },
+            timing: SystemTiming {
+                fps: CLOCK_SPEED as f64 / CYCLES_PER_FRAME as f64,
+                sample_rate: SAMPLE_RATE as f64,
+            },
+        };
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: EnvironmentFn) {
+    guard((), || {
+        set_callback(|callbacks| callbacks.environment = Some(environment));
+        let mut variables = [
+            Variable { key: PALETTE_KEY.as_ptr(), value: c"Palette; green|pocket|light|gray|title".as_ptr() },
+            Variable { key: std::ptr::null(), value: std::ptr::null() },
+        ];
+        callbacks().environment(ENVIRONMENT_SET_VARIABLES, variables.as_mut_ptr() as *mut c_void);
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: VideoRefreshFn) {
+    guard((), || set_callback(|callbacks| callbacks.video_refresh = Some(video_refresh)))
+}
+
+/// Unused, audio always goes out in batches
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: AudioSampleBatchFn) {
+    guard((), || set_callback(|callbacks| callbacks.audio_sample_batch = Some(audio_sample_batch)))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: InputPollFn) {
+    guard((), || set_callback(|callbacks| callbacks.input_poll = Some(input_poll)))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: InputStateFn) {
+    guard((), || set_callback(|callbacks| callbacks.input_state = Some(input_state)))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+/// Drops the game even after a panic, and lets the core start over if it's initialised again
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    let _ = panic::catch_unwind(|| *lock(&CORE) = None);
+    DEAD.store(false, Ordering::SeqCst);
+}
+
+/// There's only the joypad
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+/// Powers on again, keeping the cheats
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    guard((), || {
+        with_core(|core| {
+            let cheats = std::mem::replace(&mut core.gameboy.cpu.cheats, CheatList::new());
+            core.gameboy.reset();
+            core.gameboy.cpu.cheats = cheats;
+        });
+    })
+}
+
+/// Runs a frame. A game that runs STOP has ended, so the frontend is asked to close it
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    guard((), || {
+        let callbacks = callbacks();
+        let stopped = with_core(|core| {
+            let mut updated = false;
+            if callbacks.environment(ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut bool as *mut c_void) && updated {
+                update_palette(&callbacks, &mut core.palettes);
+            }
+
+            let cpu = &mut core.gameboy.cpu;
+            cpu.cheats.apply(&mut cpu.memory);
+            let mut retro = Retro {
+                callbacks,
+                palette: core.palettes.current(),
+                video: &mut core.video,
+            };
+            frontend::run_frame(&mut core.gameboy, &mut retro);
+            core.gameboy.stopped()
+        });
+        if stopped == Some(true) {
+            callbacks.environment(ENVIRONMENT_SHUTDOWN, std::ptr::null_mut());
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    guard(0, || with_core(|core| core.gameboy.save_state().len()).unwrap_or(0))
+}
+
+/// # Safety
+/// data must point to size writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    guard(false, || {
+        let state = match with_core(|core| core.gameboy.save_state()) {
+            Some(state) => state,
+            None => return false,
+        };
+        if data.is_null() || state.len() > size {
+            return false;
+        }
+        std::ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+        true
+    })
+}
+
+/// # Safety
+/// data must point to size readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    guard(false, || {
+        if data.is_null() {
+            return false;
+        }
+        let state = std::slice::from_raw_parts(data as *const u8, size);
+        with_core(|core| core.gameboy.load_state(state).is_ok()).unwrap_or(false)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {
+    guard((), || {
+        with_core(|core| {
+            let cpu = &mut core.gameboy.cpu;
+            cpu.cheats.cheats.clear();
+            cpu.cheats.apply(&mut cpu.memory);
+        });
+    })
+}
+
+/// # Safety
+/// code must be a nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(index: c_uint, enabled: bool, code: *const c_char) {
+    guard((), || {
+        if code.is_null() {
+            return;
+        }
+        let text = CStr::from_ptr(code).to_string_lossy();
+        let mut cheat = match Cheat::new(text.trim(), "") {
+            Ok(cheat) => cheat,
+            Err(_) => return,
+        };
+        cheat.enabled = enabled;
+        with_core(|core| {
+            let cheats = &mut core.gameboy.cpu.cheats.cheats;
+            match cheats.get_mut(index as usize) {
+                Some(old) => *old = cheat,
+                None => cheats.push(cheat),
+            }
+        });
+    })
+}
+
+/// Loads a game from memory, roms too big for the address space are turned down
+///
+/// # Safety
+/// game must be null or point to a GameInfo whose data holds size bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    guard(false, || {
+        let game = match game.as_ref() {
+            Some(game) if !game.data.is_null() && game.size > 0 => game,
+            _ => return false,
+        };
+        let rom = std::slice::from_raw_parts(game.data as *const u8, game.size);
+        let gameboy = match GameBoy::new(rom) {
+            Ok(gameboy) => gameboy,
+            Err(_) => return false,
+        };
+        let callbacks = callbacks();
+
+        let mut format = PIXEL_FORMAT_XRGB8888;
+        if !callbacks.environment(ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+            return false;
+        }
+
+        let mut descriptors: Vec<InputDescriptor> = BUTTONS.iter()
+            .map(|(id, _, name)| InputDescriptor { port: 0, device: DEVICE_JOYPAD, index: 0, id: *id, description: name.as_ptr() })
+            .collect();
+        descriptors.push(InputDescriptor { port: 0, device: 0, index: 0, id: 0, description: std::ptr::null() });
+        callbacks.environment(ENVIRONMENT_SET_INPUT_DESCRIPTORS, descriptors.as_mut_ptr() as *mut c_void);
+
+        let mut palettes = Palettes::new();
+        palettes.set_rom(rom);
+        update_palette(&callbacks, &mut palettes);
+        let mut loaded = Core {
+            gameboy: Box::new(gameboy),
+            palettes,
+            video: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        };
+
+        let memory = loaded.gameboy.cpu.memory.raw_mut().as_mut_ptr() as *mut c_void;
+        let descriptors: Vec<MemoryDescriptor> = MEMORY_MAP.iter()
+            .map(|&(start, offset, len, flags)| MemoryDescriptor {
+                flags,
+                ptr: memory,
+                offset,
+                start,
+                select: 0,
+                disconnect: 0,
+                len,
+                addrspace: std::ptr::null(),
+            })
+            .collect();
+        let mut map = MemoryMap { descriptors: descriptors.as_ptr(), num_descriptors: descriptors.len() as c_uint };
+        callbacks.environment(ENVIRONMENT_SET_MEMORY_MAPS, &mut map as *mut MemoryMap as *mut c_void);
+
+        *lock(&CORE) = Some(loaded);
+        true
+    })
+}
+
+/// No special games
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const GameInfo, _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    guard((), || *lock(&CORE) = None)
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+/// Cartridge ram, wram or vram, in place for the frontend to read and write
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    guard(std::ptr::null_mut(), || {
+        let (start, len) = memory_region(id);
+        match with_core(|core| core.gameboy.cpu.memory.raw_mut()[start..].as_mut_ptr() as *mut c_void) {
+            Some(data) if len > 0 => data,
+            _ => std::ptr::null_mut(),
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    guard(0, || memory_region(id).1)
+}
+
+/// Where a libretro memory id is in the address space, and how long it is
+fn memory_region(id: c_uint) -> (usize, usize) {
+    match id {
+        MEMORY_SAVE_RAM => (0xA000, with_core(|core| save_ram_size(core.gameboy.rom())).unwrap_or(0)),
+        MEMORY_SYSTEM_RAM => (0xC000, 0x2000),
+        MEMORY_VIDEO_RAM => (0x8000, 0x2000),
+        _ => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use jboy::{gameboy, golden};
+    use std::cell::Cell;
+
+    #[test]
+    fn libretro_test() {
+        thread_local! {
+            static FORMAT: Cell<c_uint> = const { Cell::new(0) };
+            static DESCRIPTORS: Cell<usize> = const { Cell::new(0) };
+            static VIDEO: Cell<(u32, c_uint, usize)> = const { Cell::new((0, 0, 0)) };
+            static AUDIO: Cell<usize> = const { Cell::new(0) };
+            static SHUTDOWNS: Cell<usize> = const { Cell::new(0) };
+        }
+        unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+            match cmd {
+                10 => FORMAT.set(*(data as *const c_uint)),
+                15 => (*(data as *mut Variable)).value = c"gray".as_ptr(),
+                0x10024 => DESCRIPTORS.set((*(data as *const MemoryMap)).num_descriptors as usize),
+                7 => SHUTDOWNS.set(SHUTDOWNS.get() + 1),
+                _ => return false,
+            }
+            true
+        }
+        unsafe extern "C" fn video(data: *const c_void, width: c_uint, _height: c_uint, pitch: usize) {
+            VIDEO.set((*(data as *const u32), width, pitch));
+        }
+        unsafe extern "C" fn audio(_data: *const i16, frames: usize) -> usize {
+            AUDIO.set(AUDIO.get() + frames);
+            frames
+        }
+        unsafe extern "C" fn poll() {}
+        unsafe extern "C" fn state(_port: c_uint, _device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+            (id == 8) as i16
+        }
+
+        assert_eq!(retro_api_version(), 1);
+        let mut info = std::mem::MaybeUninit::<SystemAvInfo>::uninit();
+        let info = unsafe {
+            retro_get_system_av_info(info.as_mut_ptr());
+            info.assume_init()
+        };
+        assert_eq!((info.geometry.base_width, info.geometry.base_height), (160, 144));
+        assert!((59.7..59.8).contains(&info.timing.fps));
+
+        retro_set_environment(environment);
+        retro_set_video_refresh(video);
+        retro_set_audio_sample_batch(audio);
+        retro_set_input_poll(poll);
+        retro_set_input_state(state);
+        retro_init();
+
+        let mut rom = golden::test_rom(&[0xC3, 0x00, 0x01]);
+        rom[0x149] = 2;
+        let game = GameInfo { path: std::ptr::null(), data: rom.as_ptr() as *const c_void, size: rom.len(), meta: std::ptr::null() };
+        assert!(unsafe { retro_load_game(&game) });
+        assert_eq!((FORMAT.get(), DESCRIPTORS.get()), (1, MEMORY_MAP.len()));
+        assert_eq!(retro_get_memory_size(MEMORY_SAVE_RAM), 0x2000);
+
+        // The core options picked gray, so the blank screen is white
+        retro_run();
+        assert_eq!(VIDEO.get(), (0xFFFFFF, 160, 640));
+        assert_eq!(AUDIO.get() as u64, gameboy::samples_until(1));
+
+        // Cheats write through to wram, which the frontend sees in place
+        unsafe { retro_cheat_set(0, true, c"014200C1".as_ptr()) };
+        retro_run();
+        let wram = retro_get_memory_data(MEMORY_SYSTEM_RAM) as *mut u8;
+        assert_eq!(unsafe { *wram.add(0x100) }, 0x42);
+        retro_cheat_reset();
+
+        let mut state = vec![0u8; retro_serialize_size()];
+        assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+        assert!(!unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, 10) });
+        unsafe { *wram.add(0x100) = 0x99 };
+        assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) });
+        assert_eq!(unsafe { *wram.add(0x100) }, 0x42);
+        assert!(!unsafe { retro_unserialize(b"nope".as_ptr() as *const c_void, 4) });
+
+        retro_reset();
+        assert_eq!(retro_get_memory_data(MEMORY_SYSTEM_RAM) as *mut u8, wram);
+        retro_unload_game();
+        assert!(retro_get_memory_data(MEMORY_SYSTEM_RAM).is_null());
+
+        // Roms that don't fit are turned down instead of panicking
+        let big = vec![0u8; 0x10001];
+        let game = GameInfo { path: std::ptr::null(), data: big.as_ptr() as *const c_void, size: big.len(), meta: std::ptr::null() };
+        assert!(!unsafe { retro_load_game(&game) });
+
+        // A game that runs STOP has ended, so the frontend is asked to close it
+        rom[0x100] = 0x10;
+        let game = GameInfo { path: std::ptr::null(), data: rom.as_ptr() as *const c_void, size: rom.len(), meta: std::ptr::null() };
+        assert!(unsafe { retro_load_game(&game) });
+        retro_run();
+        assert_eq!(SHUTDOWNS.get(), 1);
+
+        // A panic stops the core and asks for a shutdown instead of unwinding into the frontend
+        rom[0x100] = 0xDB;
+        let game = GameInfo { path: std::ptr::null(), data: rom.as_ptr() as *const c_void, size: rom.len(), meta: std::ptr::null() };
+        assert!(unsafe { retro_load_game(&game) });
+        retro_run();
+        assert!(is_dead());
+        assert_eq!(SHUTDOWNS.get(), 2);
+        retro_run();
+        assert_eq!(retro_serialize_size(), 0);
+        assert!(!unsafe { retro_load_game(&game) });
+        assert_eq!(SHUTDOWNS.get(), 2);
+
+        retro_deinit();
+        assert!(!is_dead());
+    }
+}
@@ -0,0 +1,656 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::emulation::CYCLES_PER_FRAME;
+use crate::gameboy::{SAMPLE_RATE, CHANNELS};
+use crate::graphics::{Pixel, SCREEN_WIDTH, SCREEN_HEIGHT};
+use crate::palettes::Palette;
+use crate::speed::CLOCK_SPEED;
+
+/*
+ *  Capture
+ *  Screenshots, gif and raw video, and wav audio, all written by hand so
+ *  nothing here needs a window. Headless runs can build a Frame straight
+ *  from a framebuffer and a palette and save it like the frontend does,
+ *  and pngs can be read back in to compare against
+ */
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PngError {
+    NotPng,
+    Unsupported(String),
+    Corrupt,
+}
+
+impl std::fmt::Display for PngError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PngError::NotPng => write!(f, "Not a png file"),
+            PngError::Unsupported(what) => write!(f, "Unsupported png: {}", what),
+            PngError::Corrupt => write!(f, "The png is corrupt"),
+        }
+    }
+}
+
+/// When a frame starts, in the hundredths of a second gif delays count in
+fn centiseconds(frames: u64) -> u64 {
+    (frames * 100 * CYCLES_PER_FRAME as u64 + CLOCK_SPEED as u64 / 2) / CLOCK_SPEED as u64
+}
+
+/// The first of roms/game-1.png, roms/game-2.png... that doesn't exist yet
+pub fn numbered_path(rom_path: &str, extension: &str) -> String {
+    let stem = Path::new(rom_path).with_extension("");
+    (1..)
+        .map(|n| format!("{}-{}.{}", stem.to_string_lossy(), n, extension))
+        .find(|path| !Path::new(path).exists())
+        .unwrap()
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    /// Rows of rgb pixels, top to bottom
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Frame {
+
+    pub fn new(width: usize, height: usize, pixels: Vec<[u8; 3]>) -> Self {
+        assert_eq!(pixels.len(), width * height, "frame is the wrong size");
+        Self { width, height, pixels }
+    }
+
+    /// The screen as it would be shown with a palette
+    pub fn from_pixels(pixels: &[[Pixel; SCREEN_WIDTH]; SCREEN_HEIGHT], palette: &Palette) -> Self {
+        let pixels = pixels.iter()
+            .flatten()
+            .map(|p| palette.color(*p))
+            .collect();
+        Self::new(SCREEN_WIDTH, SCREEN_HEIGHT, pixels)
+    }
+
+    /// Blows every pixel up into a square
+    pub fn scale(&self, scale: usize) -> Self {
+        let width = self.width * scale;
+        let pixels = (0..self.height * scale)
+            .flat_map(|y| (0..width).map(move |x| (x / scale, y / scale)))
+            .map(|(x, y)| self.pixels[y * self.width + x])
+            .collect();
+        Self::new(width, self.height * scale, pixels)
+    }
+
+    /// A png of the frame. The image data is stored rather than deflated, a
+    /// screen's worth is only about 70kb
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        let mut header = Vec::new();
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // 8 bit rgb, no interlacing
+        header.extend([8, 2, 0, 0, 0]);
+        png_chunk(&mut png, b"IHDR", &header);
+
+        let mut raw = Vec::with_capacity(self.pixels.len() * 3 + self.height);
+        for row in self.pixels.chunks(self.width) {
+            raw.push(0);
+            raw.extend(row.iter().flatten());
+        }
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    pub fn save_png(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_png())
+    }
+
+    /*
+     *  Reads a png back in, e.g. a golden image from another emulator.
+     *  Handles every color type at 8 bits, and gray or paletted images
+     *  packed smaller, but not interlacing. Alpha is dropped
+     */
+    pub fn from_png(png: &[u8]) -> Result<Self, PngError> {
+        if png.get(..8) != Some(b"\x89PNG\r\n\x1a\n") {
+            return Err(PngError::NotPng);
+        }
+        let (mut header, mut palette, mut data) = (None, Vec::new(), Vec::new());
+        let mut at = 8;
+        while at + 12 <= png.len() {
+            let length = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+            let body = png.get(at + 8..at + 8 + length).ok_or(PngError::Corrupt)?;
+            match &png[at + 4..at + 8] {
+                b"IHDR" if length == 13 => header = Some(body.to_vec()),
+                b"PLTE" => palette = body.chunks(3).map(|c| [c[0], c[1], c[2]]).collect(),
+                b"IDAT" => data.extend(body),
+                b"IEND" => break,
+                _ => {},
+            }
+            at += length + 12;
+        }
+        let header = header.ok_or(PngError::Corrupt)?;
+        let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+        let (depth, color_type) = (header[8] as usize, header[9]);
+        if header[12] != 0 {
+            return Err(PngError::Unsupported(String::from("interlacing")));
+        }
+        let channels = match (color_type, depth) {
+            (0, 1 | 2 | 4 | 8) | (3, 1 | 2 | 4 | 8) => 1,
+            (2, 8) => 3,
+            (4, 8) => 2,
+            (6, 8) => 4,
+            _ => return Err(PngError::Unsupported(format!("color type {} at {} bits", color_type, depth))),
+        };
+
+        let bits = channels * depth;
+        let stride = (width * bits).div_ceil(8);
+        let step = bits.div_ceil(8);
+        let raw = inflate(data.get(2..).ok_or(PngError::Corrupt)?)?;
+        if raw.len() < (stride + 1) * height {
+            return Err(PngError::Corrupt);
+        }
+
+        let mut previous = vec![0u8; stride];
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let line = &raw[y * (stride + 1)..(y + 1) * (stride + 1)];
+            let row = unfilter(line[0], &line[1..], &previous, step)?;
+            for x in 0..width {
+                let sample = |i: usize| -> usize {
+                    let bit = (x * channels + i) * depth;
+                    let byte = row[bit / 8] as usize;
+                    (byte >> (8 - depth - bit % 8)) & ((1 << depth) - 1)
+                };
+                let pixel = match color_type {
+                    0 | 4 => [(sample(0) * 255 / ((1 << depth) - 1)) as u8; 3],
+                    3 => *palette.get(sample(0)).ok_or(PngError::Corrupt)?,
+                    _ => [sample(0) as u8, sample(1) as u8, sample(2) as u8],
+                };
+                pixels.push(pixel);
+            }
+            previous = row;
+        }
+        Ok(Self::new(width, height, pixels))
+    }
+
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(0xFFFFFFFF, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            if crc & 1 == 1 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 }
+        })
+    })
+}
+
+pub fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// A zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(0xFFFF).collect() };
+    for (i, block) in blocks.iter().enumerate() {
+        out.push((i == blocks.len() - 1) as u8);
+        out.extend((block.len() as u16).to_le_bytes());
+        out.extend((!(block.len() as u16)).to_le_bytes());
+        out.extend(*block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+/// Undoes a png row's filter given the row above
+fn unfilter(filter: u8, line: &[u8], previous: &[u8], step: usize) -> Result<Vec<u8>, PngError> {
+    let mut row = line.to_vec();
+    for i in 0..row.len() {
+        let left = if i >= step { row[i - step] as i16 } else { 0 };
+        let (up, up_left) = (previous[i] as i16, if i >= step { previous[i - step] as i16 } else { 0 });
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => (left + up) / 2,
+            4 => {
+                let p = left + up - up_left;
+                let (pa, pb, pc) = ((p - left).abs(), (p - up).abs(), (p - up_left).abs());
+                if pa <= pb && pa <= pc { left } else if pb <= pc { up } else { up_left }
+            },
+            _ => return Err(PngError::Corrupt),
+        };
+        row[i] = row[i].wrapping_add(predicted as u8);
+    }
+    Ok(row)
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// The order a dynamic block lists its code length code lengths in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl BitReader<'_> {
+
+    /// Reads bits least significant first
+    fn bits(&mut self, count: u8) -> Result<u32, PngError> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self.data.get(self.at / 8).ok_or(PngError::Corrupt)?;
+            value |= (((byte >> (self.at % 8)) & 1) as u32) << i;
+            self.at += 1;
+        }
+        Ok(value)
+    }
+
+}
+
+/// A canonical huffman code, decoded a bit at a time
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|s| lengths[*s as usize] != 0).collect();
+        symbols.sort_by_key(|s| lengths[*s as usize]);
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, PngError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return self.symbols.get((index + code - first) as usize).copied().ok_or(PngError::Corrupt);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(PngError::Corrupt)
+    }
+
+}
+
+/*
+ *  Inflate
+ *  Decompresses a raw deflate stream: stored blocks, the fixed huffman
+ *  codes, and codes sent along with the block
+ */
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, PngError> {
+    let mut reader = BitReader { data, at: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                let start = reader.at.div_ceil(8);
+                let length = data.get(start..start + 2).ok_or(PngError::Corrupt)?;
+                let length = u16::from_le_bytes([length[0], length[1]]) as usize;
+                out.extend(data.get(start + 4..start + 4 + length).ok_or(PngError::Corrupt)?);
+                reader.at = (start + 4 + length) * 8;
+            },
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                inflate_block(&mut reader, &mut out, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            },
+            2 => {
+                let literals = reader.bits(5)? as usize + 257;
+                let distances = reader.bits(5)? as usize + 1;
+                let code_lengths = reader.bits(4)? as usize + 4;
+                let mut lengths = [0u8; 19];
+                for i in CODE_LENGTH_ORDER.iter().take(code_lengths) {
+                    lengths[*i] = reader.bits(3)? as u8;
+                }
+                let code = Huffman::new(&lengths);
+
+                let mut lengths = Vec::with_capacity(literals + distances);
+                while lengths.len() < literals + distances {
+                    let (value, repeat) = match code.decode(&mut reader)? {
+                        symbol @ 0..=15 => (symbol as u8, 1),
+                        16 => (*lengths.last().ok_or(PngError::Corrupt)?, 3 + reader.bits(2)?),
+                        17 => (0, 3 + reader.bits(3)?),
+                        _ => (0, 11 + reader.bits(7)?),
+                    };
+                    lengths.extend(std::iter::repeat_n(value, repeat as usize));
+                }
+                if lengths.len() != literals + distances {
+                    return Err(PngError::Corrupt);
+                }
+                let (literal_lengths, distance_lengths) = lengths.split_at(literals);
+                inflate_block(&mut reader, &mut out, &Huffman::new(literal_lengths), &Huffman::new(distance_lengths))?;
+            },
+            _ => return Err(PngError::Corrupt),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), PngError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                let extra = *LENGTH_EXTRA.get(i).ok_or(PngError::Corrupt)?;
+                let length = LENGTH_BASE[i] as usize + reader.bits(extra)? as usize;
+                let i = distances.decode(reader)? as usize;
+                let extra = *DISTANCE_EXTRA.get(i).ok_or(PngError::Corrupt)?;
+                let distance = DISTANCE_BASE[i] as usize + reader.bits(extra)? as usize;
+                if distance > out.len() {
+                    return Err(PngError::Corrupt);
+                }
+                // Copied a byte at a time since the run can overlap itself
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            },
+        }
+    }
+}
+
+/*
+ *  LZW
+ *  Gif's variant: codes start one bit wider than the color indices, grow
+ *  as the table fills, and the table is cleared once it reaches 4096
+ */
+pub fn lzw(indices: &[u8], min_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_size;
+    let end = clear + 1;
+    let mut out = Vec::new();
+    let (mut bits, mut count) = (0u32, 0u32);
+    let mut write = |code: u16, size: u32| {
+        bits |= (code as u32) << count;
+        count += size;
+        while count >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            count -= 8;
+        }
+    };
+
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut size = min_size as u32 + 1;
+    write(clear, size);
+
+    if let Some((first, rest)) = indices.split_first() {
+        let mut prefix = *first as u16;
+        for index in rest {
+            if let Some(code) = table.get(&(prefix, *index)) {
+                prefix = *code;
+                continue;
+            }
+            write(prefix, size);
+            if next == 4096 {
+                write(clear, size);
+                table.clear();
+                next = end + 1;
+                size = min_size as u32 + 1;
+            } else {
+                table.insert((prefix, *index), next);
+                if next == 1 << size && size < 12 {
+                    size += 1;
+                }
+                next += 1;
+            }
+            prefix = *index as u16;
+        }
+        write(prefix, size);
+    }
+    write(end, size);
+    write(0, 7);
+    out
+}
+
+/*
+ *  Gif Writer
+ *  Gif delays only count hundredths of a second and most viewers slow down
+ *  anything shorter than two, so a frame that would be on screen for less
+ *  than that is dropped for the next one. Frames that don't change just
+ *  make the one before last longer
+ */
+pub struct GifWriter {
+    writer: BufWriter<File>,
+    frames: u64,
+    /// The frame waiting to be written and the time it went up
+    pending: Option<(Frame, u64)>,
+}
+
+impl GifWriter {
+
+    pub fn create(path: &str, width: usize, height: usize) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&(width as u16).to_le_bytes())?;
+        writer.write_all(&(height as u16).to_le_bytes())?;
+        // No global color table, every frame brings its own
+        writer.write_all(&[0, 0, 0])?;
+        // Loop forever
+        writer.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+        Ok(Self {
+            writer,
+            frames: 0,
+            pending: None,
+        })
+    }
+
+    /// Adds the next emulated frame
+    pub fn add_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        let now = centiseconds(self.frames);
+        self.frames += 1;
+        match self.pending.take() {
+            Some((pending, start)) if pending == *frame => self.pending = Some((pending, start)),
+            Some((_, start)) if now - start < 2 => self.pending = Some((frame.clone(), start)),
+            Some((pending, start)) => {
+                self.write_frame(&pending, now - start)?;
+                self.pending = Some((frame.clone(), now));
+            },
+            None => self.pending = Some((frame.clone(), now)),
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &Frame, delay: u64) -> io::Result<()> {
+        // Colors in the order they turn up, anything past 256 takes the closest one
+        let mut colors: Vec<[u8; 3]> = Vec::new();
+        let mut indices = Vec::with_capacity(frame.pixels.len());
+        for pixel in &frame.pixels {
+            let index = match colors.iter().position(|c| c == pixel) {
+                Some(i) => i,
+                None if colors.len() < 256 => {
+                    colors.push(*pixel);
+                    colors.len() - 1
+                },
+                None => (0..colors.len())
+                    .min_by_key(|i| colors[*i].iter().zip(pixel).map(|(a, b)| (*a as i32 - *b as i32).pow(2)).sum::<i32>())
+                    .unwrap(),
+            };
+            indices.push(index as u8);
+        }
+        let table_bits = (1..=8).find(|bits| 1 << bits >= colors.len()).unwrap();
+        colors.resize(1 << table_bits, [0, 0, 0]);
+
+        // Graphic control: leave the frame in place, then wait
+        self.writer.write_all(&[0x21, 0xF9, 0x04, 0x04])?;
+        self.writer.write_all(&(delay.min(0xFFFF) as u16).to_le_bytes())?;
+        self.writer.write_all(&[0x00, 0x00])?;
+
+        self.writer.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.writer.write_all(&(frame.width as u16).to_le_bytes())?;
+        self.writer.write_all(&(frame.height as u16).to_le_bytes())?;
+        self.writer.write_all(&[0x80 | (table_bits as u8 - 1)])?;
+        self.writer.write_all(&colors.concat())?;
+
+        let min_size = table_bits.max(2) as u8;
+        self.writer.write_all(&[min_size])?;
+        for block in lzw(&indices, min_size).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0])
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some((pending, start)) = self.pending.take() {
+            let delay = (centiseconds(self.frames) - start).max(2);
+            self.write_frame(&pending, delay)?;
+        }
+        self.writer.write_all(&[0x3B])?;
+        self.writer.flush()
+    }
+
+}
+
+/*
+ *  Wav Writer
+ *  16 bit stereo pcm. The sizes in the header are filled in by finish, so
+ *  a recording that's cut off is still playable by most tools
+ */
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    samples: u64,
+}
+
+impl WavWriter {
+
+    pub fn create(path: &str) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&wav_header(0))?;
+        Ok(Self {
+            writer,
+            samples: 0,
+        })
+    }
+
+    /// Adds interleaved left and right samples
+    pub fn add_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += samples.len() as u64;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&wav_header((self.samples * 2) as u32))?;
+        self.writer.flush()
+    }
+
+}
+
+pub fn wav_header(data_size: u32) -> Vec<u8> {
+    let block_align = CHANNELS * 2;
+    let mut header = Vec::new();
+    header.extend(b"RIFF");
+    header.extend((36 + data_size).to_le_bytes());
+    header.extend(b"WAVEfmt ");
+    header.extend(16u32.to_le_bytes());
+    header.extend(1u16.to_le_bytes());
+    header.extend(CHANNELS.to_le_bytes());
+    header.extend(SAMPLE_RATE.to_le_bytes());
+    header.extend((SAMPLE_RATE * block_align as u32).to_le_bytes());
+    header.extend(block_align.to_le_bytes());
+    header.extend(16u16.to_le_bytes());
+    header.extend(b"data");
+    header.extend(data_size.to_le_bytes());
+    header
+}
+
+pub enum Video {
+    Gif(GifWriter),
+    /// Frames of rgb24 one after another, e.g. for
+    /// ffmpeg -f rawvideo -pix_fmt rgb24 -s 160x144 -r 59.73 -i game.rgb
+    Raw(BufWriter<File>),
+}
+
+/*
+ *  Recorder
+ *  Writes every emulated frame to a gif, or raw frames for anything else,
+ *  with the audio from GameBoy::take_audio in a wav next to it
+ */
+pub struct Recorder {
+    pub path: String,
+    pub audio_path: String,
+    video: Video,
+    audio: WavWriter,
+    pub frames: u64,
+}
+
+impl Recorder {
+
+    pub fn start(path: &str, width: usize, height: usize) -> io::Result<Self> {
+        let video = if path.ends_with(".gif") {
+            Video::Gif(GifWriter::create(path, width, height)?)
+        } else {
+            Video::Raw(BufWriter::new(File::create(path)?))
+        };
+        let audio_path = Path::new(path).with_extension("wav").to_string_lossy().into_owned();
+        Ok(Self {
+            path: path.to_string(),
+            audio: WavWriter::create(&audio_path)?,
+            audio_path,
+            video,
+            frames: 0,
+        })
+    }
+
+    /// Adds a frame and the audio that played during it
+    pub fn add_frame(&mut self, frame: &Frame, audio: &[i16]) -> io::Result<()> {
+        match &mut self.video {
+            Video::Gif(gif) => gif.add_frame(frame)?,
+            Video::Raw(writer) => writer.write_all(&frame.pixels.concat())?,
+        }
+        self.audio.add_samples(audio)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self.video {
+            Video::Gif(gif) => gif.finish()?,
+            Video::Raw(mut writer) => writer.flush()?,
+        }
+        self.audio.finish()
+    }
+
+}
@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::Path;
+
+use crate::cpu::memory::{Memory, RomPatch};
+
+/*
+ *  Cheats
+ *  GameShark codes write a byte to ram every frame, Game Genie codes patch
+ *  what the cpu reads from rom. A cheat file sits next to the rom with one
+ *  cheat per line, its codes joined with + and then a name:
+ *  01FF34C1 Infinite lives
+ *  00A-17B-C49+01B-17B-C49 Start on world 2
+ *  ; starts a comment
+ */
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CheatError {
+    InvalidCode(String),
+    NoSuchCheat(usize),
+}
+
+impl std::fmt::Display for CheatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheatError::InvalidCode(code) => write!(f, "{} is not a GameShark or Game Genie code", code),
+            CheatError::NoSuchCheat(i) => write!(f, "There is no cheat {}", i),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Code {
+    /// Writes value to addr every frame
+    GameShark { value: u8, addr: u16 },
+    /// Reads of addr give value instead, if the rom holds compare
+    GameGenie { addr: u16, value: u8, compare: Option<u8> },
+}
+
+/// The cheat file next to a rom, e.g. roms/game.gb -> roms/game.cht
+pub fn cheat_path(rom_path: &str) -> String {
+    Path::new(rom_path)
+        .with_extension("cht")
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn hex_digits(text: &str) -> Option<Vec<u8>> {
+    text.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect()
+}
+
+/*
+ *  Parses one code. GameShark codes are 8 hex digits, TTVVLLHH: a type, the
+ *  value, then the address low byte first. The type only picks a CGB ram
+ *  bank, so it's ignored. Game Genie codes are ABC-DEF or
+ *  ABC-DEF-GHI: AB is the value, the address is FCDE with F inverted, and
+ *  G and I hold the compare byte rotated right by 2 and xored with BA
+ */
+pub fn parse_code(text: &str) -> Result<Code, CheatError> {
+    let invalid = || CheatError::InvalidCode(text.to_string());
+
+    if !text.contains('-') && text.len() == 8 {
+        let digits = hex_digits(text).ok_or_else(invalid)?;
+        let byte = |i: usize| digits[i] << 4 | digits[i + 1];
+        return Ok(Code::GameShark {
+            value: byte(2),
+            addr: u16::from_le_bytes([byte(4), byte(6)]),
+        });
+    }
+
+    let digits = hex_digits(&text.replace('-', "")).ok_or_else(invalid)?;
+    if digits.len() != 6 && digits.len() != 9 {
+        return Err(invalid());
+    }
+    let addr = ((digits[5] ^ 0xF) as u16) << 12 | (digits[2] as u16) << 8 | (digits[3] as u16) << 4 | digits[4] as u16;
+    if addr >= 0x8000 {
+        return Err(invalid());
+    }
+    let compare = if digits.len() == 9 {
+        Some((digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA)
+    } else {
+        None
+    };
+    Ok(Code::GameGenie { addr, value: digits[0] << 4 | digits[1], compare })
+}
+
+pub struct Cheat {
+    pub name: String,
+    pub text: String,
+    pub codes: Vec<Code>,
+    pub enabled: bool,
+}
+
+impl Cheat {
+
+    /// Parses codes joined with +
+    pub fn new(text: &str, name: &str) -> Result<Self, CheatError> {
+        Ok(Self {
+            name: name.to_string(),
+            text: text.to_string(),
+            codes: text.split('+').map(parse_code).collect::<Result<_, _>>()?,
+            enabled: true,
+        })
+    }
+
+}
+
+/*
+ *  Cheat List
+ *  Every loaded cheat, switched on and off one at a time or all together
+ *  with the hotkey. apply runs once per frame
+ */
+pub struct CheatList {
+    pub cheats: Vec<Cheat>,
+    pub enabled: bool,
+}
+
+impl Default for CheatList {
+    fn default() -> Self {
+        Self {
+            cheats: Vec::new(),
+            enabled: true,
+        }
+    }
+}
+
+impl CheatList {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, CheatError> {
+        let mut list = Self::new();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (codes, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            list.cheats.push(Cheat::new(codes, name.trim())?);
+        }
+        Ok(list)
+    }
+
+    /// Loads the cheat file next to a rom, or no cheats if there isn't one
+    pub fn load_for_rom(rom_path: &str) -> Result<Self, CheatError> {
+        match fs::read_to_string(cheat_path(rom_path)) {
+            Ok(text) => Self::parse(&text),
+            Err(_) => Ok(Self::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cheats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    pub fn add(&mut self, text: &str, name: &str) -> Result<(), CheatError> {
+        self.cheats.push(Cheat::new(text, name)?);
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> Result<(), CheatError> {
+        let cheat = self.cheats.get_mut(index).ok_or(CheatError::NoSuchCheat(index))?;
+        cheat.enabled = enabled;
+        Ok(())
+    }
+
+    /// Turns every cheat on or off at once, returns whether they're now on
+    pub fn toggle(&mut self) -> bool {
+        self.enabled = !self.enabled;
+        self.enabled
+    }
+
+    fn active_codes(&self) -> impl Iterator<Item = &Code> {
+        self.cheats.iter()
+            .filter(|c| self.enabled && c.enabled)
+            .flat_map(|c| c.codes.iter())
+    }
+
+    /// Writes the GameShark values and refreshes the Game Genie patches
+    pub fn apply(&self, memory: &mut Memory) {
+        memory.patches.clear();
+        for code in self.active_codes() {
+            match *code {
+                Code::GameShark { value, addr, .. } => memory.poke(addr, value),
+                Code::GameGenie { addr, value, compare } => memory.patches.push(RomPatch { addr, value, compare }),
+            }
+        }
+    }
+
+    /// One line per cheat for listing, e.g. "0 on  01FF34C1 Infinite lives"
+    pub fn describe(&self) -> Vec<String> {
+        self.cheats.iter().enumerate()
+            .map(|(i, c)| format!("{} {:<3} {} {}", i, if c.enabled { "on" } else { "off" }, c.text, c.name))
+            .collect()
+    }
+
+}
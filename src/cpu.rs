@@ -4,6 +4,11 @@ pub mod register;
 use self::memory::Memory;
 use self::register::Registers;
 use crate::util::BitGrabber;
+use crate::joypad::Joypad;
+use crate::trace::Tracer;
+use crate::cheats::CheatList;
+use crate::graphics::Screen;
+use crate::savestate::{SaveState, StateReader, StateWriter, StateError};
 
 pub enum RegCode {
     A,
@@ -68,8 +73,14 @@ impl std::fmt::Display for CondCode {
 pub struct Cpu {
     pub memory: Memory,
     pub registers: Registers,
+    pub joypad: Joypad,
+    pub trace: Tracer,
+    pub cheats: CheatList,
+    pub screen: Screen,
     interupts: bool,
     halted: bool,
+    /// Set by STOP, which ends the program, not kept in save states
+    stopped: bool,
 }
 
 impl Cpu {
@@ -116,7 +127,7 @@ impl Cpu {
         m_Rom[0xFFFF] = 0x00 ;
     }
 
-    pub fn new_with_rom(rom: &Vec<u8>) -> Self {
+    pub fn new_with_rom(rom: &[u8]) -> Self {
         let mut memory = Memory::new();
         for (i, val) in rom.iter().enumerate() {
             memory[i] = *val;
@@ -125,8 +136,13 @@ impl Cpu {
         let mut cpu = Cpu {
             memory,
             registers: Registers::new(),
+            joypad: Joypad::new(),
+            trace: Tracer::new(),
+            cheats: CheatList::new(),
+            screen: Screen::new(),
             interupts: true,
             halted: false,
+            stopped: false,
         };
         cpu.init_reg();
         cpu
@@ -138,8 +154,13 @@ impl Cpu {
         Self {
             memory: Memory::new(),
             registers,
+            joypad: Joypad::new(),
+            trace: Tracer::new(),
+            cheats: CheatList::new(),
+            screen: Screen::new(),
             interupts: true,
             halted: false,
+            stopped: false,
         }
     }
 
@@ -155,6 +176,15 @@ impl Cpu {
         self.registers.pc -= 1;
     }
     
+    /*
+     *  Refreshes the P1 register from the joypad
+     *  Keeps whichever button group the game last selected
+     */
+    pub fn update_joypad(&mut self) {
+        let select = self.memory.peek(0xFF00);
+        self.memory.poke(0xFF00, self.joypad.register(select));
+    }
+    
     pub fn get_16_pc(&mut self) -> u16 {
         let mut num: u16 = (self.current_pc_byte() as u16);
         self.increment_pc();
@@ -169,6 +199,27 @@ impl Cpu {
     pub fn halt(&mut self) {
         self.halted = true;
     }
+
+    /*
+     *  STOP Instruction
+     *  Games only use it to end, so the frontend shuts down once it's run
+     */
+    pub fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Moves over what save states hold from another cpu, keeping everything else
+    pub fn take_state(&mut self, from: Cpu) {
+        self.registers = from.registers;
+        self.interupts = from.interupts;
+        self.halted = from.halted;
+        self.joypad = from.joypad;
+        self.memory.take_state(from.memory);
+    }
     
     /*
      *  EI Instruction
@@ -393,8 +444,9 @@ impl Cpu {
      *  JR instruction
      *  CondCode is the condition that must be true for the jump
      *  jump_by is the relative distance to add to the program counter
+     *  Returns whether it jumped
      */
-    pub fn jump_relative(&mut self, cond: CondCode, jump_by: i8) {
+    pub fn jump_relative(&mut self, cond: CondCode, jump_by: i8) -> bool {
         let jump;
 
         match cond {
@@ -407,8 +459,8 @@ impl Cpu {
 
         if jump {
             self.registers.pc = (self.registers.pc as i16 + jump_by as i16) as u16;
-            println!("Jumped to: {}", self.registers.pc);
         }
+        jump
     }
     
     /*
@@ -567,6 +619,101 @@ impl Cpu {
         self.registers.sp = ((self.registers.sp as i16).wrapping_add(val as i16)) as u16;
     }
     
+    /*
+     *  LD HL,SP+e instruction
+     *  Flags come from adding e to the low byte of sp, as in ADD SP,e
+     */
+    pub fn load_hl_sp(&mut self, val: i8) {
+        let sp = self.registers.sp;
+        let low = val as u8;
+        self.registers.af.flip_flags_down();
+        if (sp & 0x0F) + (low as u16 & 0x0F) > 0x0F {
+            self.registers.af.flip_hcarry_flag();
+        }
+        if (sp & 0xFF) + low as u16 > 0xFF {
+            self.registers.af.flip_carry_flag();
+        }
+        self.registers.hl.change_as_one(sp.wrapping_add(val as u16));
+    }
+
+    /*
+     *  LD SP,HL instruction
+     */
+    pub fn load_sp_hl(&mut self) {
+        self.registers.sp = self.registers.hl.take_as_one();
+    }
+
+    /*
+     *  DAA instruction
+     *  Turns A back into binary coded decimal after an add or subtract
+     *  of two bcd numbers, using the flags that instruction left
+     */
+    pub fn decimal_adjust(&mut self) {
+        let subtract = self.registers.af.is_subtract_high();
+        let mut carry = self.registers.af.is_carry_high();
+        let hcarry = self.registers.af.is_hcarry_high();
+        let mut a = self.registers.af.left;
+        if subtract {
+            if carry {
+                a = a.wrapping_sub(0x60);
+            }
+            if hcarry {
+                a = a.wrapping_sub(0x06);
+            }
+        } else {
+            if carry || a > 0x99 {
+                a = a.wrapping_add(0x60);
+                carry = true;
+            }
+            if hcarry || a & 0x0F > 0x09 {
+                a = a.wrapping_add(0x06);
+            }
+        }
+
+        self.registers.af.left = a;
+        self.registers.af.flip_flags_down();
+        if a == 0 {
+            self.registers.af.flip_zero_flag();
+        }
+        if subtract {
+            self.registers.af.flip_subtract_flag();
+        }
+        if carry {
+            self.registers.af.flip_carry_flag();
+        }
+    }
+
+    /*
+     *  CPL instruction
+     *  Flips every bit of A, sets subtract and half carry
+     */
+    pub fn complement(&mut self) {
+        self.registers.af.left = !self.registers.af.left;
+        if !self.registers.af.is_subtract_high() {
+            self.registers.af.flip_subtract_flag();
+        }
+        if !self.registers.af.is_hcarry_high() {
+            self.registers.af.flip_hcarry_flag();
+        }
+    }
+
+    /*
+     *  SCF and CCF instructions
+     *  Sets the carry flag, or flips it if flip is true. Zero is kept,
+     *  subtract and half carry are cleared
+     */
+    pub fn set_carry(&mut self, flip: bool) {
+        let carry = if flip { !self.registers.af.is_carry_high() } else { true };
+        let zero = self.registers.af.is_zero_high();
+        self.registers.af.flip_flags_down();
+        if zero {
+            self.registers.af.flip_zero_flag();
+        }
+        if carry {
+            self.registers.af.flip_carry_flag();
+        }
+    }
+
     /*
      *  SUB instruction
      *  Subtracts the source value from register A
@@ -815,46 +962,54 @@ impl Cpu {
         }
     }
 
-    pub fn ret(&mut self, cond: CondCode) {
-        if match cond {
+    /// Returns whether the condition held and it returned
+    pub fn ret(&mut self, cond: CondCode) -> bool {
+        let taken = match cond {
             CondCode::Z => self.registers.af.is_zero_high(),
             CondCode::NZ => !self.registers.af.is_zero_high(),
             CondCode::C => self.registers.af.is_carry_high(),
             CondCode::NC => !self.registers.af.is_carry_high(),
             CondCode::Always => true,
-        } {
+        };
+        if taken {
             self.pop(RegCode::PC);
         }
+        taken
     }
 
-    pub fn jump(&mut self, cond: CondCode, to: u16) {
-        if match cond {
+    /// Returns whether the condition held and it jumped
+    pub fn jump(&mut self, cond: CondCode, to: u16) -> bool {
+        let taken = match cond {
             CondCode::Z => self.registers.af.is_zero_high(),
             CondCode::NZ => !self.registers.af.is_zero_high(),
             CondCode::C => self.registers.af.is_carry_high(),
             CondCode::NC => !self.registers.af.is_carry_high(),
             CondCode::Always => true,
-        } {
+        };
+        if taken {
             self.registers.pc = to;
-            println!("Jumped to: {}", self.registers.pc);
         }
+        taken
     }
 
     pub fn jump_hl(&mut self) {
         self.registers.pc = self.registers.hl.take_as_one();
     }
 
-    pub fn call(&mut self, cond:CondCode, to: u16) {
-        if match cond {
+    /// Returns whether the condition held and it called
+    pub fn call(&mut self, cond:CondCode, to: u16) -> bool {
+        let taken = match cond {
             CondCode::Z => self.registers.af.is_zero_high(),
             CondCode::NZ => !self.registers.af.is_zero_high(),
             CondCode::C => self.registers.af.is_carry_high(),
             CondCode::NC => !self.registers.af.is_carry_high(),
             CondCode::Always => true,
-        } {
+        };
+        if taken {
             self.push(RegCode::PC);
             self.registers.pc = to;
         }
+        taken
     }
 
     pub fn restart(&mut self, to: u16) {
@@ -1225,3 +1380,23 @@ impl Cpu {
     }
 
 }
+
+impl SaveState for Cpu {
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.registers.save_state(writer);
+        writer.write_bool(self.interupts);
+        writer.write_bool(self.halted);
+        self.joypad.save_state(writer);
+        self.memory.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.registers.load_state(reader)?;
+        self.interupts = reader.read_bool()?;
+        self.halted = reader.read_bool()?;
+        self.joypad.load_state(reader)?;
+        self.memory.load_state(reader)
+    }
+
+}
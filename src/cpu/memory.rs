@@ -1,12 +1,137 @@
+use std::cell::RefCell;
+
+use crate::savestate::{SaveState, StateReader, StateWriter, StateError};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+pub struct Watchpoint {
+    pub addr: u16,
+    pub read: bool,
+    pub write: bool,
+}
+
+pub struct WatchHit {
+    pub addr: u16,
+    pub access: Access,
+}
+
+/// A byte the cpu reads in place of a rom byte, only while the rom holds compare if there is one
+pub struct RomPatch {
+    pub addr: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+/// The named regions of the address space, as (first address, last address, name)
+pub const REGIONS: [(u16, u16, &str); 11] = [
+    (0x0000, 0x3FFF, "ROM bank 0"),
+    (0x4000, 0x7FFF, "ROM bank 1"),
+    (0x8000, 0x9FFF, "VRAM"),
+    (0xA000, 0xBFFF, "Cartridge RAM"),
+    (0xC000, 0xDFFF, "WRAM"),
+    (0xE000, 0xFDFF, "Echo RAM"),
+    (0xFE00, 0xFE9F, "OAM"),
+    (0xFEA0, 0xFEFF, "Unusable"),
+    (0xFF00, 0xFF7F, "I/O"),
+    (0xFF80, 0xFFFE, "HRAM"),
+    (0xFFFF, 0xFFFF, "IE"),
+];
+
+/// The region an address is in
+pub fn region(addr: u16) -> (u16, u16, &'static str) {
+    *REGIONS.iter()
+        .find(|(start, end, _)| addr >= *start && addr <= *end)
+        .unwrap()
+}
+
+/*
+ *  Memory
+ *  Indexing goes through the watchpoints so the debugger can see accesses
+ *  made by instructions, peek and poke skip them for tools looking at memory.
+ *  Reads below 0x8000 also go through the rom patches, so Game Genie codes
+ *  change what both the cpu and the tools see without touching the rom
+ */
 pub struct Memory {
-    mem: [u8; 0x10000]
+    mem: [u8; 0x10000],
+    pub watchpoints: Vec<Watchpoint>,
+    pub patches: Vec<RomPatch>,
+    hits: RefCell<Vec<WatchHit>>,
 }
 
 impl Memory {
-    
+
     pub fn new() -> Self {
         Self {
             mem: [0; 0x10000],
+            watchpoints: Vec::new(),
+            patches: Vec::new(),
+            hits: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn peek(&self, addr: u16) -> u8 {
+        *self.read(addr as usize)
+    }
+
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.mem[addr as usize] = val;
+    }
+
+    /// Finds the first copy of a byte pattern at or after start, wrapping around
+    pub fn find(&self, start: u16, pattern: &[u8]) -> Option<u16> {
+        if pattern.is_empty() || pattern.len() > self.mem.len() {
+            return None;
+        }
+        (0..self.mem.len())
+            .map(|i| (start as usize + i) % self.mem.len())
+            .find(|addr| pattern.iter().enumerate().all(|(i, b)| self.mem[(addr + i) % self.mem.len()] == *b))
+            .map(|addr| addr as u16)
+    }
+
+    /// Copy of the whole address space
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.mem.to_vec()
+    }
+
+    /// Takes the address space from another memory, keeping the watchpoints and patches
+    pub fn take_state(&mut self, from: Memory) {
+        self.mem = from.mem;
+    }
+
+    /// The whole address space in place, for frontends that read it directly like libretro's memory maps
+    pub fn raw_mut(&mut self) -> &mut [u8; 0x10000] {
+        &mut self.mem
+    }
+
+    fn read(&self, index: usize) -> &u8 {
+        if !self.patches.is_empty() && index < 0x8000 {
+            let patch = self.patches.iter()
+                .find(|p| p.addr as usize == index && p.compare.is_none_or(|c| c == self.mem[index]));
+            if let Some(patch) = patch {
+                return &patch.value;
+            }
+        }
+        &self.mem[index]
+    }
+
+    /// Watchpoint hits since the last call
+    pub fn take_hits(&self) -> Vec<WatchHit> {
+        self.hits.take()
+    }
+
+    fn check_watch(&self, index: usize, access: Access) {
+        for watch in &self.watchpoints {
+            let watched = match access {
+                Access::Read => watch.read,
+                Access::Write => watch.write,
+            };
+            if watched && watch.addr as usize == index {
+                self.hits.borrow_mut().push(WatchHit { addr: watch.addr, access });
+            }
         }
     }
 
@@ -16,14 +141,32 @@ impl std::ops::Index<usize> for Memory {
     type Output = u8;
 
     fn index(&self, index: usize) -> &Self::Output {
-        &self.mem[index]
+        if !self.watchpoints.is_empty() {
+            self.check_watch(index, Access::Read);
+        }
+        self.read(index)
     }
 }
 
 impl std::ops::IndexMut<usize> for Memory {
-    
+
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        if !self.watchpoints.is_empty() {
+            self.check_watch(index, Access::Write);
+        }
         &mut self.mem[index]
     }
 
 }
+
+impl SaveState for Memory {
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.mem);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.mem)
+    }
+
+}
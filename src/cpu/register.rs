@@ -1,3 +1,5 @@
+use crate::savestate::{SaveState, StateReader, StateWriter, StateError};
+
 pub struct RegisterPair {
     pub left: u8,
     pub right: u8
@@ -94,3 +96,41 @@ impl Registers {
     }
 
 }
+
+impl SaveState for RegisterPair {
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.left);
+        writer.write_u8(self.right);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.left = reader.read_u8()?;
+        self.right = reader.read_u8()?;
+        Ok(())
+    }
+
+}
+
+impl SaveState for Registers {
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.af.save_state(writer);
+        self.bc.save_state(writer);
+        self.de.save_state(writer);
+        self.hl.save_state(writer);
+        writer.write_u16(self.sp);
+        writer.write_u16(self.pc);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.af.load_state(reader)?;
+        self.bc.load_state(reader)?;
+        self.de.load_state(reader)?;
+        self.hl.load_state(reader)?;
+        self.sp = reader.read_u16()?;
+        self.pc = reader.read_u16()?;
+        Ok(())
+    }
+
+}
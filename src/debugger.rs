@@ -0,0 +1,413 @@
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::cpu::Cpu;
+use crate::cpu::memory::{Access, Watchpoint};
+use crate::emulation::{decode_at, Instruction};
+use crate::io_registers;
+use crate::symbols::Symbols;
+
+pub mod gdb;
+
+use self::gdb::GdbStub;
+
+/// A call the program hasn't returned from yet
+struct Frame {
+    from: u16,
+    to: u16,
+    sp: u16,
+}
+
+enum Step {
+    Into,
+    Over { to: u16, sp: u16 },
+    Out { sp: u16 },
+    RunTo(u16),
+}
+
+/*
+ *  Debugger
+ *  Commands are read from stdin on their own thread and run between frames
+ *  on the emulation thread, so the window keeps drawing while paused
+ */
+pub struct Debugger {
+    pub paused: bool,
+    pub log_instructions: bool,
+    pub breakpoints: Vec<u16>,
+    pub gdb: Option<GdbStub>,
+    pub symbols: Symbols,
+    step: Option<Step>,
+    calls: Vec<Frame>,
+    last_call: Option<(u16, u16)>,
+    resuming: bool,
+    commands: Receiver<String>,
+}
+
+const HELP: &str = "\
+Commands:
+  c, continue            resume execution
+  p, pause               pause execution
+  s, step                step into the next instruction
+  n, next                step over calls
+  finish                 run until the current function returns
+  until <addr>           run to an address
+  bt, backtrace          show the call stack
+  b, break [addr]        add a breakpoint, or list them
+  d, delete <addr>       remove a breakpoint
+  w, watch <addr> [r|w|rw]  add a watchpoint (default rw)
+  unwatch <addr>         remove a watchpoint
+  r, regs                show registers and flags
+  set <reg> <value>      set a, b, c, d, e, h, l, f, af, bc, de, hl, sp or pc
+  flag <z|n|h|c> <0|1>   set a flag
+  x <addr> [len]         dump memory
+  poke <addr> <value>    write a byte to memory
+  io [name]              show hardware registers decoded into fields
+  cheat                  list cheats
+  cheat add <code> [name]  add a GameShark or Game Genie code, join several with +
+  cheat <on|off> <n>     switch a cheat on or off
+  log <on|off>           print every executed instruction
+  trace <on|off>         write the gameboy-doctor trace
+  trace range <from> <to>|off  only trace instructions in a range
+Addresses can be symbol names when the rom has a .sym file";
+
+/// Parses a hex number, with or without a 0x or $ prefix
+pub fn parse_number(text: &str) -> Option<u16> {
+    let text = text.trim_start_matches("0x").trim_start_matches('$');
+    u16::from_str_radix(text, 16).ok()
+}
+
+pub fn flags_string(cpu: &Cpu) -> String {
+    let af = &cpu.registers.af;
+    format!("{}{}{}{}",
+        if af.is_zero_high() { 'Z' } else { '-' },
+        if af.is_subtract_high() { 'N' } else { '-' },
+        if af.is_hcarry_high() { 'H' } else { '-' },
+        if af.is_carry_high() { 'C' } else { '-' },
+    )
+}
+
+pub fn registers_string(cpu: &Cpu) -> String {
+    let r = &cpu.registers;
+    format!("A:{:02X} F:{:02X} [{}] B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X}",
+        r.af.left, r.af.right, flags_string(cpu), r.bc.left, r.bc.right,
+        r.de.left, r.de.right, r.hl.left, r.hl.right, r.sp, r.pc)
+}
+
+/// Assembly text for the instruction at an address
+pub fn instruction_string(cpu: &Cpu, addr: u16, symbols: &Symbols) -> String {
+    match decode_at(cpu, addr) {
+        Some(decoded) => decoded.text_with(&|a| symbols.format(a)),
+        None => format!("DB ${:02X}", cpu.memory.peek(addr)),
+    }
+}
+
+impl Debugger {
+
+    pub fn new(paused: bool) -> Self {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        if paused {
+            println!("Debugger paused at start, type help for commands");
+        }
+
+        Self {
+            paused,
+            log_instructions: false,
+            breakpoints: Vec::new(),
+            gdb: None,
+            symbols: Symbols::new(),
+            step: None,
+            calls: Vec::new(),
+            last_call: None,
+            resuming: false,
+            commands,
+        }
+    }
+
+    /// Runs any commands typed since the last call, and any GDB packets
+    pub fn poll(&mut self, cpu: &mut Cpu) {
+        while let Ok(line) = self.commands.try_recv() {
+            self.execute(&line, cpu);
+        }
+
+        if let Some(mut gdb) = self.gdb.take() {
+            gdb.poll(self, cpu);
+            self.gdb = Some(gdb);
+        }
+    }
+
+    /// Called before every instruction, returns true if execution should stop
+    pub fn should_stop(&mut self, cpu: &mut Cpu) -> bool {
+        if self.paused {
+            return true;
+        }
+
+        let pc = cpu.registers.pc;
+        let sp = cpu.registers.sp;
+        let reason = if self.resuming {
+            self.resuming = false;
+            None
+        } else if self.breakpoints.contains(&pc) {
+            Some(format!("Breakpoint at {}", self.symbols.describe(pc)))
+        } else {
+            match self.step {
+                Some(Step::Into) => Some(String::from("Step")),
+                Some(Step::Over { to, sp: from }) if pc == to && sp >= from => Some(String::from("Step")),
+                Some(Step::Out { sp: from }) if sp > from => Some(String::from("Returned")),
+                Some(Step::RunTo(to)) if pc == to => Some(format!("Reached {}", self.symbols.describe(to))),
+                _ => None,
+            }
+        };
+
+        match reason {
+            Some(reason) => {
+                self.stop(cpu, &reason);
+                true
+            },
+            None => {
+                let call = decode_at(cpu, pc)
+                    .is_some_and(|d| matches!(d.instruction, Instruction::CALL(_, _) | Instruction::RST(_)));
+                self.last_call = if call { Some((pc, sp)) } else { None };
+                false
+            },
+        }
+    }
+
+    /// Called after every instruction to report watchpoint hits
+    pub fn after_instruction(&mut self, cpu: &mut Cpu) {
+        // Calls push the return address, so a frame is gone once sp climbs back above it
+        let sp = cpu.registers.sp;
+        self.calls.retain(|frame| frame.sp >= sp);
+        if let Some((from, before)) = self.last_call.take() {
+            if sp == before.wrapping_sub(2) {
+                self.calls.push(Frame { from, to: cpu.registers.pc, sp });
+            }
+        }
+
+        let hits = cpu.memory.take_hits();
+        if hits.is_empty() {
+            return;
+        }
+        for hit in &hits {
+            let access = match hit.access {
+                Access::Read => "read",
+                Access::Write => "write",
+            };
+            println!("Watchpoint: {} at {} (now {:#04x})", access, self.symbols.format(hit.addr), cpu.memory.peek(hit.addr));
+        }
+        self.stop(cpu, "Watchpoint hit");
+    }
+
+    pub fn stop(&mut self, cpu: &mut Cpu, reason: &str) {
+        self.paused = true;
+        self.step = None;
+        let pc = cpu.registers.pc;
+        println!("{} - {}: {}", reason, self.symbols.describe(pc), instruction_string(cpu, pc, &self.symbols));
+        println!("{}", registers_string(cpu));
+        if let Some(gdb) = self.gdb.as_mut() {
+            gdb.report_stop();
+        }
+    }
+
+    fn resume(&mut self, step: Option<Step>) {
+        self.paused = false;
+        self.resuming = true;
+        self.step = step;
+    }
+
+    pub fn execute(&mut self, line: &str, cpu: &mut Cpu) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let args: Vec<Option<u16>> = words.iter().map(|w| self.resolve(w)).collect();
+        let arg = |i: usize| args.get(i).copied().flatten();
+
+        match words.as_slice() {
+            [] => {},
+            ["help"] | ["h"] => println!("{}", HELP),
+            ["c"] | ["continue"] => self.resume(None),
+            ["p"] | ["pause"] => self.stop(cpu, "Paused"),
+            ["s"] | ["step"] => self.resume(Some(Step::Into)),
+            ["n"] | ["next"] => {
+                match decode_at(cpu, cpu.registers.pc) {
+                    Some(decoded) if matches!(decoded.instruction, Instruction::CALL(_, _) | Instruction::RST(_)) => {
+                        self.resume(Some(Step::Over { to: decoded.next(), sp: cpu.registers.sp }));
+                    },
+                    _ => self.resume(Some(Step::Into)),
+                }
+            },
+            ["finish"] => self.resume(Some(Step::Out { sp: cpu.registers.sp })),
+            ["until", _] => match arg(1) {
+                Some(addr) => self.resume(Some(Step::RunTo(addr))),
+                None => println!("Invalid address"),
+            },
+            ["bt"] | ["backtrace"] => {
+                for line in self.backtrace(cpu) {
+                    println!("{}", line);
+                }
+            },
+            ["b"] | ["break"] => {
+                for addr in &self.breakpoints {
+                    println!("Breakpoint at {}", self.symbols.describe(*addr));
+                }
+                for watch in &cpu.memory.watchpoints {
+                    println!("Watchpoint at {} ({}{})", self.symbols.format(watch.addr),
+                        if watch.read { "r" } else { "" }, if watch.write { "w" } else { "" });
+                }
+            },
+            ["b", _] | ["break", _] => match arg(1) {
+                Some(addr) if !self.breakpoints.contains(&addr) => {
+                    self.breakpoints.push(addr);
+                    println!("Breakpoint added at {}", self.symbols.describe(addr));
+                },
+                Some(_) => println!("Breakpoint already set"),
+                None => println!("Invalid address"),
+            },
+            ["d", _] | ["delete", _] => match arg(1) {
+                Some(addr) => self.breakpoints.retain(|b| *b != addr),
+                None => println!("Invalid address"),
+            },
+            ["w", _] | ["watch", _] | ["w", _, _] | ["watch", _, _] => {
+                let mode = words.get(2).copied().unwrap_or("rw");
+                match arg(1) {
+                    Some(addr) => {
+                        cpu.memory.watchpoints.push(Watchpoint {
+                            addr,
+                            read: mode.contains('r'),
+                            write: mode.contains('w'),
+                        });
+                        println!("Watchpoint added at {} ({})", self.symbols.format(addr), mode);
+                    },
+                    None => println!("Invalid address"),
+                }
+            },
+            ["unwatch", _] => match arg(1) {
+                Some(addr) => cpu.memory.watchpoints.retain(|w| w.addr != addr),
+                None => println!("Invalid address"),
+            },
+            ["r"] | ["regs"] => println!("{}", registers_string(cpu)),
+            ["set", reg, _] => match arg(2) {
+                Some(val) => self.set_register(cpu, reg, val),
+                None => println!("Invalid value"),
+            },
+            ["flag", flag, val] => {
+                let high = *val == "1";
+                let af = &mut cpu.registers.af;
+                match *flag {
+                    "z" if af.is_zero_high() != high => af.flip_zero_flag(),
+                    "n" if af.is_subtract_high() != high => af.flip_subtract_flag(),
+                    "h" if af.is_hcarry_high() != high => af.flip_hcarry_flag(),
+                    "c" if af.is_carry_high() != high => af.flip_carry_flag(),
+                    "z" | "n" | "h" | "c" => {},
+                    _ => println!("Unknown flag {}", flag),
+                }
+                println!("{}", registers_string(cpu));
+            },
+            ["x", _] | ["x", _, _] => match arg(1) {
+                Some(addr) => {
+                    let len = arg(2).unwrap_or(0x40) as u32;
+                    for row in (0..len).step_by(16) {
+                        let start = addr as u32 + row;
+                        let bytes: Vec<String> = (start..(start + 16).min(addr as u32 + len))
+                            .filter(|a| *a <= 0xFFFF)
+                            .map(|a| format!("{:02X}", cpu.memory.peek(a as u16)))
+                            .collect();
+                        if !bytes.is_empty() {
+                            println!("{:04X}: {}", start, bytes.join(" "));
+                        }
+                    }
+                },
+                None => println!("Invalid address"),
+            },
+            ["poke", _, _] => match (arg(1), arg(2)) {
+                (Some(addr), Some(val)) if val <= 0xFF => cpu.memory.poke(addr, val as u8),
+                _ => println!("Invalid address or value"),
+            },
+            ["io"] => {
+                for register in io_registers::REGISTERS {
+                    println!("{}", register.describe(cpu));
+                }
+            },
+            ["io", name] => match io_registers::find(name) {
+                Some(register) => println!("{}", register.describe(cpu)),
+                None => println!("Unknown register {}", name),
+            },
+            ["cheat"] => {
+                for line in cpu.cheats.describe() {
+                    println!("{}", line);
+                }
+            },
+            ["cheat", "add", code, name @ ..] => match cpu.cheats.add(code, &name.join(" ")) {
+                Ok(()) => println!("Added cheat {}", cpu.cheats.len() - 1),
+                Err(e) => println!("{}", e),
+            },
+            ["cheat", state @ ("on" | "off"), index] => match index.parse() {
+                Ok(i) => if let Err(e) = cpu.cheats.set_enabled(i, *state == "on") {
+                    println!("{}", e);
+                },
+                Err(_) => println!("Invalid cheat number"),
+            },
+            ["log", "on"] => self.log_instructions = true,
+            ["log", "off"] => self.log_instructions = false,
+            ["trace", "on"] => cpu.trace.enabled = true,
+            ["trace", "off"] => {
+                cpu.trace.enabled = false;
+                cpu.trace.flush();
+            },
+            ["trace", "range", "off"] => cpu.trace.range = None,
+            ["trace", "range", _, _] => match (arg(2), arg(3)) {
+                (Some(from), Some(to)) => cpu.trace.range = Some((from, to)),
+                _ => println!("Invalid range"),
+            },
+            _ => println!("Unknown command, type help for a list"),
+        }
+    }
+
+    /// The current location followed by every call the program is inside of
+    pub fn backtrace(&self, cpu: &Cpu) -> Vec<String> {
+        let mut lines = vec![format!("#0 {}", self.symbols.describe(cpu.registers.pc))];
+        for (i, frame) in self.calls.iter().rev().enumerate() {
+            lines.push(format!("#{} {} called from {}", i + 1, self.symbols.describe(frame.to), self.symbols.describe(frame.from)));
+        }
+        lines
+    }
+
+    /// A symbol name or a hex number
+    fn resolve(&self, text: &str) -> Option<u16> {
+        self.symbols.visible_address(text).or_else(|| parse_number(text))
+    }
+
+    fn set_register(&mut self, cpu: &mut Cpu, reg: &str, val: u16) {
+        let r = &mut cpu.registers;
+        let byte = val as u8;
+        match reg {
+            "a" => r.af.left = byte,
+            "f" => r.af.right = byte & 0xF0,
+            "b" => r.bc.left = byte,
+            "c" => r.bc.right = byte,
+            "d" => r.de.left = byte,
+            "e" => r.de.right = byte,
+            "h" => r.hl.left = byte,
+            "l" => r.hl.right = byte,
+            "af" => r.af.change_as_one(val & 0xFFF0),
+            "bc" => r.bc.change_as_one(val),
+            "de" => r.de.change_as_one(val),
+            "hl" => r.hl.change_as_one(val),
+            "sp" => r.sp = val,
+            "pc" => r.pc = val,
+            _ => {
+                println!("Unknown register {}", reg);
+                return;
+            },
+        }
+        println!("{}", registers_string(cpu));
+    }
+
+}
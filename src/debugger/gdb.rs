@@ -0,0 +1,370 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::cpu::Cpu;
+use crate::cpu::memory::Watchpoint;
+use super::{Debugger, Step};
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// Register layout reported to GDB, every register is 16 bits little endian
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.jboy.sm83.core">
+    <reg name="af" bitsize="16" type="int" regnum="0"/>
+    <reg name="bc" bitsize="16" type="int"/>
+    <reg name="de" bitsize="16" type="int"/>
+    <reg name="hl" bitsize="16" type="int"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+const REGISTER_COUNT: usize = 6;
+
+/*
+ *  GDB Stub
+ *  Speaks the GDB remote serial protocol over TCP. It drives the same
+ *  Debugger as the console, so breakpoints set from either side are shared
+ */
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: Vec<u8>,
+    last_packet: Vec<u8>,
+    running: bool,
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| text.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
+fn read_register(cpu: &Cpu, index: usize) -> Option<u16> {
+    let r = &cpu.registers;
+    match index {
+        0 => Some(r.af.take_as_one()),
+        1 => Some(r.bc.take_as_one()),
+        2 => Some(r.de.take_as_one()),
+        3 => Some(r.hl.take_as_one()),
+        4 => Some(r.sp),
+        5 => Some(r.pc),
+        _ => None,
+    }
+}
+
+fn write_register(cpu: &mut Cpu, index: usize, val: u16) -> bool {
+    let r = &mut cpu.registers;
+    match index {
+        0 => r.af.change_as_one(val & 0xFFF0),
+        1 => r.bc.change_as_one(val),
+        2 => r.de.change_as_one(val),
+        3 => r.hl.change_as_one(val),
+        4 => r.sp = val,
+        5 => r.pc = val,
+        _ => return false,
+    }
+    true
+}
+
+/// Registers go over the wire in target byte order
+fn register_hex(val: u16) -> String {
+    format!("{:02x}{:02x}", val & 0xFF, val >> 8)
+}
+
+/// Splits "addr,len" into numbers
+fn parse_pair(text: &str) -> Option<(u16, usize)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((u16::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
+
+impl GdbStub {
+
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+
+        let stub = Self {
+            listener,
+            client: None,
+            buffer: Vec::new(),
+            last_packet: Vec::new(),
+            running: false,
+        };
+        println!("Waiting for GDB on 127.0.0.1:{}", stub.port());
+        Ok(stub)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map(|a| a.port()).unwrap_or(0)
+    }
+
+    /// Accepts a connection and handles any packets that have arrived
+    pub fn poll(&mut self, debugger: &mut Debugger, cpu: &mut Cpu) {
+        if self.client.is_none() {
+            if let Ok((stream, addr)) = self.listener.accept() {
+                if stream.set_nonblocking(true).is_ok() {
+                    println!("GDB connected from {}", addr);
+                    self.client = Some(stream);
+                    self.buffer.clear();
+                    debugger.stop(cpu, "GDB attached");
+                }
+            }
+        }
+
+        let mut closed = false;
+        if let Some(client) = self.client.as_mut() {
+            let mut chunk = [0; 1024];
+            loop {
+                match client.read(&mut chunk) {
+                    Ok(0) => {
+                        closed = true;
+                        break;
+                    },
+                    Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(_) => {
+                        closed = true;
+                        break;
+                    },
+                }
+            }
+        }
+
+        self.process_buffer(debugger, cpu);
+
+        if closed {
+            self.disconnect(debugger);
+        }
+    }
+
+    fn process_buffer(&mut self, debugger: &mut Debugger, cpu: &mut Cpu) {
+        while !self.buffer.is_empty() && self.client.is_some() {
+            match self.buffer[0] {
+                b'+' => {
+                    self.buffer.remove(0);
+                },
+                b'-' => {
+                    self.buffer.remove(0);
+                    let packet = self.last_packet.clone();
+                    self.send_raw(&packet);
+                },
+                0x03 => {
+                    self.buffer.remove(0);
+                    debugger.stop(cpu, "Interrupted by GDB");
+                    self.send_stop(SIGINT);
+                },
+                b'$' => {
+                    let end = match self.buffer.iter().position(|b| *b == b'#') {
+                        Some(end) if end + 2 < self.buffer.len() => end,
+                        _ => return,
+                    };
+                    let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let sum = std::str::from_utf8(&packet[end + 1..])
+                        .ok()
+                        .and_then(|s| u8::from_str_radix(s, 16).ok());
+
+                    if sum != Some(checksum(data)) {
+                        self.send_raw(b"-");
+                        continue;
+                    }
+                    self.send_raw(b"+");
+
+                    let data = String::from_utf8_lossy(data).into_owned();
+                    if let Some(reply) = self.handle(&data, debugger, cpu) {
+                        self.send(&reply);
+                    }
+                },
+                _ => {
+                    self.buffer.remove(0);
+                },
+            }
+        }
+    }
+
+    /*
+     *  Handles a single packet
+     *  Returns the reply, or None when the reply comes later (continue and step
+     *  answer once the target stops)
+     */
+    pub fn handle(&mut self, packet: &str, debugger: &mut Debugger, cpu: &mut Cpu) -> Option<String> {
+        let split = packet.char_indices().nth(1).map_or(packet.len(), |(i, _)| i);
+        let (command, args) = packet.split_at(split);
+
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => (0..REGISTER_COUNT)
+                .filter_map(|i| read_register(cpu, i))
+                .map(register_hex)
+                .collect(),
+            "G" => match hex_bytes(args) {
+                Some(bytes) if bytes.len() >= REGISTER_COUNT * 2 => {
+                    for (i, pair) in bytes.chunks(2).take(REGISTER_COUNT).enumerate() {
+                        write_register(cpu, i, u16::from_le_bytes([pair[0], pair[1]]));
+                    }
+                    String::from("OK")
+                },
+                _ => String::from("E01"),
+            },
+            "p" => match usize::from_str_radix(args, 16).ok().and_then(|i| read_register(cpu, i)) {
+                Some(val) => register_hex(val),
+                None => String::from("E01"),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(index, val)| {
+                    let bytes = hex_bytes(val)?;
+                    let index = usize::from_str_radix(index, 16).ok()?;
+                    Some((index, u16::from_le_bytes([*bytes.first()?, bytes.get(1).copied().unwrap_or(0)])))
+                });
+                match parsed {
+                    Some((index, val)) if write_register(cpu, index, val) => String::from("OK"),
+                    _ => String::from("E01"),
+                }
+            },
+            "m" => match parse_pair(args) {
+                Some((addr, len)) => (0..len.min(0x10000))
+                    .map(|i| format!("{:02x}", cpu.memory.peek(addr.wrapping_add(i as u16))))
+                    .collect(),
+                None => String::from("E01"),
+            },
+            "M" => {
+                let parsed = args.split_once(':')
+                    .and_then(|(range, data)| Some((parse_pair(range)?, hex_bytes(data)?)));
+                match parsed {
+                    Some(((addr, len), bytes)) if bytes.len() == len => {
+                        for (i, byte) in bytes.iter().enumerate() {
+                            cpu.memory.poke(addr.wrapping_add(i as u16), *byte);
+                        }
+                        String::from("OK")
+                    },
+                    _ => String::from("E01"),
+                }
+            },
+            "c" | "s" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    cpu.registers.pc = addr;
+                }
+                let step = if command == "s" { Some(Step::Into) } else { None };
+                debugger.resume(step);
+                self.running = true;
+                return None;
+            },
+            "Z" | "z" => self.breakpoint(command == "Z", args, debugger, cpu),
+            "H" => String::from("OK"),
+            "k" | "D" => {
+                self.send("OK");
+                self.disconnect(debugger);
+                return None;
+            },
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    /// Z and z packets, type 0 and 1 are breakpoints, 2 to 4 are watchpoints
+    fn breakpoint(&mut self, insert: bool, args: &str, debugger: &mut Debugger, cpu: &mut Cpu) -> String {
+        let mut parts = args.split(',');
+        let kind = parts.next();
+        let addr = match parts.next().and_then(|a| u16::from_str_radix(a, 16).ok()) {
+            Some(addr) => addr,
+            None => return String::from("E01"),
+        };
+
+        let (read, write) = match kind {
+            Some("0") | Some("1") => {
+                if insert && !debugger.breakpoints.contains(&addr) {
+                    debugger.breakpoints.push(addr);
+                } else if !insert {
+                    debugger.breakpoints.retain(|b| *b != addr);
+                }
+                return String::from("OK");
+            },
+            Some("2") => (false, true),
+            Some("3") => (true, false),
+            Some("4") => (true, true),
+            _ => return String::new(),
+        };
+
+        let watchpoints = &mut cpu.memory.watchpoints;
+        if insert {
+            watchpoints.push(Watchpoint { addr, read, write });
+        } else {
+            watchpoints.retain(|w| !(w.addr == addr && w.read == read && w.write == write));
+        }
+        String::from("OK")
+    }
+
+    fn query(&self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            return String::from("PacketSize=1000;qXfer:features:read+");
+        }
+        if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            return match parse_pair(range) {
+                Some((offset, len)) => {
+                    let offset = (offset as usize).min(TARGET_XML.len());
+                    let end = (offset + len).min(TARGET_XML.len());
+                    let prefix = if end == TARGET_XML.len() { 'l' } else { 'm' };
+                    format!("{}{}", prefix, &TARGET_XML[offset..end])
+                },
+                None => String::from("E01"),
+            };
+        }
+        match args {
+            "Attached" => String::from("1"),
+            "C" => String::from("QC1"),
+            "fThreadInfo" => String::from("m1"),
+            "sThreadInfo" => String::from("l"),
+            _ => String::new(),
+        }
+    }
+
+    /// Tells GDB the target stopped, if it is waiting on a continue or step
+    pub fn report_stop(&mut self) {
+        if self.running {
+            self.send_stop(SIGTRAP);
+        }
+    }
+
+    fn send_stop(&mut self, signal: u8) {
+        self.running = false;
+        self.send(&format!("S{:02x}", signal));
+    }
+
+    fn send(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        self.last_packet = packet.clone().into_bytes();
+        self.send_raw(packet.as_bytes());
+    }
+
+    fn send_raw(&mut self, data: &[u8]) {
+        if let Some(client) = self.client.as_mut() {
+            if client.write_all(data).is_err() {
+                self.client = None;
+            }
+        }
+    }
+
+    fn disconnect(&mut self, debugger: &mut Debugger) {
+        if self.client.take().is_some() {
+            println!("GDB disconnected");
+        }
+        self.buffer.clear();
+        self.running = false;
+        debugger.resume(None);
+    }
+
+}
@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+
+use crate::cpu::{CondCode, RegCode};
+use crate::emulation::{decode_bytes, Decoded, Instruction};
+use crate::symbols::Symbols;
+
+/*
+ *  Disassembler
+ *  Turns decoded instructions into assembly text and dumps whole roms.
+ *  Addresses in the rom are shown as bank:address, bank 0 is always at
+ *  0x0000-0x3FFF and every other bank is switched into 0x4000-0x7FFF
+ */
+
+pub const BANK_SIZE: usize = 0x4000;
+
+fn condition(cond: &CondCode) -> Option<&'static str> {
+    match cond {
+        CondCode::NZ => Some("NZ"),
+        CondCode::NC => Some("NC"),
+        CondCode::Z => Some("Z"),
+        CondCode::C => Some("C"),
+        CondCode::Always => None,
+    }
+}
+
+fn with_condition(mnemonic: &str, cond: &CondCode, operand: String) -> String {
+    match condition(cond) {
+        Some(cond) => format!("{} {},{}", mnemonic, cond, operand),
+        None => format!("{} {}", mnemonic, operand),
+    }
+}
+
+fn signed(val: i8) -> String {
+    if val < 0 {
+        format!("-${:02X}", val.unsigned_abs())
+    } else {
+        format!("+${:02X}", val)
+    }
+}
+
+/// Operand of an 8 bit instruction, register pairs are always pointers here
+fn operand8(code: &RegCode) -> String {
+    match code {
+        RegCode::BC | RegCode::DE | RegCode::HL => format!("({})", code),
+        RegCode::Const8(val) => format!("${:02X}", val),
+        RegCode::Const16(val) => format!("(${:04X})", val),
+        _ => code.to_string(),
+    }
+}
+
+fn operand16(code: &RegCode) -> String {
+    match code {
+        RegCode::Const16(val) => format!("${:04X}", val),
+        _ => code.to_string(),
+    }
+}
+
+/// Operand of the LD instructions that reach into the 0xFF00 page
+fn operand_high(code: &RegCode) -> String {
+    match code {
+        RegCode::Const8(val) => format!("(${:04X})", 0xFF00 + *val as u16),
+        RegCode::C => String::from("($FF00+C)"),
+        _ => operand8(code),
+    }
+}
+
+/*
+ *  Formats an instruction as assembly
+ *  next is the address after the instruction (for relative jumps), and
+ *  address names jump and call targets
+ */
+pub fn format_instruction(instruction: &Instruction, next: u16, address: &dyn Fn(u16) -> String) -> String {
+    match instruction {
+        Instruction::PLACEHOLDER => String::from("???"),
+        Instruction::NOP => String::from("NOP"),
+        Instruction::STOP => String::from("STOP"),
+        Instruction::HALT => String::from("HALT"),
+        Instruction::DI => String::from("DI"),
+        Instruction::EI => String::from("EI"),
+        Instruction::RLCA => String::from("RLCA"),
+        Instruction::RLA => String::from("RLA"),
+        Instruction::RRCA => String::from("RRCA"),
+        Instruction::RRA => String::from("RRA"),
+        Instruction::DAA => String::from("DAA"),
+        Instruction::CPL => String::from("CPL"),
+        Instruction::SCF => String::from("SCF"),
+        Instruction::CCF => String::from("CCF"),
+        Instruction::RETI => String::from("RETI"),
+        Instruction::JPHL => String::from("JP HL"),
+        Instruction::LDSPHL => String::from("LD SP,HL"),
+        Instruction::ADDSP(val) => format!("ADD SP,{}", signed(*val)),
+        Instruction::LDHLSP(val) => format!("LD HL,SP{}", signed(*val)),
+        Instruction::JR(cond, by) => with_condition("JR", cond, address(next.wrapping_add(*by as u16))),
+        Instruction::JP(cond, to) => with_condition("JP", cond, address(*to)),
+        Instruction::CALL(cond, to) => with_condition("CALL", cond, address(*to)),
+        Instruction::RET(cond) => match condition(cond) {
+            Some(cond) => format!("RET {}", cond),
+            None => String::from("RET"),
+        },
+        Instruction::RST(to) => format!("RST ${:02X}", to),
+        Instruction::LD16(RegCode::Const16(to), source) => format!("LD (${:04X}),{}", to, source),
+        Instruction::LD16(target, source) => format!("LD {},{}", target, operand16(source)),
+        Instruction::LD8(target, source) => format!("LD {},{}", operand8(target), operand8(source)),
+        Instruction::LDW(target, source) => {
+            let mnemonic = if matches!(target, RegCode::Const8(_)) || matches!(source, RegCode::Const8(_)) { "LDH" } else { "LD" };
+            format!("{} {},{}", mnemonic, operand_high(target), operand_high(source))
+        },
+        Instruction::LDI(RegCode::HL, source) => format!("LD (HL+),{}", source),
+        Instruction::LDI(target, _) => format!("LD {},(HL+)", target),
+        Instruction::LDD(RegCode::HL, source) => format!("LD (HL-),{}", source),
+        Instruction::LDD(target, _) => format!("LD {},(HL-)", target),
+        Instruction::INC16(code) => format!("INC {}", code),
+        Instruction::DEC16(code) => format!("DEC {}", code),
+        Instruction::INC8(code) => format!("INC {}", operand8(code)),
+        Instruction::DEC8(code) => format!("DEC {}", operand8(code)),
+        Instruction::ADD16(code) => format!("ADD HL,{}", code),
+        Instruction::ADD8(code) => format!("ADD A,{}", operand8(code)),
+        Instruction::ADC(code) => format!("ADC A,{}", operand8(code)),
+        Instruction::SBC(code) => format!("SBC A,{}", operand8(code)),
+        Instruction::SUB(code) => format!("SUB {}", operand8(code)),
+        Instruction::AND(code) => format!("AND {}", operand8(code)),
+        Instruction::XOR(code) => format!("XOR {}", operand8(code)),
+        Instruction::OR(code) => format!("OR {}", operand8(code)),
+        Instruction::CP(code) => format!("CP {}", operand8(code)),
+        Instruction::PUSH(code) => format!("PUSH {}", code),
+        Instruction::POP(code) => format!("POP {}", code),
+        Instruction::RLC(code) => format!("RLC {}", operand8(code)),
+        Instruction::RRC(code) => format!("RRC {}", operand8(code)),
+        Instruction::RL(code) => format!("RL {}", operand8(code)),
+        Instruction::RR(code) => format!("RR {}", operand8(code)),
+        Instruction::SLA(code) => format!("SLA {}", operand8(code)),
+        Instruction::SRA(code) => format!("SRA {}", operand8(code)),
+        Instruction::SRL(code) => format!("SRL {}", operand8(code)),
+        Instruction::SWAP(code) => format!("SWAP {}", operand8(code)),
+        Instruction::BIT(bit, code) => format!("BIT {},{}", bit, operand8(code)),
+        Instruction::RES(bit, code) => format!("RES {},{}", bit, operand8(code)),
+        Instruction::SET(bit, code) => format!("SET {},{}", bit, operand8(code)),
+    }
+}
+
+impl Decoded {
+
+    /// Assembly text, e.g. "LD A,$12"
+    pub fn text(&self) -> String {
+        self.text_with(&|addr| format!("${:04X}", addr))
+    }
+
+    /// Assembly text with jump and call targets named by address
+    pub fn text_with(&self, address: &dyn Fn(u16) -> String) -> String {
+        format_instruction(&self.instruction, self.next(), address)
+    }
+
+}
+
+/*
+ *  Works out which bank a jump target lives in
+ *  Bank 0 is fixed, a jump into 0x4000-0x7FFF stays in the current bank, and
+ *  anything else (ram, or switchable rom seen from bank 0) has no bank
+ */
+pub fn target_bank(bank: usize, addr: u16) -> Option<usize> {
+    match addr {
+        0x0000..=0x3FFF => Some(0),
+        0x4000..=0x7FFF if bank > 0 => Some(bank),
+        _ => None,
+    }
+}
+
+pub fn label_name(bank: usize, addr: u16) -> String {
+    format!("L{:02X}_{:04X}", bank, addr)
+}
+
+/// A single line of a rom dump, either an instruction or a byte that isn't one
+pub struct Line {
+    pub bank: usize,
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub decoded: Option<Decoded>,
+}
+
+/// Linear sweep over every bank of the rom
+pub fn sweep(rom: &[u8]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let banks = rom.len().div_ceil(BANK_SIZE);
+
+    for bank in 0..banks {
+        let start = if bank == 0 { 0x0000 } else { 0x4000 };
+        let data = &rom[bank * BANK_SIZE..rom.len().min((bank + 1) * BANK_SIZE)];
+
+        let mut offset = 0;
+        while offset < data.len() {
+            let addr = (start + offset) as u16;
+            let decoded = decode_bytes(&data[offset..], addr);
+            let length = decoded.as_ref().map_or(1, |d| d.length as usize);
+            lines.push(Line {
+                bank,
+                addr,
+                bytes: data[offset..offset + length].to_vec(),
+                decoded,
+            });
+            offset += length;
+        }
+    }
+
+    lines
+}
+
+/*
+ *  Disassembles a whole rom
+ *  Symbols and any jump or call targets that land on an instruction get a
+ *  label, named after the bank and address when there's no symbol for them.
+ *  Cycle counts are in the comments
+ */
+pub fn disassemble(rom: &[u8], symbols: &Symbols) -> String {
+    let lines = sweep(rom);
+    let starts: HashSet<(usize, u16)> = lines.iter()
+        .filter(|l| l.decoded.is_some())
+        .map(|l| (l.bank, l.addr))
+        .collect();
+    let labels: HashSet<(usize, u16)> = lines.iter()
+        .filter_map(|l| {
+            let target = l.decoded.as_ref()?.target()?;
+            Some((target_bank(l.bank, target)?, target))
+        })
+        .chain(starts.iter().copied().filter(|(bank, addr)| symbols.name(*bank, *addr).is_some()))
+        .filter(|t| starts.contains(t))
+        .collect();
+    let label = |bank: usize, addr: u16| match symbols.name(bank, addr) {
+        Some(name) => name.to_string(),
+        None => label_name(bank, addr),
+    };
+
+    let mut out = String::new();
+    for line in &lines {
+        if labels.contains(&(line.bank, line.addr)) {
+            out.push_str(&format!("{}:\n", label(line.bank, line.addr)));
+        }
+
+        let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let text = match &line.decoded {
+            Some(decoded) => {
+                let text = decoded.text_with(&|addr| match target_bank(line.bank, addr) {
+                    Some(bank) if labels.contains(&(bank, addr)) => label(bank, addr),
+                    Some(bank) => symbols.format_banked(bank, addr),
+                    None => symbols.format(addr),
+                });
+                if decoded.cycles == decoded.taken_cycles {
+                    format!("{:<20} ; {}", text, decoded.cycles)
+                } else {
+                    format!("{:<20} ; {}/{}", text, decoded.cycles, decoded.taken_cycles)
+                }
+            },
+            None => format!("DB ${:02X}", line.bytes[0]),
+        };
+        out.push_str(&format!("{:02X}:{:04X}  {:<9} {}\n", line.bank, line.addr, bytes.join(" "), text));
+    }
+    out
+}
@@ -1,6 +1,9 @@
 use crate::cpu::RegCode;
 use crate::cpu::CondCode;
 use crate::cpu::Cpu;
+use crate::trace;
+
+pub const CYCLES_PER_FRAME: u32 = 70224;
 
 pub enum Instruction {
     PLACEHOLDER,
@@ -23,7 +26,13 @@ pub enum Instruction {
     RLA,
     RRCA,
     RRA,
+    DAA,
+    CPL,
+    SCF,
+    CCF,
     ADDSP(i8),
+    LDHLSP(i8),
+    LDSPHL,
     ADD8(RegCode),
     ADD16(RegCode),
     SUB(RegCode),
@@ -118,9 +127,27 @@ impl std::fmt::Display for Instruction {
             Instruction::RRA => {
                 write!(f, "Rotating A right through carry")
             },
+            Instruction::DAA => {
+                write!(f, "Decimal adjusting A")
+            },
+            Instruction::CPL => {
+                write!(f, "Complementing A")
+            },
+            Instruction::SCF => {
+                write!(f, "Setting the carry flag")
+            },
+            Instruction::CCF => {
+                write!(f, "Flipping the carry flag")
+            },
             Instruction::ADDSP(i) => {
                 write!(f, "Adding {:#04x} to the stack pointer", i)
             },
+            Instruction::LDHLSP(i) => {
+                write!(f, "LD from the stack pointer plus {:#04x}, into HL", i)
+            },
+            Instruction::LDSPHL => {
+                write!(f, "LD from HL, into SP")
+            },
             Instruction::ADD8(code) => {
                 write!(f, "Adding to a from {}", code)
             },
@@ -210,61 +237,191 @@ impl std::fmt::Display for Instruction {
 
 }
 
+impl Instruction {
+
+    /*
+     *  Clock cycles taken by the instruction
+     *  taken selects the longer timing of conditional jumps, calls and returns
+     */
+    pub fn cycles(&self, taken: bool) -> u32 {
+        let indirect = |code: &RegCode| matches!(code, RegCode::BC | RegCode::DE | RegCode::HL);
+        let operand = |code: &RegCode| if matches!(code, RegCode::HL | RegCode::Const8(_)) { 8 } else { 4 };
+        let branch = |cond: &CondCode, short: u32, long: u32| if taken || matches!(cond, CondCode::Always) { long } else { short };
+
+        match self {
+            Instruction::PLACEHOLDER => 0,
+            Instruction::NOP | Instruction::STOP | Instruction::HALT | Instruction::DI | Instruction::EI => 4,
+            Instruction::RLCA | Instruction::RLA | Instruction::RRCA | Instruction::RRA => 4,
+            Instruction::DAA | Instruction::CPL | Instruction::SCF | Instruction::CCF => 4,
+            Instruction::JR(cond, _) => branch(cond, 8, 12),
+            Instruction::LD16(RegCode::Const16(_), _) => 20,
+            Instruction::LD16(_, _) => 12,
+            Instruction::LD8(RegCode::HL, RegCode::Const8(_)) => 12,
+            Instruction::LD8(target, source) => {
+                if indirect(target) || indirect(source) || matches!(source, RegCode::Const8(_)) { 8 } else { 4 }
+            },
+            Instruction::LDW(RegCode::Const16(_), _) | Instruction::LDW(_, RegCode::Const16(_)) => 16,
+            Instruction::LDW(RegCode::Const8(_), _) | Instruction::LDW(_, RegCode::Const8(_)) => 12,
+            Instruction::LDW(_, _) => 8,
+            Instruction::LDD(_, _) | Instruction::LDI(_, _) => 8,
+            Instruction::INC16(_) | Instruction::DEC16(_) => 8,
+            Instruction::INC8(RegCode::HL) | Instruction::DEC8(RegCode::HL) => 12,
+            Instruction::INC8(_) | Instruction::DEC8(_) => 4,
+            Instruction::ADDSP(_) => 16,
+            Instruction::LDHLSP(_) => 12,
+            Instruction::LDSPHL => 8,
+            Instruction::ADD16(_) => 8,
+            Instruction::ADD8(code) | Instruction::SUB(code) | Instruction::AND(code) | Instruction::XOR(code) |
+            Instruction::OR(code) | Instruction::SBC(code) | Instruction::ADC(code) | Instruction::CP(code) => operand(code),
+            Instruction::PUSH(_) => 16,
+            Instruction::POP(_) => 12,
+            Instruction::RET(CondCode::Always) => 16,
+            Instruction::RET(cond) => branch(cond, 8, 20),
+            Instruction::RETI => 16,
+            Instruction::JP(cond, _) => branch(cond, 12, 16),
+            Instruction::JPHL => 4,
+            Instruction::RST(_) => 16,
+            Instruction::CALL(cond, _) => branch(cond, 12, 24),
+            Instruction::BIT(_, RegCode::HL) => 12,
+            Instruction::RLC(code) | Instruction::RRC(code) | Instruction::RL(code) | Instruction::RR(code) |
+            Instruction::SLA(code) | Instruction::SRA(code) | Instruction::SRL(code) | Instruction::SWAP(code) |
+            Instruction::BIT(_, code) | Instruction::RES(_, code) | Instruction::SET(_, code) => {
+                if matches!(code, RegCode::HL) { 16 } else { 8 }
+            },
+        }
+    }
+
+}
+
+/*
+ *  Decoded Instruction
+ *  Everything known about an instruction without running it
+ */
+pub struct Decoded {
+    pub instruction: Instruction,
+    pub addr: u16,
+    pub length: u16,
+    pub cycles: u32,
+    pub taken_cycles: u32,
+}
+
+impl Decoded {
+
+    /// Address of the instruction after this one
+    pub fn next(&self) -> u16 {
+        self.addr.wrapping_add(self.length)
+    }
+
+    /// Where a jump, call or restart goes, if it is known before running
+    pub fn target(&self) -> Option<u16> {
+        match self.instruction {
+            Instruction::JR(_, by) => Some(self.next().wrapping_add(by as u16)),
+            Instruction::JP(_, to) | Instruction::CALL(_, to) | Instruction::RST(to) => Some(to),
+            _ => None,
+        }
+    }
+
+}
+
+/*
+ *  Fetches the instruction at pc and moves pc past it
+ */
 pub fn fetch(cpu: &mut Cpu) -> Instruction {
     let nibble = cpu.current_pc_byte();
-    
-    /*
-    clearscreen::clear().unwrap();
-    println!("Registers:");
-    println!("A:{: >4} F:{: >4}", cpu.registers.af.left, cpu.registers.af.right);
-    println!("B:{: >4} C:{: >4}", cpu.registers.bc.left, cpu.registers.bc.right);
-    println!("D:{: >4} E:{: >4}", cpu.registers.de.left, cpu.registers.de.right);
-    println!("H:{: >4} L:{: >4}", cpu.registers.hl.left, cpu.registers.hl.right);
-    println!("SP:{: >8}", cpu.registers.sp);
-    println!("PC:{: >8}", cpu.registers.pc);
-    println!("Opcode: {:#04x}", nibble);
-    */
 
+    if cpu.trace.wants(cpu.registers.pc) {
+        let line = trace::doctor_line(cpu);
+        cpu.trace.write_line(&line);
+    }
+
+    let pc = cpu.registers.pc;
+    let decoded = decode(|addr| cpu.memory[addr as usize], pc)
+        .unwrap_or_else(|| panic!("Invalid Opcode: {nibble} | Instruction could not be fetched."));
+    cpu.registers.pc = pc.wrapping_add(decoded.length);
+    decoded.instruction
+}
+
+/// Decodes the instruction at addr in the cpu's memory without touching the cpu
+pub fn decode_at(cpu: &Cpu, addr: u16) -> Option<Decoded> {
+    decode(|a| cpu.memory.peek(a), addr)
+}
+
+/// Decodes an instruction from the start of a byte slice, None if it runs off the end
+pub fn decode_bytes(bytes: &[u8], addr: u16) -> Option<Decoded> {
+    let decoded = decode(|a| bytes.get(a.wrapping_sub(addr) as usize).copied().unwrap_or(0), addr)?;
+    if decoded.length as usize > bytes.len() {
+        return None;
+    }
+    Some(decoded)
+}
+
+struct Cursor<F: Fn(u16) -> u8> {
+    read: F,
+    pc: u16,
+}
+
+impl<F: Fn(u16) -> u8> Cursor<F> {
+
+    fn current_pc_byte(&self) -> u8 {
+        (self.read)(self.pc)
+    }
+
+    fn increment_pc(&mut self) {
+        self.pc = self.pc.wrapping_add(1);
+    }
+
+    fn get_16_pc(&mut self) -> u16 {
+        let mut num: u16 = self.current_pc_byte() as u16;
+        self.increment_pc();
+        num += (self.current_pc_byte() as u16) << 8;
+        num
+    }
+
+}
+
+/*
+ *  Decodes the instruction at addr, reading bytes through read
+ *  Has no side effects, returns None for opcodes that don't exist
+ */
+pub fn decode(read: impl Fn(u16) -> u8, addr: u16) -> Option<Decoded> {
+    let mut cursor = Cursor { read, pc: addr };
     let mut prefixed = false;
 
-    let mut instruction = match nibble {
+    let mut instruction = match cursor.current_pc_byte() {
         
-        // Instruction that doesn't exist but helps debug
-        0xD3 => {
-            println!("{}", cpu.registers.af.left);
-            Instruction::NOP
-        },
+        // Instruction that doesn't exist but helps debug, fetch prints A for it
+        0xD3 => Instruction::NOP,
 
         0x00 => Instruction::NOP,
         0x10 => Instruction::STOP,
         0x20 => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte() as i8;
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte() as i8;
             Instruction::JR(CondCode::NZ, val)
         },
         0x30 => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte() as i8;
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte() as i8;
             Instruction::JR(CondCode::NC, val)
         }
         0x01 => {
-            cpu.increment_pc();
-            let code = RegCode::Const16(cpu.get_16_pc());
+            cursor.increment_pc();
+            let code = RegCode::Const16(cursor.get_16_pc());
             Instruction::LD16(RegCode::BC, code)
         },
         0x11 => {
-            cpu.increment_pc();
-            let code = RegCode::Const16(cpu.get_16_pc());
+            cursor.increment_pc();
+            let code = RegCode::Const16(cursor.get_16_pc());
             Instruction::LD16(RegCode::DE, code)
         },
         0x21 => {
-            cpu.increment_pc();
-            let code = RegCode::Const16(cpu.get_16_pc());
+            cursor.increment_pc();
+            let code = RegCode::Const16(cursor.get_16_pc());
             Instruction::LD16(RegCode::HL, code)
         },
         0x31 => {
-            cpu.increment_pc();
-            let code = RegCode::Const16(cpu.get_16_pc());
+            cursor.increment_pc();
+            let code = RegCode::Const16(cursor.get_16_pc());
             Instruction::LD16(RegCode::SP, code)
         },
         0x02 => Instruction::LD8(RegCode::BC, RegCode::A),
@@ -284,47 +441,47 @@ pub fn fetch(cpu: &mut Cpu) -> Instruction {
         0x25 => Instruction::DEC8(RegCode::H),
         0x35 => Instruction::DEC8(RegCode::HL),
         0x06 => {
-            cpu.increment_pc();
-            let code = RegCode::Const8(cpu.current_pc_byte());
+            cursor.increment_pc();
+            let code = RegCode::Const8(cursor.current_pc_byte());
             Instruction::LD8(RegCode::B, code)
         },
         0x16 => {
-            cpu.increment_pc();
-            let code = RegCode::Const8(cpu.current_pc_byte());
+            cursor.increment_pc();
+            let code = RegCode::Const8(cursor.current_pc_byte());
             Instruction::LD8(RegCode::D, code)
         },
         0x26 => {
-            cpu.increment_pc();
-            let code = RegCode::Const8(cpu.current_pc_byte());
+            cursor.increment_pc();
+            let code = RegCode::Const8(cursor.current_pc_byte());
             Instruction::LD8(RegCode::H, code)
         },
         0x36 => {
-            cpu.increment_pc();
-            let code = RegCode::Const8(cpu.current_pc_byte());
+            cursor.increment_pc();
+            let code = RegCode::Const8(cursor.current_pc_byte());
             Instruction::LD8(RegCode::HL, code)
         },
         0x07 => Instruction::RLCA,
         0x17 => Instruction::RLA,
-        0x27 => todo!(),
-        0x37 => todo!(),
+        0x27 => Instruction::DAA,
+        0x37 => Instruction::SCF,
         0x08 => {
-            cpu.increment_pc();
-            let code = RegCode::Const16(cpu.get_16_pc());
+            cursor.increment_pc();
+            let code = RegCode::Const16(cursor.get_16_pc());
             Instruction::LD16(code, RegCode::SP)
         },
         0x18 => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte() as i8;
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte() as i8;
             Instruction::JR(CondCode::Always, val)
         },
         0x28 => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte() as i8;
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte() as i8;
             Instruction::JR(CondCode::Z, val)
         },
         0x38 => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte() as i8;
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte() as i8;
             Instruction::JR(CondCode::C, val)
         },
         0x09 => Instruction::ADD16(RegCode::BC),
@@ -348,29 +505,29 @@ pub fn fetch(cpu: &mut Cpu) -> Instruction {
         0x2D => Instruction::DEC8(RegCode::L),
         0x3D => Instruction::DEC8(RegCode::A),
         0x0E => {
-            cpu.increment_pc();
-            let code = RegCode::Const8(cpu.current_pc_byte());
+            cursor.increment_pc();
+            let code = RegCode::Const8(cursor.current_pc_byte());
             Instruction::LD8(RegCode::C, code)
         },
         0x1E => {
-            cpu.increment_pc();
-            let code = RegCode::Const8(cpu.current_pc_byte());
+            cursor.increment_pc();
+            let code = RegCode::Const8(cursor.current_pc_byte());
             Instruction::LD8(RegCode::E, code)
         },
         0x2E => {
-            cpu.increment_pc();
-            let code = RegCode::Const8(cpu.current_pc_byte());
+            cursor.increment_pc();
+            let code = RegCode::Const8(cursor.current_pc_byte());
             Instruction::LD8(RegCode::L, code)
         },
         0x3E => {
-            cpu.increment_pc();
-            let code = RegCode::Const8(cpu.current_pc_byte());
+            cursor.increment_pc();
+            let code = RegCode::Const8(cursor.current_pc_byte());
             Instruction::LD8(RegCode::A, code)
         },
         0x0F => Instruction::RRCA,
         0x1F => Instruction::RRA,
-        0x2F => todo!(),
-        0x3F => todo!(),
+        0x2F => Instruction::CPL,
+        0x3F => Instruction::CCF,
         0x40 => Instruction::LD8(RegCode::B, RegCode::B),
         0x41 => Instruction::LD8(RegCode::B, RegCode::C),
         0x42 => Instruction::LD8(RegCode::B, RegCode::D),
@@ -502,13 +659,13 @@ pub fn fetch(cpu: &mut Cpu) -> Instruction {
         0xC0 => Instruction::RET(CondCode::NZ),
         0xD0 => Instruction::RET(CondCode::NC),
         0xE0 => {
-            cpu.increment_pc();
-            let code = RegCode::Const8(cpu.current_pc_byte());
+            cursor.increment_pc();
+            let code = RegCode::Const8(cursor.current_pc_byte());
             Instruction::LDW(code, RegCode::A)
         },
         0xF0 => {
-            cpu.increment_pc();
-            let code = RegCode::Const8(cpu.current_pc_byte());
+            cursor.increment_pc();
+            let code = RegCode::Const8(cursor.current_pc_byte());
             Instruction::LDW(RegCode::A, code)
         },
         0xC1 => Instruction::POP(RegCode::BC),
@@ -516,31 +673,31 @@ pub fn fetch(cpu: &mut Cpu) -> Instruction {
         0xE1 => Instruction::POP(RegCode::HL),
         0xF1 => Instruction::POP(RegCode::AF),
         0xC2 => {
-            cpu.increment_pc();
-            let jump_to = cpu.get_16_pc();
+            cursor.increment_pc();
+            let jump_to = cursor.get_16_pc();
             Instruction::JP(CondCode::NZ, jump_to)
         },
         0xD2 => {
-            cpu.increment_pc();
-            let jump_to = cpu.get_16_pc();
+            cursor.increment_pc();
+            let jump_to = cursor.get_16_pc();
             Instruction::JP(CondCode::NC, jump_to)
         },
         0xE2 => Instruction::LDW(RegCode::C, RegCode::A),
         0xF2 => Instruction::LDW(RegCode::A, RegCode::C),
         0xC3 => {
-            cpu.increment_pc();
-            let jump_to = cpu.get_16_pc();
+            cursor.increment_pc();
+            let jump_to = cursor.get_16_pc();
             Instruction::JP(CondCode::Always, jump_to)
         },
         0xF3 => Instruction::DI,
         0xC4 => {
-            cpu.increment_pc();
-            let function_at = cpu.get_16_pc();
+            cursor.increment_pc();
+            let function_at = cursor.get_16_pc();
             Instruction::CALL(CondCode::NZ, function_at)
         }
         0xD4 => {
-            cpu.increment_pc();
-            let function_at = cpu.get_16_pc();
+            cursor.increment_pc();
+            let function_at = cursor.get_16_pc();
             Instruction::CALL(CondCode::NC, function_at)
         },
         0xC5 => Instruction::PUSH(RegCode::BC),
@@ -548,23 +705,23 @@ pub fn fetch(cpu: &mut Cpu) -> Instruction {
         0xE5 => Instruction::PUSH(RegCode::HL),
         0xF5 => Instruction::PUSH(RegCode::AF),
         0xC6 => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte();
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte();
             Instruction::ADD8(RegCode::Const8(val))
         }
         0xD6 => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte();
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte();
             Instruction::SUB(RegCode::Const8(val))
         }
         0xE6 => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte();
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte();
             Instruction::AND(RegCode::Const8(val))
         }
         0xF6 => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte();
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte();
             Instruction::OR(RegCode::Const8(val))
         },
         0xC7 => Instruction::RST(0x00),
@@ -573,30 +730,38 @@ pub fn fetch(cpu: &mut Cpu) -> Instruction {
         0xF7 => Instruction::RST(0x30),
         0xC8 => Instruction::RET(CondCode::Z),
         0xD8 => Instruction::RET(CondCode::C),
-        0xE8 => todo!(),
-        0xF8 => todo!(),
+        0xE8 => {
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte() as i8;
+            Instruction::ADDSP(val)
+        },
+        0xF8 => {
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte() as i8;
+            Instruction::LDHLSP(val)
+        },
         0xC9 => Instruction::RET(CondCode::Always),
         0xD9 => Instruction::RETI,
         0xE9 => Instruction::JPHL,
-        0xF9 => todo!(),
+        0xF9 => Instruction::LDSPHL,
         0xCA => {
-            cpu.increment_pc();
-            let jump_to = cpu.get_16_pc();
+            cursor.increment_pc();
+            let jump_to = cursor.get_16_pc();
             Instruction::JP(CondCode::Z, jump_to)
         },
         0xDA => {
-            cpu.increment_pc();
-            let jump_to = cpu.get_16_pc();
+            cursor.increment_pc();
+            let jump_to = cursor.get_16_pc();
             Instruction::JP(CondCode::C, jump_to)
         },
         0xEA => {
-            cpu.increment_pc();
-            let code = RegCode::Const16(cpu.get_16_pc());
+            cursor.increment_pc();
+            let code = RegCode::Const16(cursor.get_16_pc());
             Instruction::LDW(code, RegCode::A)
         }
         0xFA => {
-            cpu.increment_pc();
-            let code = RegCode::Const16(cpu.get_16_pc());
+            cursor.increment_pc();
+            let code = RegCode::Const16(cursor.get_16_pc());
             Instruction::LDW(RegCode::A, code)
         },
         0xCB => {
@@ -605,38 +770,38 @@ pub fn fetch(cpu: &mut Cpu) -> Instruction {
         },
         0xFB => Instruction::EI,
         0xCC => {
-            cpu.increment_pc();
-            let function_at = cpu.get_16_pc();
+            cursor.increment_pc();
+            let function_at = cursor.get_16_pc();
             Instruction::CALL(CondCode::Z, function_at)
         },
         0xDC => {
-            cpu.increment_pc();
-            let function_at = cpu.get_16_pc();
+            cursor.increment_pc();
+            let function_at = cursor.get_16_pc();
             Instruction::CALL(CondCode::C, function_at)
         },
         0xCD => {
-            cpu.increment_pc();
-            let function_at = cpu.get_16_pc();
+            cursor.increment_pc();
+            let function_at = cursor.get_16_pc();
             Instruction::CALL(CondCode::Always, function_at)
         },
         0xCE => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte();
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte();
             Instruction::ADC(RegCode::Const8(val))
         },
         0xDE => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte();
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte();
             Instruction::SBC(RegCode::Const8(val))
         },
         0xEE => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte();
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte();
             Instruction::XOR(RegCode::Const8(val))
         },
         0xFE => {
-            cpu.increment_pc();
-            let val = cpu.current_pc_byte();
+            cursor.increment_pc();
+            let val = cursor.current_pc_byte();
             Instruction::CP(RegCode::Const8(val))
         },
         0xCF => Instruction::RST(0x08),
@@ -644,13 +809,13 @@ pub fn fetch(cpu: &mut Cpu) -> Instruction {
         0xEF => Instruction::RST(0x28),
         0xFF => Instruction::RST(0x38),
         
-        _ => panic!("Invalid Opcode: {nibble} | Instruction could not be fetched.")
+        _ => return None,
     };
 
-    cpu.increment_pc();
+    cursor.increment_pc();
 
     if prefixed {
-        instruction = match cpu.current_pc_byte() {
+        instruction = match cursor.current_pc_byte() {
             0x0 => Instruction::RLC(RegCode::B),
             0x1 => Instruction::RLC(RegCode::C),
             0x2 => Instruction::RLC(RegCode::D),
@@ -907,16 +1072,38 @@ pub fn fetch(cpu: &mut Cpu) -> Instruction {
             0xfd => Instruction::SET(7, RegCode::L),
             0xfe => Instruction::SET(7, RegCode::HL),
             0xff => Instruction::SET(7, RegCode::A),
-        }
+        };
+        // Past the second byte too, so a prefixed instruction is two long and
+        // fetch leaves pc on the next instruction rather than on this one's operand
+        cursor.increment_pc();
     }
 
-    instruction
+    let length = cursor.pc.wrapping_sub(addr);
+    let cycles = instruction.cycles(false);
+    let taken_cycles = instruction.cycles(true);
+    Some(Decoded {
+        instruction,
+        addr,
+        length,
+        cycles,
+        taken_cycles,
+    })
 }
 
-pub fn run(cpu: &mut Cpu, instruction: Instruction) {
+
+/*
+ *  Executes a fetched instruction
+ *  Returns the clock cycles it took
+ */
+pub fn run(cpu: &mut Cpu, instruction: Instruction) -> u32 {
+    let cycles = instruction.cycles(false);
+    let taken_cycles = instruction.cycles(true);
+    // Set by conditional jumps, calls and returns whose condition held
+    let mut taken = false;
+
     match instruction {
         Instruction::STOP => {
-            std::process::exit(0);
+            cpu.stop();
         },
         Instruction::NOP => {},
         Instruction::HALT => todo!(),
@@ -950,6 +1137,24 @@ pub fn run(cpu: &mut Cpu, instruction: Instruction) {
         Instruction::ADDSP(val) => {
             cpu.add_sp(val);
         },
+        Instruction::DAA => {
+            cpu.decimal_adjust();
+        },
+        Instruction::CPL => {
+            cpu.complement();
+        },
+        Instruction::SCF => {
+            cpu.set_carry(false);
+        },
+        Instruction::CCF => {
+            cpu.set_carry(true);
+        },
+        Instruction::LDHLSP(val) => {
+            cpu.load_hl_sp(val);
+        },
+        Instruction::LDSPHL => {
+            cpu.load_sp_hl();
+        },
         Instruction::LD8(target, source) => {
             cpu.load8(target, source);
         },
@@ -984,8 +1189,7 @@ pub fn run(cpu: &mut Cpu, instruction: Instruction) {
             cpu.rotate_left_carry_a();
         },
         Instruction::JR(condition, val) => {
-            println!("JR: {val}");
-            cpu.jump_relative(condition, val);
+            taken = cpu.jump_relative(condition, val);
         },
         Instruction::CP(target) => {
             cpu.cp(target);
@@ -1000,10 +1204,10 @@ pub fn run(cpu: &mut Cpu, instruction: Instruction) {
             cpu.restart(i);
         },
         Instruction::CALL(condition, i) => {
-            cpu.call(condition, i);
+            taken = cpu.call(condition, i);
         },
         Instruction::RET(condition) => {
-            cpu.ret(condition);
+            taken = cpu.ret(condition);
         },
         Instruction::LDW(target, source) => {
             cpu.load_weird(target, source);
@@ -1015,7 +1219,7 @@ pub fn run(cpu: &mut Cpu, instruction: Instruction) {
             cpu.ei();
         },
         Instruction::JP(condition, i) => {
-            cpu.jump(condition, i);
+            taken = cpu.jump(condition, i);
         },
         Instruction::RETI => {
             todo!()
@@ -1060,4 +1264,12 @@ pub fn run(cpu: &mut Cpu, instruction: Instruction) {
             panic!("Placeholder instruction ran.")
         }
     }
+
+    cpu.update_joypad();
+
+    if taken {
+        taken_cycles
+    } else {
+        cycles
+    }
 }
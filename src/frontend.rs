@@ -0,0 +1,113 @@
+use crate::gameboy::GameBoy;
+use crate::graphics::{Pixel, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+pub type Framebuffer = [[Pixel; SCREEN_WIDTH]; SCREEN_HEIGHT];
+
+/*
+ *  Frontends
+ *  What the core needs from whatever is showing it. A frontend can be one
+ *  type doing all three, or separate pieces, e.g. SDL's window and audio
+ *  queue. None of them are handed the cpu, so a frontend only sees what a
+ *  player would
+ */
+
+/// Somewhere to show finished frames
+pub trait VideoSink {
+    /// Called once per frame with the shades, mapping them to colors is up to the sink
+    fn present(&mut self, frame: &Framebuffer);
+}
+
+/// Somewhere to play sound
+pub trait AudioSink {
+    /// Interleaved stereo samples at gameboy::SAMPLE_RATE
+    fn queue(&mut self, samples: &[i16]);
+}
+
+/// Where the buttons come from
+pub trait InputSource {
+    /// Reads whatever input arrived since the last poll, once per frame
+    fn poll(&mut self);
+
+    /// Buttons held down, one bit each as in joypad
+    fn buttons(&self) -> u8;
+
+    /// True once the player asked to stop
+    fn quit(&self) -> bool {
+        false
+    }
+}
+
+/// All three in one, the usual shape for a frontend
+pub trait Frontend: VideoSink + AudioSink + InputSource {}
+
+impl<T: VideoSink + AudioSink + InputSource> Frontend for T {}
+
+/// Latches input, runs a frame and hands its picture and sound on
+pub fn run_frame(gameboy: &mut GameBoy, frontend: &mut impl Frontend) {
+    frontend.poll();
+    gameboy.set_buttons(frontend.buttons());
+    gameboy.run_frame();
+    frontend.present(gameboy.framebuffer());
+    frontend.queue(&gameboy.take_audio());
+}
+
+/*
+ *  Null Frontend
+ *  Shows and plays nothing, for tests and headless runs. Buttons come from
+ *  a script of one mask per frame, and once that runs out it asks to quit
+ */
+pub struct NullFrontend {
+    pub script: Vec<u8>,
+    position: usize,
+    pub frames: u64,
+    pub samples: usize,
+    pub last_frame: Option<Box<Framebuffer>>,
+}
+
+impl NullFrontend {
+
+    pub fn new(script: Vec<u8>) -> Self {
+        Self {
+            script,
+            position: 0,
+            frames: 0,
+            samples: 0,
+            last_frame: None,
+        }
+    }
+
+    /// Runs until the script is used up or the program stops
+    pub fn run(&mut self, gameboy: &mut GameBoy) {
+        while !self.quit() && !gameboy.stopped() {
+            run_frame(gameboy, self);
+        }
+    }
+
+}
+
+impl VideoSink for NullFrontend {
+    fn present(&mut self, frame: &Framebuffer) {
+        self.frames += 1;
+        self.last_frame = Some(Box::new(*frame));
+    }
+}
+
+impl AudioSink for NullFrontend {
+    fn queue(&mut self, samples: &[i16]) {
+        self.samples += samples.len();
+    }
+}
+
+impl InputSource for NullFrontend {
+    fn poll(&mut self) {
+        self.position += 1;
+    }
+
+    fn buttons(&self) -> u8 {
+        self.position.checked_sub(1).and_then(|i| self.script.get(i)).copied().unwrap_or(0)
+    }
+
+    fn quit(&self) -> bool {
+        self.position >= self.script.len()
+    }
+}
@@ -0,0 +1,154 @@
+use crate::cpu::Cpu;
+use crate::emulation::{fetch, run, CYCLES_PER_FRAME};
+use crate::frontend::Framebuffer;
+use crate::savestate::{self, StateError};
+use crate::speed::CLOCK_SPEED;
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const CHANNELS: u16 = 2;
+/// Roms are copied straight into the address space, there's no bank switching yet
+pub const MAX_ROM_SIZE: usize = 0x10000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CartridgeError {
+    TooLarge(usize),
+}
+
+impl std::fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CartridgeError::TooLarge(size) => write!(f, "The rom is {} bytes, at most {} fit without bank switching", size, MAX_ROM_SIZE),
+        }
+    }
+}
+
+/// Audio samples per channel from power on to the end of a frame
+pub fn samples_until(frames: u64) -> u64 {
+    frames * SAMPLE_RATE as u64 * CYCLES_PER_FRAME as u64 / CLOCK_SPEED as u64
+}
+
+/*
+ *  GameBoy
+ *  The emulator behind one type, for frontends and tools that embed it.
+ *  Cycles are counted across calls, so stepping an instruction at a time
+ *  and running whole frames can be mixed. There's no sound yet, so every
+ *  frame adds its length in silence to the audio buffer
+ */
+pub struct GameBoy {
+    /// Everything else, for debuggers and other tools that need the insides
+    pub cpu: Cpu,
+    rom: Vec<u8>,
+    frame_cycles: u32,
+    /// Frames finished since power on
+    pub frames: u64,
+    samples: Vec<i16>,
+}
+
+impl GameBoy {
+
+    /// Powers on with a cartridge
+    pub fn new(rom: &[u8]) -> Result<Self, CartridgeError> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(CartridgeError::TooLarge(rom.len()));
+        }
+        Ok(Self::power_on(rom.to_vec()))
+    }
+
+    fn power_on(rom: Vec<u8>) -> Self {
+        Self {
+            cpu: Cpu::new_with_rom(&rom),
+            rom,
+            frame_cycles: 0,
+            frames: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Swaps the cartridge and powers on again, a rom that doesn't fit leaves the old one in
+    pub fn load_cartridge(&mut self, rom: &[u8]) -> Result<(), CartridgeError> {
+        *self = Self::new(rom)?;
+        Ok(())
+    }
+
+    /// Powers on again with the same cartridge
+    pub fn reset(&mut self) {
+        let rom = std::mem::take(&mut self.rom);
+        *self = Self::power_on(rom);
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Runs one instruction, true if it finished a frame. Does nothing once stopped
+    pub fn step(&mut self) -> bool {
+        if self.stopped() {
+            return false;
+        }
+        let instruction = fetch(&mut self.cpu);
+        self.frame_cycles += run(&mut self.cpu, instruction);
+        if self.frame_cycles < CYCLES_PER_FRAME {
+            return false;
+        }
+        self.frame_cycles = 0;
+        self.frames += 1;
+        let silence = (samples_until(self.frames) - samples_until(self.frames - 1)) as usize * CHANNELS as usize;
+        self.samples.extend(std::iter::repeat_n(0, silence));
+        // Keep a second at most for frontends that never play it
+        let limit = (SAMPLE_RATE * CHANNELS as u32) as usize;
+        if self.samples.len() > limit {
+            self.samples.drain(..self.samples.len() - limit);
+        }
+        true
+    }
+
+    /// Runs to the end of the current frame, or until the program stops
+    pub fn run_frame(&mut self) {
+        while !self.step() && !self.stopped() {}
+    }
+
+    /// True once the program ran STOP, frontends should shut down then
+    pub fn stopped(&self) -> bool {
+        self.cpu.stopped()
+    }
+
+    /// Cycles run so far in the current frame
+    pub fn frame_cycles(&self) -> u32 {
+        self.frame_cycles
+    }
+
+    /// Forgets a partly run frame, e.g. after the state was swapped out
+    pub fn restart_frame(&mut self) {
+        self.frame_cycles = 0;
+    }
+
+    /// Buttons held down, one bit each as in joypad
+    pub fn set_buttons(&mut self, pressed: u8) {
+        self.cpu.joypad.pressed = pressed;
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.cpu.joypad.pressed
+    }
+
+    /// The last frame as shades, to be mapped through a palette
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.cpu.screen.pixels
+    }
+
+    /// Interleaved stereo samples made since the last call
+    pub fn take_audio(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        savestate::save(&self.cpu)
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        savestate::load(&mut self.cpu, data)?;
+        self.restart_frame();
+        Ok(())
+    }
+
+}
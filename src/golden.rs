@@ -0,0 +1,199 @@
+use std::fs;
+use std::path::Path;
+
+use crate::capture::{Frame, PngError};
+use crate::gameboy::{CartridgeError, GameBoy};
+use crate::palettes::{self, Palettes, PaletteError};
+
+pub const DEFAULT_FRAMES: u32 = 60;
+/// Plain grays, the same as most emulators' reference images
+pub const DEFAULT_PALETTE: &str = "gray";
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(std::io::Error),
+    Png(PngError),
+    Palette(PaletteError),
+    UnknownPalette(String),
+    WrongSize(usize, usize),
+    Mismatch(usize, String),
+    HashMismatch(u64),
+    Cartridge(CartridgeError),
+}
+
+impl std::fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GoldenError::Io(e) => write!(f, "{}", e),
+            GoldenError::Png(e) => write!(f, "{}", e),
+            GoldenError::Palette(e) => write!(f, "{}", e),
+            GoldenError::UnknownPalette(name) => write!(f, "No palette called {}", name),
+            GoldenError::WrongSize(width, height) => write!(f, "Golden image is {}x{}, not 160x144", width, height),
+            GoldenError::Mismatch(count, diff) => write!(f, "{} pixels differ, see {}", count, diff),
+            GoldenError::HashMismatch(hash) => write!(f, "Screen hash is {:016x}", hash),
+            GoldenError::Cartridge(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for GoldenError {
+    fn from(e: std::io::Error) -> Self {
+        GoldenError::Io(e)
+    }
+}
+
+impl From<PngError> for GoldenError {
+    fn from(e: PngError) -> Self {
+        GoldenError::Png(e)
+    }
+}
+
+impl From<CartridgeError> for GoldenError {
+    fn from(e: CartridgeError) -> Self {
+        GoldenError::Cartridge(e)
+    }
+}
+
+/// A 32KB rom with nothing in it but some code at the entry point, for tests
+pub fn test_rom(entry: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x100 + entry.len()].copy_from_slice(entry);
+    rom
+}
+
+/// Powers on with a rom and runs whole frames without any input
+pub fn run_frames(rom: &[u8], frames: u32) -> Result<GameBoy, CartridgeError> {
+    let mut gameboy = GameBoy::new(rom)?;
+    for _ in 0..frames {
+        gameboy.run_frame();
+    }
+    Ok(gameboy)
+}
+
+/*
+ *  Compares two frames of the same size. The diff image is a faded copy of
+ *  the expected frame with every pixel that doesn't match in red
+ */
+pub fn diff(actual: &Frame, expected: &Frame) -> (usize, Frame) {
+    let mut count = 0;
+    let pixels = actual.pixels.iter().zip(expected.pixels.iter())
+        .map(|(a, e)| {
+            if a == e {
+                e.map(|c| 0x80 + c / 2)
+            } else {
+                count += 1;
+                [0xFF, 0x00, 0x00]
+            }
+        })
+        .collect();
+    (count, Frame::new(expected.width, expected.height, pixels))
+}
+
+/*
+ *  Golden Test
+ *  Runs a rom headless and checks the screen against a stored png, a hash
+ *  of the shades, or both, e.g. for dmg-acid2:
+ *  JBoyEmulator golden dmg-acid2.gb --frames 60 --png dmg-acid2.png
+ *  On a mismatch a diff image is written next to the golden one. --update
+ *  writes the golden png instead of checking it. The check is only as good
+ *  as what's in cpu.screen, and until Gpu::redraw draws into it every run
+ *  ends on a blank screen, so goldens recorded before then match any rom
+ */
+pub struct GoldenTest {
+    pub rom_path: String,
+    pub frames: u32,
+    pub png: Option<String>,
+    pub hash: Option<u64>,
+    pub palette: String,
+    pub palettes: String,
+    pub diff: Option<String>,
+    pub update: bool,
+}
+
+impl GoldenTest {
+
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut test = Self {
+            rom_path: String::new(),
+            frames: DEFAULT_FRAMES,
+            png: None,
+            hash: None,
+            palette: String::from(DEFAULT_PALETTE),
+            palettes: String::from(palettes::DEFAULT_PATH),
+            diff: None,
+            update: false,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--frames" => {
+                    test.frames = args.next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--frames takes a number of frames");
+                },
+                "--png" => test.png = Some(args.next().expect("--png takes a golden image")),
+                "--hash" => {
+                    test.hash = Some(args.next()
+                        .and_then(|v| u64::from_str_radix(&v, 16).ok())
+                        .expect("--hash takes a screen hash in hex"));
+                },
+                "--palette" => test.palette = args.next().expect("--palette takes a palette name"),
+                "--palettes" => test.palettes = args.next().expect("--palettes takes a palette file"),
+                "--diff" => test.diff = Some(args.next().expect("--diff takes a file to write the diff image to")),
+                "--update" => test.update = true,
+                _ => test.rom_path = arg,
+            }
+        }
+        test
+    }
+
+    /// Where the diff image goes, roms/golden.png -> roms/golden-diff.png
+    fn diff_path(&self, png: &str) -> String {
+        self.diff.clone().unwrap_or_else(|| {
+            let stem = Path::new(png).with_extension("");
+            format!("{}-diff.png", stem.to_string_lossy())
+        })
+    }
+
+    pub fn run(&self) -> Result<(), GoldenError> {
+        let rom = fs::read(&self.rom_path)?;
+        let gameboy = run_frames(&rom, self.frames)?;
+        println!("Screen hash after {} frames: {:016x}", self.frames, gameboy.cpu.screen.hash());
+        self.check(&rom, &gameboy)
+    }
+
+    /// Checks the screen a rom was run to against the golden png and hash
+    pub fn check(&self, rom: &[u8], gameboy: &GameBoy) -> Result<(), GoldenError> {
+        let hash = gameboy.cpu.screen.hash();
+        if let Some(png) = &self.png {
+            let mut palettes = Palettes::new();
+            palettes.load(&self.palettes).map_err(GoldenError::Palette)?;
+            palettes.set_rom(rom);
+            if !palettes.select(&self.palette) {
+                return Err(GoldenError::UnknownPalette(self.palette.clone()));
+            }
+            let actual = Frame::from_pixels(gameboy.framebuffer(), palettes.current());
+
+            if self.update {
+                actual.save_png(png)?;
+                println!("Wrote {}", png);
+            } else {
+                let expected = Frame::from_png(&fs::read(png)?)?;
+                if (expected.width, expected.height) != (actual.width, actual.height) {
+                    return Err(GoldenError::WrongSize(expected.width, expected.height));
+                }
+                let (count, image) = diff(&actual, &expected);
+                if count > 0 {
+                    let path = self.diff_path(png);
+                    image.save_png(&path)?;
+                    return Err(GoldenError::Mismatch(count, path));
+                }
+            }
+        }
+
+        match self.hash {
+            Some(expected) if expected != hash => Err(GoldenError::HashMismatch(hash)),
+            _ => Ok(()),
+        }
+    }
+
+}
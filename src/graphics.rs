@@ -1,10 +1,47 @@
-use crate::sdl::SdlHandles;
-use crate::sdl::canvas::Pixel;
 use crate::cpu::Cpu;
+use crate::palettes::Palette;
 use crate::util::BitGrabber;
 
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Pixel {
+    LightestGreen,
+    LightGreen,
+    DarkGreen,
+    DarkestGreen,
+}
+
+impl Pixel {
+
+    /// The pixel for a shade from a palette, 0 being the lightest
+    pub fn from_shade(shade: u8) -> Self {
+        match shade & 0b11 {
+            0 => Pixel::LightestGreen,
+            1 => Pixel::LightGreen,
+            2 => Pixel::DarkGreen,
+            _ => Pixel::DarkestGreen,
+        }
+    }
+
+    /// The shade back as a number, 0 being the lightest
+    pub fn shade(self) -> usize {
+        match self {
+            Pixel::LightestGreen => 0,
+            Pixel::LightGreen => 1,
+            Pixel::DarkGreen => 2,
+            Pixel::DarkestGreen => 3,
+        }
+    }
+
+    pub fn color(self, palette: &Palette) -> [u8; 3] {
+        palette.color(self)
+    }
+
+}
+
 pub struct Gpu {
-    handle: SdlHandles,
     pixels: [[Pixel; 256]; 256],
     lcd: Lcd,
 }
@@ -20,6 +57,128 @@ pub struct Lcd {
     pub background_enabled: bool,
 }
 
+pub const LCDC: u16 = 0xFF40;
+pub const SCY: u16 = 0xFF42;
+pub const SCX: u16 = 0xFF43;
+pub const BGP: u16 = 0xFF47;
+pub const OBP0: u16 = 0xFF48;
+pub const OBP1: u16 = 0xFF49;
+pub const WY: u16 = 0xFF4A;
+pub const WX: u16 = 0xFF4B;
+
+pub const TILE_COUNT: usize = 384;
+pub const OAM_START: u16 = 0xFE00;
+pub const SPRITE_COUNT: usize = 40;
+
+/*
+ *  Tile
+ *  Decodes the 16 bytes of a tile into 8 rows of color ids. Each row is two
+ *  bytes, the first holding the low bit of every pixel and the second the
+ *  high bit, with the leftmost pixel in bit 7
+ */
+pub fn decode_tile(bytes: &[u8]) -> [[u8; 8]; 8] {
+    let mut rows = [[0; 8]; 8];
+    for (y, row) in rows.iter_mut().enumerate() {
+        let (low, high) = (bytes[y * 2], bytes[y * 2 + 1]);
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let bit = 7 - x;
+            *pixel = ((high >> bit) & 1) << 1 | ((low >> bit) & 1);
+        }
+    }
+    rows
+}
+
+/// Reads and decodes the tile starting at an address
+pub fn read_tile(cpu: &Cpu, addr: u16) -> [[u8; 8]; 8] {
+    let bytes: Vec<u8> = (0..16).map(|i| cpu.memory.peek(addr.wrapping_add(i))).collect();
+    decode_tile(&bytes)
+}
+
+/*
+ *  Screen
+ *  The finished frame as shades, kept with the rest of the machine so it
+ *  can be read without a window. Frontends map it through a palette
+ */
+pub struct Screen {
+    pub pixels: [[Pixel; SCREEN_WIDTH]; SCREEN_HEIGHT],
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self {
+            pixels: [[Pixel::LightestGreen; SCREEN_WIDTH]; SCREEN_HEIGHT],
+        }
+    }
+}
+
+impl Screen {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// FNV-1a hash of the shades, the same whatever palette it's shown with
+    pub fn hash(&self) -> u64 {
+        self.pixels.iter()
+            .flatten()
+            .fold(0xCBF29CE484222325, |hash, p| (hash ^ p.shade() as u64).wrapping_mul(0x100000001B3))
+    }
+
+}
+
+/// Shade a palette register gives a color id
+pub fn shade(palette: u8, color: u8) -> Pixel {
+    Pixel::from_shade((palette >> (color * 2)) & 0b11)
+}
+
+/*
+ *  Sprite
+ *  One of the 40 four byte entries in OAM. Positions are stored offset so
+ *  that 0 is just off screen, y by 16 and x by 8
+ */
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Sprite {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub flags: u8,
+}
+
+impl Sprite {
+
+    pub fn read(cpu: &Cpu, index: usize) -> Self {
+        let addr = OAM_START + index as u16 * 4;
+        Self {
+            y: cpu.memory.peek(addr),
+            x: cpu.memory.peek(addr + 1),
+            tile: cpu.memory.peek(addr + 2),
+            flags: cpu.memory.peek(addr + 3),
+        }
+    }
+
+    /// Drawn behind background colors 1-3
+    pub fn behind_background(&self) -> bool {
+        self.flags.nth_bit_as_bool(7)
+    }
+
+    pub fn y_flip(&self) -> bool {
+        self.flags.nth_bit_as_bool(6)
+    }
+
+    pub fn x_flip(&self) -> bool {
+        self.flags.nth_bit_as_bool(5)
+    }
+
+    /// Palette register the sprite is colored with
+    pub fn palette(&self) -> u16 {
+        if self.flags.nth_bit_as_bool(4) { OBP1 } else { OBP0 }
+    }
+
+    pub fn visible(&self, lcd: &Lcd) -> bool {
+        self.x > 0 && self.x < 168 && self.y as u16 + lcd.sprite_height() as u16 > 16 && self.y < 160
+    }
+
+}
 
 impl Lcd {
 
@@ -36,6 +195,13 @@ impl Lcd {
         }
     }
     
+    /// The lcd as LCDC currently sets it
+    pub fn from_memory(cpu: &Cpu) -> Self {
+        let mut lcd = Self::new();
+        lcd.update_with_byte(cpu.memory.peek(LCDC));
+        lcd
+    }
+
     pub fn update_with_byte(&mut self, byte: u8) {
         self.enabled = byte.nth_bit_as_bool(7);
         self.display_select = byte.nth_bit_as_bool(6);
@@ -47,13 +213,38 @@ impl Lcd {
         self.background_enabled = byte.nth_bit_as_bool(0);
     }
 
+    /*
+     *  Address of a background or window tile's data
+     *  Tiles 0-255 start at 0x8000, unless LCDC bit 4 is clear, in which case
+     *  the index is signed and tile 0 is at 0x9000
+     */
+    pub fn tile_address(&self, index: u8) -> u16 {
+        if self.tile_data {
+            0x8000 + index as u16 * 16
+        } else {
+            0x9000u16.wrapping_add((index as i8 as i16 * 16) as u16)
+        }
+    }
+
+    pub fn background_map(&self) -> u16 {
+        if self.background_select { 0x9C00 } else { 0x9800 }
+    }
+
+    pub fn window_map(&self) -> u16 {
+        if self.display_select { 0x9C00 } else { 0x9800 }
+    }
+
+    /// Height of every sprite, set by LCDC bit 2
+    pub fn sprite_height(&self) -> u8 {
+        if self.sprite_size { 16 } else { 8 }
+    }
+
 }
 
 impl Gpu {
 
-    pub fn new(handle: SdlHandles) -> Self {
+    pub fn new() -> Self {
         Self {
-            handle,
             pixels: [[Pixel::LightestGreen; 256]; 256],
             lcd: Lcd::new(),
         }
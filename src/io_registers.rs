@@ -0,0 +1,212 @@
+use crate::cpu::Cpu;
+
+/*
+ *  I/O Registers
+ *  Every hardware register at 0xFF00-0xFF7F plus IE, with each bit split into
+ *  named fields. The LCDC fields match what Lcd::update_with_byte reads
+ */
+
+/// How a field's value is shown
+pub enum Format {
+    /// A single bit, ON or OFF
+    Flag,
+    /// A number in decimal
+    Number,
+    /// One name per value
+    Names(&'static [&'static str]),
+}
+
+pub struct Field {
+    pub name: &'static str,
+    pub high: u8,
+    pub low: u8,
+    pub format: Format,
+}
+
+pub struct Register {
+    pub addr: u16,
+    pub name: &'static str,
+    pub fields: &'static [Field],
+}
+
+const fn flag(name: &'static str, bit: u8) -> Field {
+    Field { name, high: bit, low: bit, format: Format::Flag }
+}
+
+const fn number(name: &'static str, high: u8, low: u8) -> Field {
+    Field { name, high, low, format: Format::Number }
+}
+
+const fn named(name: &'static str, high: u8, low: u8, names: &'static [&'static str]) -> Field {
+    Field { name, high, low, format: Format::Names(names) }
+}
+
+const fn register(addr: u16, name: &'static str, fields: &'static [Field]) -> Register {
+    Register { addr, name, fields }
+}
+
+const INTERRUPTS: &[Field] = &[
+    flag("JOYPAD", 4),
+    flag("SERIAL", 3),
+    flag("TIMER", 2),
+    flag("STAT", 1),
+    flag("VBLANK", 0),
+];
+const DUTY: Field = named("DUTY", 7, 6, &["12.5%", "25%", "50%", "75%"]);
+const ENVELOPE: &[Field] = &[
+    number("VOLUME", 7, 4),
+    named("DIR", 3, 3, &["DOWN", "UP"]),
+    number("PACE", 2, 0),
+];
+const CONTROL: &[Field] = &[
+    flag("TRIGGER", 7),
+    flag("LENGTH", 6),
+    number("FREQ HI", 2, 0),
+];
+const PALETTE: &[Field] = &[
+    number("3", 7, 6),
+    number("2", 5, 4),
+    number("1", 3, 2),
+    number("0", 1, 0),
+];
+
+pub const REGISTERS: &[Register] = &[
+    register(0xFF00, "P1", &[
+        named("BUTTONS", 5, 5, &["SELECTED", "-"]),
+        named("DPAD", 4, 4, &["SELECTED", "-"]),
+        number("INPUT", 3, 0),
+    ]),
+    register(0xFF01, "SB", &[number("DATA", 7, 0)]),
+    register(0xFF02, "SC", &[
+        flag("TRANSFER", 7),
+        named("CLOCK", 0, 0, &["EXTERNAL", "INTERNAL"]),
+    ]),
+    register(0xFF04, "DIV", &[number("DIV", 7, 0)]),
+    register(0xFF05, "TIMA", &[number("COUNT", 7, 0)]),
+    register(0xFF06, "TMA", &[number("MODULO", 7, 0)]),
+    register(0xFF07, "TAC", &[
+        flag("ENABLE", 2),
+        named("CLOCK", 1, 0, &["4096HZ", "262144HZ", "65536HZ", "16384HZ"]),
+    ]),
+    register(0xFF0F, "IF", INTERRUPTS),
+    register(0xFF10, "NR10", &[
+        number("TIME", 6, 4),
+        named("DIR", 3, 3, &["UP", "DOWN"]),
+        number("SHIFT", 2, 0),
+    ]),
+    register(0xFF11, "NR11", &[DUTY, number("LENGTH", 5, 0)]),
+    register(0xFF12, "NR12", ENVELOPE),
+    register(0xFF13, "NR13", &[number("FREQ LO", 7, 0)]),
+    register(0xFF14, "NR14", CONTROL),
+    register(0xFF16, "NR21", &[DUTY, number("LENGTH", 5, 0)]),
+    register(0xFF17, "NR22", ENVELOPE),
+    register(0xFF18, "NR23", &[number("FREQ LO", 7, 0)]),
+    register(0xFF19, "NR24", CONTROL),
+    register(0xFF1A, "NR30", &[flag("DAC", 7)]),
+    register(0xFF1B, "NR31", &[number("LENGTH", 7, 0)]),
+    register(0xFF1C, "NR32", &[named("LEVEL", 6, 5, &["MUTE", "100%", "50%", "25%"])]),
+    register(0xFF1D, "NR33", &[number("FREQ LO", 7, 0)]),
+    register(0xFF1E, "NR34", CONTROL),
+    register(0xFF20, "NR41", &[number("LENGTH", 5, 0)]),
+    register(0xFF21, "NR42", ENVELOPE),
+    register(0xFF22, "NR43", &[
+        number("SHIFT", 7, 4),
+        named("WIDTH", 3, 3, &["15BIT", "7BIT"]),
+        number("DIVIDER", 2, 0),
+    ]),
+    register(0xFF23, "NR44", &[flag("TRIGGER", 7), flag("LENGTH", 6)]),
+    register(0xFF24, "NR50", &[
+        flag("VIN L", 7),
+        number("LEFT", 6, 4),
+        flag("VIN R", 3),
+        number("RIGHT", 2, 0),
+    ]),
+    register(0xFF25, "NR51", &[
+        flag("4L", 7),
+        flag("3L", 6),
+        flag("2L", 5),
+        flag("1L", 4),
+        flag("4R", 3),
+        flag("3R", 2),
+        flag("2R", 1),
+        flag("1R", 0),
+    ]),
+    register(0xFF26, "NR52", &[
+        flag("SOUND", 7),
+        flag("CH4", 3),
+        flag("CH3", 2),
+        flag("CH2", 1),
+        flag("CH1", 0),
+    ]),
+    register(0xFF40, "LCDC", &[
+        flag("LCD", 7),
+        named("WIN MAP", 6, 6, &["9800", "9C00"]),
+        flag("WIN", 5),
+        named("TILES", 4, 4, &["8800", "8000"]),
+        named("BG MAP", 3, 3, &["9800", "9C00"]),
+        named("OBJ SIZE", 2, 2, &["8X8", "8X16"]),
+        flag("OBJ", 1),
+        flag("BG", 0),
+    ]),
+    register(0xFF41, "STAT", &[
+        flag("LYC INT", 6),
+        flag("OAM INT", 5),
+        flag("VBLANK INT", 4),
+        flag("HBLANK INT", 3),
+        flag("LYC=LY", 2),
+        named("MODE", 1, 0, &["HBLANK", "VBLANK", "OAM", "DRAW"]),
+    ]),
+    register(0xFF42, "SCY", &[number("SCY", 7, 0)]),
+    register(0xFF43, "SCX", &[number("SCX", 7, 0)]),
+    register(0xFF44, "LY", &[number("LY", 7, 0)]),
+    register(0xFF45, "LYC", &[number("LYC", 7, 0)]),
+    register(0xFF46, "DMA", &[number("SOURCE HI", 7, 0)]),
+    register(0xFF47, "BGP", PALETTE),
+    register(0xFF48, "OBP0", PALETTE),
+    register(0xFF49, "OBP1", PALETTE),
+    register(0xFF4A, "WY", &[number("WY", 7, 0)]),
+    register(0xFF4B, "WX", &[number("WX", 7, 0)]),
+    register(0xFFFF, "IE", INTERRUPTS),
+];
+
+impl Field {
+
+    /// The field's bits shifted down
+    pub fn value(&self, byte: u8) -> u8 {
+        let width = self.high - self.low + 1;
+        let mask = if width == 8 { 0xFF } else { (1 << width) - 1 };
+        (byte >> self.low) & mask
+    }
+
+    pub fn format(&self, byte: u8) -> String {
+        let value = self.value(byte);
+        match self.format {
+            Format::Flag => String::from(if value != 0 { "ON" } else { "OFF" }),
+            Format::Number => value.to_string(),
+            Format::Names(names) => names.get(value as usize).map_or_else(|| value.to_string(), |n| n.to_string()),
+        }
+    }
+
+}
+
+impl Register {
+
+    /// Every field as "NAME=VALUE"
+    pub fn decode(&self, byte: u8) -> Vec<String> {
+        self.fields.iter()
+            .map(|field| format!("{}={}", field.name, field.format(byte)))
+            .collect()
+    }
+
+    /// e.g. "FF40 LCDC 91  LCD=ON WIN MAP=9800 ..."
+    pub fn describe(&self, cpu: &Cpu) -> String {
+        let byte = cpu.memory.peek(self.addr);
+        format!("{:04X} {:<4} {:02X}  {}", self.addr, self.name, byte, self.decode(byte).join("  "))
+    }
+
+}
+
+/// A register by name, ignoring case
+pub fn find(name: &str) -> Option<&'static Register> {
+    REGISTERS.iter().find(|r| r.name.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,53 @@
+use crate::savestate::{SaveState, StateReader, StateWriter, StateError};
+
+pub const RIGHT: u8 = 0b00000001;
+pub const LEFT: u8 = 0b00000010;
+pub const UP: u8 = 0b00000100;
+pub const DOWN: u8 = 0b00001000;
+pub const A: u8 = 0b00010000;
+pub const B: u8 = 0b00100000;
+pub const SELECT: u8 = 0b01000000;
+pub const START: u8 = 0b10000000;
+
+/*
+ *  Joypad
+ *  pressed holds one bit per button, high when the button is down
+ *  The game selects the direction or action group through bits 4 and 5
+ *  of P1 (0xFF00) and reads the group back active low in bits 0-3
+ */
+#[derive(Default)]
+pub struct Joypad {
+    pub pressed: u8,
+}
+
+impl Joypad {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, select: u8) -> u8 {
+        let mut low = 0x0F;
+        if select & 0x10 == 0 {
+            low &= !(self.pressed & 0x0F);
+        }
+        if select & 0x20 == 0 {
+            low &= !(self.pressed >> 4);
+        }
+        0xC0 | (select & 0x30) | low
+    }
+
+}
+
+impl SaveState for Joypad {
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.pressed);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.pressed = reader.read_u8()?;
+        Ok(())
+    }
+
+}
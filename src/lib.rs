@@ -0,0 +1,1218 @@
+/*
+ *  JBoy
+ *  The emulator as a library. GameBoy is the way in for frontends, which
+ *  plug into it through the traits in frontend. The modules under it are
+ *  public for tools that need to reach further in, like the debugger and
+ *  ram search. Nothing here opens a window
+ */
+pub mod cpu;
+mod util;
+pub mod emulation;
+pub mod graphics;
+pub mod palettes;
+pub mod savestate;
+pub mod rewind;
+pub mod joypad;
+pub mod movie;
+pub mod debugger;
+pub mod disasm;
+pub mod symbols;
+pub mod trace;
+pub mod io_registers;
+pub mod cheats;
+pub mod ram_search;
+pub mod speed;
+pub mod capture;
+pub mod golden;
+pub mod gameboy;
+pub mod frontend;
+
+pub use gameboy::GameBoy;
+
+#[cfg(test)]
+mod tests {
+    
+    use crate::cpu::{ Cpu, CondCode, RegCode };
+    use crate::savestate::{ self, StateError };
+    use crate::rewind::{ self, RewindBuffer };
+    use crate::movie::{ Movie, MoviePlayer, MovieError };
+    use crate::emulation::{ fetch, run, decode_bytes, Instruction };
+    use crate::disasm;
+    use crate::symbols::Symbols;
+    use crate::trace::{ self, DiffEvent, Tracer, TraceDiff };
+    use crate::graphics::Pixel;
+    use crate::cheats::{ self, CheatList, CheatError, Code };
+    use crate::speed::{ self, SpeedControl };
+    use crate::joypad;
+    use crate::debugger::{ self, Debugger };
+    use crate::debugger::gdb::GdbStub;
+    use crate::gameboy::{ self, CartridgeError, GameBoy };
+    use crate::frontend::{ self, NullFrontend, InputSource };
+    use crate::golden;
+    use std::io::{ Read, Write };
+    use std::net::TcpStream;
+
+    #[test]
+    fn load8_test() {
+        let mut cpu = Cpu::new();
+        
+        cpu.load8(RegCode::A, RegCode::Const8(8));
+        assert_eq!(cpu.registers.af.left, 8);
+
+        cpu.registers.bc.change_as_one(23);
+        cpu.load8(RegCode::BC, RegCode::A);
+        assert_eq!(cpu.memory[23], 8);
+    }
+    
+    #[test]
+    fn load16_test() {
+        let mut cpu = Cpu::new();
+        
+        cpu.load16(RegCode::BC, RegCode::Const16(8));
+        assert_eq!(cpu.registers.bc.take_as_one(), 8);
+
+        println!("{}", (17 & 0xFF00) >> 4);
+
+        cpu.load16(RegCode::Const16(8), RegCode::Const16(0b00010001));
+        assert_eq!(cpu.memory[8], 1);
+        assert_eq!(cpu.memory[9], 1);
+    }
+
+    #[test]
+    fn increment8_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.increment8(RegCode::A);
+        assert_eq!(cpu.registers.af.left, 1);
+
+        cpu.increment8(RegCode::HL);
+        assert_eq!(cpu.memory[0], 1);
+    }
+
+    #[test]
+    fn increment16_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.increment16(RegCode::BC);
+        assert_eq!(cpu.registers.bc.take_as_one(), 1);
+        
+        cpu.increment16(RegCode::SP);
+        assert_eq!(cpu.registers.sp, 0);
+    }
+
+    #[test]
+    fn decrement8_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.decrement8(RegCode::A);
+        assert_eq!(cpu.registers.af.left, u8::MAX);
+
+        cpu.decrement8(RegCode::HL);
+        assert_eq!(cpu.memory[0], 255);
+    }
+
+    #[test]
+    fn decrement16_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.decrement16(RegCode::BC);
+        assert_eq!(cpu.registers.bc.take_as_one(), u16::MAX);
+    }
+
+    #[test]
+    fn jump_relative_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.pc = 100;
+        cpu.jump_relative(CondCode::Always, -10);
+        assert_eq!(cpu.registers.pc, 90);
+        cpu.jump_relative(CondCode::Always, 10);
+        assert_eq!(cpu.registers.pc, 100);
+    }
+
+    #[test]
+    fn branch_cycles_test() {
+        // Branches that land where they'd have fallen through still take the long timing
+        let mut cpu = Cpu::new_with_rom(&[0x28, 0x00, 0xCA, 0x05, 0x00, 0xC4, 0x00, 0x00, 0xC8]);
+        cpu.registers.pc = 0;
+        cpu.registers.af.right = 0x80;
+        let cycles: Vec<u32> = (0..2).map(|_| {
+            let instruction = fetch(&mut cpu);
+            run(&mut cpu, instruction)
+        }).collect();
+        assert_eq!((cycles, cpu.registers.pc), (vec![12, 16], 5));
+
+        // CALL NZ and RET Z
+        let instruction = fetch(&mut cpu);
+        assert_eq!(run(&mut cpu, instruction), 12);
+        cpu.registers.af.right = 0;
+        cpu.registers.pc = 8;
+        let instruction = fetch(&mut cpu);
+        assert_eq!(run(&mut cpu, instruction), 8);
+        assert_eq!(cpu.registers.pc, 9);
+    }
+
+    #[test]
+    fn rotate_left_carry_a_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.af.left = 0b10000000;
+        cpu.rotate_left_carry_a();
+        assert!(cpu.registers.af.is_carry_high());
+
+        cpu.registers.af.left = 0b10101010;
+        cpu.rotate_left_carry_a();
+        assert!(cpu.registers.af.is_carry_high());
+        assert_eq!(cpu.registers.af.left, 0b01010101);
+    }
+
+    #[test]
+    fn rotate_right_carry_a_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.af.left = 0b00000001;
+        cpu.rotate_right_carry_a();
+        assert!(cpu.registers.af.is_carry_high());
+
+        cpu.registers.af.left = 0b10101010;
+        cpu.rotate_right_carry_a();
+        assert_eq!(cpu.registers.af.left, 0b11010101);
+    }
+
+    #[test]
+    fn rotate_right_a_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.af.left = 0b00000001;
+        cpu.rotate_right_a();
+        assert!(cpu.registers.af.is_carry_high());
+        assert_eq!(cpu.registers.af.left, 0b10000000);
+    }
+
+    #[test]
+    fn rotate_left_a_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.af.left = 0b10000000;
+        cpu.rotate_left_a();
+        assert!(cpu.registers.af.is_carry_high());
+        assert_eq!(cpu.registers.af.left, 0b00000001);
+    }
+
+    #[test]
+    fn add8_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.bc.left = 0b10001000;
+        cpu.registers.af.left = 0b10001000;
+        cpu.add8(RegCode::B);
+        assert!(cpu.registers.af.is_carry_high());
+        assert!(cpu.registers.af.is_hcarry_high());
+        assert_eq!(cpu.registers.af.left, 0b00010000);
+
+        cpu.registers.af.left = 0;
+        cpu.add8(RegCode::Const8(0));
+        assert!(cpu.registers.af.is_zero_high());
+    }
+
+    #[test]
+    fn add16_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.af.flip_zero_flag();
+        cpu.registers.hl.change_as_one(0b1000100000000000);
+        cpu.registers.de.change_as_one(0b1000100000000000);
+        cpu.add16(RegCode::DE);
+        
+        assert!(cpu.registers.af.is_zero_high());
+        assert!(cpu.registers.af.is_carry_high());
+        assert!(cpu.registers.af.is_hcarry_high());
+        assert_eq!(cpu.registers.hl.take_as_one(), 0b0001000000000000);
+    }
+
+    #[test]
+    fn sub_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.sub(RegCode::Const8(90));
+        
+        assert_eq!(cpu.registers.af.left, 166);
+        assert!(cpu.registers.af.is_carry_high());
+
+        cpu.sub(RegCode::Const8(166));
+
+        assert_eq!(cpu.registers.af.left, 0);
+        assert!(cpu.registers.af.is_zero_high());
+
+        cpu.registers.af.left = 0b00010000;
+        cpu.sub(RegCode::Const8(0b00001000));
+
+        assert!(cpu.registers.af.is_hcarry_high());
+    }
+
+    #[test]
+    fn and_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.af.left = 0b01010000;
+        cpu.and(RegCode::Const8(0b10100000));
+
+        assert!(cpu.registers.af.is_zero_high());
+        assert!(cpu.registers.af.is_hcarry_high());
+    }
+
+    #[test]
+    fn or_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.af.left = 0b01010000;
+        cpu.or(RegCode::Const8(0b01000000));
+
+        assert_eq!(cpu.registers.af.left, 0b01010000);
+
+        cpu.registers.af.left = 0;
+        cpu.or(RegCode::Const8(0));
+
+        assert!(cpu.registers.af.is_zero_high());
+    }
+
+    #[test]
+    fn xor_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.af.left = 0b01010000;
+        cpu.xor(RegCode::Const8(0b01000000));
+
+        assert_eq!(cpu.registers.af.left, 0b00010000);
+        cpu.xor(RegCode::Const8(0b00010000));
+        
+        assert!(cpu.registers.af.is_zero_high());
+    }
+
+    #[test]
+    fn addc_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.bc.left = 0b10001000;
+        cpu.registers.af.left = 0b10001000;
+        cpu.addc(RegCode::B);
+        assert!(cpu.registers.af.is_carry_high());
+        assert!(cpu.registers.af.is_hcarry_high());
+        assert_eq!(cpu.registers.af.left, 0b00010000);
+        
+        cpu.registers.af.flip_flags_down();
+        cpu.registers.af.left = 0;
+        cpu.addc(RegCode::Const8(0));
+        assert!(cpu.registers.af.is_zero_high());
+
+        cpu.registers.af.flip_carry_flag();
+        cpu.addc(RegCode::Const8(0));
+        assert_eq!(cpu.registers.af.left, 1);
+    }
+
+    #[test]
+    fn subc_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.subc(RegCode::Const8(90));
+        
+        assert_eq!(cpu.registers.af.left, 166);
+        assert!(cpu.registers.af.is_carry_high());
+        
+        cpu.registers.af.flip_flags_down();
+        cpu.subc(RegCode::Const8(166));
+
+        assert_eq!(cpu.registers.af.left, 0);
+        assert!(cpu.registers.af.is_zero_high());
+
+        cpu.registers.af.left = 0b00010000;
+        cpu.subc(RegCode::Const8(0b00001000));
+
+        assert!(cpu.registers.af.is_hcarry_high());
+        
+        cpu.registers.af.left = 90;
+        cpu.registers.af.flip_carry_flag();
+        cpu.subc(RegCode::Const8(89));
+        assert!(cpu.registers.af.is_zero_high());
+    }
+
+    #[test]
+    fn cp_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.cp(RegCode::Const8(90));
+        
+        assert_ne!(cpu.registers.af.left, 166);
+        assert!(cpu.registers.af.is_carry_high());
+        
+        cpu.registers.af.left = 166;
+        cpu.cp(RegCode::Const8(166));
+
+        assert_ne!(cpu.registers.af.left, 0);
+        assert!(cpu.registers.af.is_zero_high());
+
+        cpu.registers.af.left = 0b00010000;
+        cpu.cp(RegCode::Const8(0b00001000));
+
+        assert!(cpu.registers.af.is_hcarry_high());
+    }
+
+    #[test]
+    fn push_pop_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.bc.change_as_one(400);
+
+        cpu.push(RegCode::BC);
+        cpu.pop(RegCode::DE);
+
+        assert_eq!(cpu.registers.de.take_as_one(), 400);
+    }
+
+    #[test]
+    fn prefixed_instruction_test() {
+        let mut cpu = Cpu::new();
+        cpu.registers.pc = 0xC000;
+        cpu.registers.hl.change_as_one(0xD000);
+        cpu.registers.bc.left = 0b10000001;
+        cpu.memory[0xD000] = 0b00000001;
+        // RLC B, RLC (HL), NOP
+        for (i, byte) in [0xCB, 0x00, 0xCB, 0x06, 0x00].iter().enumerate() {
+            cpu.memory[0xC000 + i] = *byte;
+        }
+
+        let instruction = fetch(&mut cpu);
+        assert_eq!(cpu.registers.pc, 0xC002);
+        assert_eq!(run(&mut cpu, instruction), 8);
+        assert_eq!(cpu.registers.bc.left, 0b00000011);
+
+        let instruction = fetch(&mut cpu);
+        assert_eq!(cpu.registers.pc, 0xC004);
+        assert_eq!(run(&mut cpu, instruction), 16);
+        assert_eq!(cpu.memory[0xD000], 0b00000010);
+
+        assert!(matches!(fetch(&mut cpu), Instruction::NOP));
+        assert_eq!(cpu.registers.pc, 0xC005);
+    }
+
+    #[test]
+    fn decimal_adjust_test() {
+        let mut cpu = Cpu::new();
+
+        // 0x45 + 0x38 = 0x7D, which is 83 in bcd
+        cpu.registers.af.left = 0x45;
+        cpu.add8(RegCode::Const8(0x38));
+        assert_eq!(run(&mut cpu, Instruction::DAA), 4);
+        assert_eq!(cpu.registers.af.left, 0x83);
+        assert!(!cpu.registers.af.is_carry_high());
+
+        cpu.registers.af.left = 0x99;
+        cpu.add8(RegCode::Const8(0x01));
+        run(&mut cpu, Instruction::DAA);
+        assert_eq!(cpu.registers.af.left, 0x00);
+        assert!(cpu.registers.af.is_zero_high());
+        assert!(cpu.registers.af.is_carry_high());
+
+        // 0x42 - 0x09 = 0x39 with a half borrow, 33 in bcd
+        cpu.registers.af.left = 0x39;
+        cpu.registers.af.flip_flags_down();
+        cpu.registers.af.flip_subtract_flag();
+        cpu.registers.af.flip_hcarry_flag();
+        run(&mut cpu, Instruction::DAA);
+        assert_eq!(cpu.registers.af.left, 0x33);
+        assert!(cpu.registers.af.is_subtract_high());
+    }
+
+    #[test]
+    fn carry_flag_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.af.left = 0b10100101;
+        run(&mut cpu, Instruction::CPL);
+        assert_eq!(cpu.registers.af.left, 0b01011010);
+        assert!(cpu.registers.af.is_subtract_high() && cpu.registers.af.is_hcarry_high());
+
+        cpu.registers.af.flip_zero_flag();
+        run(&mut cpu, Instruction::SCF);
+        assert!(cpu.registers.af.is_carry_high() && cpu.registers.af.is_zero_high());
+        assert!(!cpu.registers.af.is_subtract_high() && !cpu.registers.af.is_hcarry_high());
+
+        run(&mut cpu, Instruction::CCF);
+        assert!(!cpu.registers.af.is_carry_high());
+        run(&mut cpu, Instruction::CCF);
+        assert!(cpu.registers.af.is_carry_high());
+        assert!(cpu.registers.af.is_zero_high());
+    }
+
+    #[test]
+    fn load_sp_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.sp = 0xFFF8;
+        assert_eq!(run(&mut cpu, Instruction::LDHLSP(2)), 12);
+        assert_eq!(cpu.registers.hl.take_as_one(), 0xFFFA);
+        assert!(!cpu.registers.af.is_carry_high() && !cpu.registers.af.is_hcarry_high());
+
+        run(&mut cpu, Instruction::LDHLSP(-1));
+        assert_eq!(cpu.registers.hl.take_as_one(), 0xFFF7);
+        assert!(cpu.registers.af.is_carry_high() && cpu.registers.af.is_hcarry_high());
+        assert!(!cpu.registers.af.is_zero_high());
+
+        cpu.registers.hl.change_as_one(0xC123);
+        assert_eq!(run(&mut cpu, Instruction::LDSPHL), 8);
+        assert_eq!(cpu.registers.sp, 0xC123);
+    }
+
+    #[test]
+    fn save_state_round_trip_test() {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.af.change_as_one(0x12B0);
+        cpu.registers.hl.change_as_one(0xC000);
+        cpu.registers.sp = 0xFFFE;
+        cpu.registers.pc = 0x0150;
+        cpu.memory[0xC000] = 42;
+        cpu.di();
+        cpu.halt();
+
+        let state = savestate::save(&cpu);
+        let restored = savestate::restore(&state).unwrap();
+
+        assert_eq!(restored.registers.af.take_as_one(), 0x12B0);
+        assert_eq!(restored.registers.hl.take_as_one(), 0xC000);
+        assert_eq!(restored.registers.sp, 0xFFFE);
+        assert_eq!(restored.registers.pc, 0x0150);
+        assert_eq!(restored.memory[0xC000], 42);
+        assert_eq!(savestate::save(&restored), state);
+    }
+
+    #[test]
+    fn save_state_rejects_bad_data_test() {
+        let mut cpu = Cpu::new();
+        cpu.registers.pc = 0x100;
+
+        let mut state = savestate::save(&cpu);
+        state[4] = savestate::STATE_VERSION + 1;
+        assert!(matches!(savestate::load(&mut cpu, &state), Err(StateError::UnsupportedVersion(_))));
+        assert!(matches!(savestate::load(&mut cpu, b"nope"), Err(StateError::BadMagic)));
+
+        state[4] = savestate::STATE_VERSION;
+        state.truncate(state.len() - 1);
+        assert!(matches!(savestate::load(&mut cpu, &state), Err(StateError::UnexpectedEnd)));
+        assert_eq!(cpu.registers.pc, 0x100);
+
+        // A good state replaces the machine but not the tools watching it
+        let good = savestate::save(&cpu);
+        cpu.registers.pc = 0x200;
+        cpu.memory.poke(0xC000, 7);
+        cpu.memory.watchpoints.push(crate::cpu::memory::Watchpoint { addr: 0xC000, read: true, write: false });
+        savestate::load(&mut cpu, &good).unwrap();
+        assert_eq!((cpu.registers.pc, cpu.memory.peek(0xC000)), (0x100, 0));
+        assert_eq!(cpu.memory.watchpoints.len(), 1);
+    }
+
+    #[test]
+    fn rewind_delta_test() {
+        let older = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let newer = vec![1, 2, 9, 4, 5, 0, 0, 8];
+
+        let delta = rewind::compress_delta(&older, &newer);
+        let mut state = newer.clone();
+        rewind::apply_delta(&mut state, &delta);
+        assert_eq!(state, older);
+        rewind::apply_delta(&mut state, &delta);
+        assert_eq!(state, newer);
+    }
+
+    #[test]
+    fn rewind_buffer_test() {
+        let mut buffer = RewindBuffer::new(1, usize::MAX);
+        let mut cpu = Cpu::new();
+
+        for pc in 0..5 {
+            cpu.registers.pc = pc;
+            buffer.record_frame(&cpu);
+        }
+
+        for pc in (1..5).rev() {
+            assert!(buffer.rewind_frame(&mut cpu));
+            assert_eq!(cpu.registers.pc, pc);
+        }
+        assert!(!buffer.rewind_frame(&mut cpu));
+        assert_eq!(cpu.registers.pc, 0);
+        assert!(!buffer.rewind_frame(&mut cpu));
+        assert_eq!(cpu.registers.pc, 0);
+    }
+
+    #[test]
+    fn rewind_capacity_test() {
+        let cpu = Cpu::new();
+        let state_size = savestate::save(&cpu).len();
+        let mut buffer = RewindBuffer::new(1, state_size + 64);
+        let mut cpu = Cpu::new();
+
+        for i in 0..100 {
+            cpu.memory[0xC000 + i] = 0xFF;
+            buffer.record_frame(&cpu);
+        }
+        assert!(buffer.used() <= state_size + 64);
+        assert!(buffer.rewind_frame(&mut cpu));
+        assert_eq!(cpu.memory[0xC000 + 99], 0xFF);
+    }
+
+    #[test]
+    fn joypad_register_test() {
+        let mut cpu = Cpu::new();
+        cpu.joypad.pressed = joypad::A | joypad::UP;
+
+        cpu.memory[0xFF00] = 0x20;
+        cpu.update_joypad();
+        assert_eq!(cpu.memory[0xFF00] & 0x0F, 0b1011);
+
+        cpu.memory[0xFF00] = 0x10;
+        cpu.update_joypad();
+        assert_eq!(cpu.memory[0xFF00] & 0x0F, 0b1110);
+    }
+
+    #[test]
+    fn movie_playback_test() {
+        // LD A,(FF00) / LD (HL+),A / JR -4
+        let rom = vec![0xF0, 0x00, 0x22, 0x18, 0xFB];
+        let run_movie = |movie: &Movie| {
+            let mut cpu = movie.begin(&rom).unwrap();
+            cpu.registers.hl.change_as_one(0xC000);
+            let mut player = MoviePlayer::new(Movie::from_bytes(&movie.to_bytes()).unwrap());
+            while let Some(input) = player.next_input() {
+                cpu.joypad.pressed = input;
+                for _ in 0..3 {
+                    let instruction = fetch(&mut cpu);
+                    run(&mut cpu, instruction);
+                }
+            }
+            savestate::save(&cpu)
+        };
+
+        let mut movie = Movie::from_power_on(&rom);
+        for frame in 0..20 {
+            movie.record_frame(if frame % 3 == 1 { joypad::START } else { 0 });
+        }
+
+        let mut idle = Movie::from_power_on(&rom);
+        for _ in 0..20 {
+            idle.record_frame(0);
+        }
+
+        assert_eq!(run_movie(&movie), run_movie(&movie));
+        assert_ne!(run_movie(&movie), run_movie(&idle));
+        assert!(matches!(movie.begin(&[0x00]), Err(MovieError::RomMismatch)));
+    }
+
+    #[test]
+    fn debugger_breakpoint_test() {
+        // NOP / NOP / CALL 0x0010 ... 0x0010: RET
+        let mut rom = vec![0x00, 0x00, 0xCD, 0x10, 0x00];
+        rom.resize(0x10, 0x00);
+        rom.push(0xC9);
+        let mut cpu = Cpu::new_with_rom(&rom);
+        cpu.registers.pc = 0;
+        let mut debugger = Debugger::new(false);
+
+        let run_until_stop = |cpu: &mut Cpu, debugger: &mut Debugger| {
+            for _ in 0..100 {
+                if debugger.should_stop(cpu) {
+                    return;
+                }
+                let instruction = fetch(cpu);
+                run(cpu, instruction);
+                debugger.after_instruction(cpu);
+            }
+            panic!("Debugger never stopped");
+        };
+
+        debugger.execute("b 2", &mut cpu);
+        run_until_stop(&mut cpu, &mut debugger);
+        assert_eq!(cpu.registers.pc, 2);
+
+        debugger.execute("n", &mut cpu);
+        run_until_stop(&mut cpu, &mut debugger);
+        assert_eq!(cpu.registers.pc, 5);
+
+        debugger.execute("set a 42", &mut cpu);
+        debugger.execute("flag c 1", &mut cpu);
+        assert_eq!(cpu.registers.af.left, 0x42);
+        assert!(cpu.registers.af.is_carry_high());
+
+        debugger.execute("watch c000 w", &mut cpu);
+        cpu.memory[0xC000] = 1;
+        debugger.after_instruction(&mut cpu);
+        assert!(debugger.paused);
+        assert_eq!(debugger::parse_number("0x1F"), Some(0x1F));
+    }
+
+    /// Sends a packet (if any) to the stub and waits for the next reply
+    fn gdb_exchange(client: &mut TcpStream, packet: Option<&str>, cpu: &mut Cpu, debugger: &mut Debugger) -> String {
+        if let Some(packet) = packet {
+            let sum = packet.bytes().fold(0u8, |s, b| s.wrapping_add(b));
+            client.write_all(format!("${}#{:02x}", packet, sum).as_bytes()).unwrap();
+        }
+        let mut reply = Vec::new();
+        for _ in 0..100 {
+            debugger.poll(cpu);
+            let mut byte = [0];
+            while !reply.ends_with(b"#") && client.read(&mut byte).is_ok() {
+                if byte[0] == b'$' || !reply.is_empty() {
+                    reply.push(byte[0]);
+                }
+            }
+            if reply.ends_with(b"#") {
+                let mut sum = [0; 2];
+                client.read_exact(&mut sum).unwrap();
+                return String::from_utf8_lossy(&reply[1..reply.len() - 1]).into_owned();
+            }
+        }
+        panic!("No reply to {:?}", packet);
+    }
+
+    #[test]
+    fn gdb_stub_test() {
+        // NOP / NOP / NOP / NOP
+        let mut cpu = Cpu::new_with_rom(&[0x00; 0x10]);
+        cpu.registers.pc = 0;
+        cpu.registers.bc.change_as_one(0x1234);
+        let mut debugger = Debugger::new(false);
+        debugger.gdb = Some(GdbStub::listen(0).unwrap());
+        let port = debugger.gdb.as_ref().unwrap().port();
+
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        client.set_read_timeout(Some(std::time::Duration::from_millis(10))).unwrap();
+        let client = &mut client;
+
+        assert_eq!(gdb_exchange(client, Some("?"), &mut cpu, &mut debugger), "S05");
+        assert!(debugger.paused);
+        assert_eq!(gdb_exchange(client, Some("p1"), &mut cpu, &mut debugger), "3412");
+        assert_eq!(gdb_exchange(client, Some("Mc000,2:abcd"), &mut cpu, &mut debugger), "OK");
+        assert_eq!(gdb_exchange(client, Some("mc000,2"), &mut cpu, &mut debugger), "abcd");
+        let xml = gdb_exchange(client, Some("qXfer:features:read:target.xml:0,1000"), &mut cpu, &mut debugger);
+        assert!(xml.starts_with("l<?xml"));
+        assert_eq!(gdb_exchange(client, Some("Z0,2,1"), &mut cpu, &mut debugger), "OK");
+        assert_eq!(debugger.breakpoints, vec![2]);
+
+        // Continue only answers once the breakpoint is hit
+        client.write_all(b"$c#63").unwrap();
+        for _ in 0..10 {
+            debugger.poll(&mut cpu);
+        }
+        assert!(!debugger.paused);
+        for _ in 0..10 {
+            if debugger.should_stop(&mut cpu) {
+                break;
+            }
+            let instruction = fetch(&mut cpu);
+            run(&mut cpu, instruction);
+        }
+        assert_eq!(cpu.registers.pc, 2);
+        assert_eq!(gdb_exchange(client, None, &mut cpu, &mut debugger), "S05");
+        assert_eq!(gdb_exchange(client, Some("g"), &mut cpu, &mut debugger), "b0013412d8004d01feff0200");
+    }
+
+    #[test]
+    fn disassembler_test() {
+        let decoded = decode_bytes(&[0xFA, 0x34, 0x12], 0x150).unwrap();
+        assert_eq!(decoded.length, 3);
+        assert_eq!(decoded.cycles, 16);
+        assert_eq!(decoded.text(), "LD A,($1234)");
+        assert_eq!(decode_bytes(&[0xCB, 0x7E], 0).unwrap().text(), "BIT 7,(HL)");
+        assert_eq!(decode_bytes(&[0xE0, 0x40], 0).unwrap().text(), "LDH ($FF40),A");
+        assert!(matches!(decode_bytes(&[0xE8, 0xFE], 0).unwrap().instruction, Instruction::ADDSP(-2)));
+        assert!(decode_bytes(&[0xC3, 0x00], 0).is_none());
+        assert!(decode_bytes(&[0xDD], 0).is_none());
+
+        // Prefixed instructions move pc past both bytes
+        let mut cpu = Cpu::new_with_rom(&[0xCB, 0x37, 0x00]);
+        cpu.registers.pc = 0;
+        fetch(&mut cpu);
+        assert_eq!(cpu.registers.pc, 2);
+
+        // bank 0: JR -2 to itself, bank 1: CALL into itself and JP into bank 0
+        let mut rom = vec![0x18, 0xFE];
+        rom.resize(0x4000, 0x00);
+        rom.extend_from_slice(&[0xCD, 0x03, 0x40, 0xC3, 0x00, 0x00]);
+        let text = disasm::disassemble(&rom, &Symbols::new());
+        assert!(text.contains("L00_0000:\n00:0000  18 FE     JR L00_0000          ; 12\n"));
+        assert!(text.contains("01:4000  CD 03 40  CALL L01_4003        ; 24\n"));
+        assert!(text.contains("L01_4003:\n01:4003  C3 00 00  JP L00_0000          ; 16\n"));
+    }
+
+    #[test]
+    fn symbols_test() {
+        let symbols = Symbols::parse("; File generated by rgblink\n00:0000 Start\n00:0010 Func\n01:4000 Far\n00:c000 wCounter\n");
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.visible_address("Func"), Some(0x10));
+        assert_eq!(symbols.format(0xC000), "wCounter");
+        assert_eq!(symbols.describe(0x12), "$0012 <Func+2>");
+
+        // Start: CALL Func ... Func: NOP / RET
+        let mut rom = vec![0xCD, 0x10, 0x00];
+        rom.resize(0x10, 0x00);
+        rom.extend_from_slice(&[0x00, 0xC9]);
+        let mut cpu = Cpu::new_with_rom(&rom);
+        cpu.registers.pc = 0;
+        let mut debugger = Debugger::new(false);
+        debugger.symbols = symbols.clone();
+
+        debugger.execute("b Func", &mut cpu);
+        assert_eq!(debugger.breakpoints, vec![0x10]);
+        for _ in 0..10 {
+            if debugger.should_stop(&mut cpu) {
+                break;
+            }
+            let instruction = fetch(&mut cpu);
+            run(&mut cpu, instruction);
+            debugger.after_instruction(&mut cpu);
+        }
+        assert_eq!(cpu.registers.pc, 0x10);
+        assert_eq!(debugger.backtrace(&cpu), vec!["#0 $0010 <Func>", "#1 $0010 <Func> called from $0000 <Start>"]);
+
+        debugger.execute("finish", &mut cpu);
+        for _ in 0..10 {
+            if debugger.should_stop(&mut cpu) {
+                break;
+            }
+            let instruction = fetch(&mut cpu);
+            run(&mut cpu, instruction);
+            debugger.after_instruction(&mut cpu);
+        }
+        assert_eq!(cpu.registers.pc, 3);
+        assert_eq!(debugger.backtrace(&cpu).len(), 1);
+
+        let text = disasm::disassemble(&rom, &symbols);
+        assert!(text.contains("Start:\n00:0000  CD 10 00  CALL Func"));
+    }
+
+    #[test]
+    fn trace_test() {
+        // LD A,0x12 / NOP
+        let mut cpu = Cpu::new_with_rom(&[0x3E, 0x12, 0x00]);
+        cpu.registers.pc = 0;
+        assert_eq!(trace::doctor_line(&cpu), "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0000 PCMEM:3E,12,00,00");
+
+        let path = std::env::temp_dir().join("jboy_trace_test.log");
+        let path = path.to_str().unwrap();
+        cpu.trace = Tracer::to_file(path).unwrap();
+        cpu.trace.range = trace::parse_range("1:ff");
+        for _ in 0..2 {
+            let instruction = fetch(&mut cpu);
+            run(&mut cpu, instruction);
+        }
+        cpu.trace.flush();
+
+        let log = std::fs::read_to_string(path).unwrap();
+        assert_eq!(log, "A:12 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0002 PCMEM:00,00,00,00\n");
+        std::fs::remove_file(path).unwrap();
+
+        // A failed write turns the trace off and leaves the error for the frontend
+        struct Full;
+        impl std::io::Write for Full {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::StorageFull.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        cpu.trace = Tracer::to_writer(Box::new(Full));
+        cpu.trace.write_line("A:00");
+        assert!(!cpu.trace.enabled);
+        assert_eq!(cpu.trace.take_error().map(|e| e.kind()), Some(std::io::ErrorKind::StorageFull));
+        assert!(cpu.trace.take_error().is_none());
+        assert!(cpu.trace.toggle());
+        assert!(!cpu.trace.toggle());
+    }
+
+    #[test]
+    fn trace_diff_test() {
+        // LD A,0x12 / INC A / NOP / NOP
+        let rom = [0x3E, 0x12, 0x3C, 0x00, 0x00];
+        let mut cpu = Cpu::new_with_rom(&rom);
+        cpu.registers.pc = 0;
+        let mut reference = String::new();
+        for _ in 0..3 {
+            reference.push_str(&trace::doctor_line(&cpu));
+            reference.push('\n');
+            let instruction = fetch(&mut cpu);
+            run(&mut cpu, instruction);
+        }
+        // Pretend the reference had a different flag result for INC A
+        let reference = reference.replacen("A:13 F:10", "A:13 F:00", 1);
+
+        let mut cpu = Cpu::new_with_rom(&rom);
+        cpu.registers.pc = 0;
+        let symbols = Symbols::new();
+        let mut diff = TraceDiff::new(Box::new(std::io::Cursor::new(reference.into_bytes())), 4);
+        let mut report = None;
+        for _ in 0..3 {
+            report = diff.check(&cpu, &symbols);
+            if report.is_some() {
+                break;
+            }
+            let instruction = fetch(&mut cpu);
+            run(&mut cpu, instruction);
+        }
+
+        let report = match report {
+            Some(DiffEvent::Diverged(report)) => report,
+            _ => panic!("Trace should have diverged"),
+        };
+        assert_eq!(cpu.registers.pc, 3);
+        assert!(report.starts_with("Trace diverged at line 3\n"));
+        assert!(report.contains("  F expected 00 got 10\n"));
+        assert!(report.contains("PC:0002 PCMEM:3C,00,00,00  INC A\n"));
+        assert!(report.contains("=> $0003: NOP\n"));
+        assert!(diff.check(&cpu, &symbols).is_none());
+
+        // A reference that runs out says how far it got, once
+        let mut diff = TraceDiff::new(Box::new(std::io::Cursor::new(trace::doctor_line(&cpu).into_bytes())), 4);
+        assert!(diff.check(&cpu, &symbols).is_none());
+        assert_eq!(diff.check(&cpu, &symbols), Some(DiffEvent::Ended(1)));
+        assert!(diff.check(&cpu, &symbols).is_none());
+    }
+
+    #[test]
+    fn cheats_test() {
+        assert_eq!(cheats::parse_code("01FF34C1"), Ok(Code::GameShark { value: 0xFF, addr: 0xC134 }));
+        assert_eq!(cheats::parse_code("3E1-23B-E0A"), Ok(Code::GameGenie { addr: 0x4123, value: 0x3E, compare: Some(0x00) }));
+        assert_eq!(cheats::parse_code("3E1-23B"), Ok(Code::GameGenie { addr: 0x4123, value: 0x3E, compare: None }));
+        assert_eq!(cheats::parse_code("3E1-234"), Err(CheatError::InvalidCode(String::from("3E1-234"))));
+        assert!(cheats::parse_code("01FF34CZ").is_err());
+        assert_eq!(cheats::cheat_path("roms/game.gb"), "roms/game.cht");
+
+        let mut list = CheatList::parse("; lives\n01FF34C1 Infinite lives\n3E1-23B-E0A+3E1-24B Patch\n\n").unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.cheats[1].name, "Patch");
+        assert_eq!(list.cheats[1].codes.len(), 2);
+        assert!(CheatList::parse("nonsense").is_err());
+
+        let mut cpu = Cpu::new();
+        list.apply(&mut cpu.memory);
+        assert_eq!(cpu.memory.peek(0xC134), 0xFF);
+        assert_eq!(cpu.memory[0x4123], 0x3E);
+        assert_eq!(cpu.memory.peek(0x4124), 0x3E);
+
+        // The compare byte keeps the patch off when the rom holds something else
+        cpu.memory.poke(0x4123, 0x01);
+        assert_eq!(cpu.memory[0x4123], 0x01);
+
+        list.set_enabled(1, false).unwrap();
+        assert_eq!(list.set_enabled(5, false), Err(CheatError::NoSuchCheat(5)));
+        list.apply(&mut cpu.memory);
+        assert_eq!(cpu.memory.peek(0x4124), 0x00);
+
+        assert!(!list.toggle());
+        cpu.memory.poke(0xC134, 0x02);
+        list.apply(&mut cpu.memory);
+        assert_eq!(cpu.memory.peek(0xC134), 0x02);
+        assert_eq!(list.describe()[0], "0 on  01FF34C1 Infinite lives");
+    }
+
+    #[test]
+    fn speed_test() {
+        let mut speed = SpeedControl::new();
+        assert_eq!(speed.speed(), 1.0);
+        assert!(speed.toggle_slow_motion());
+        assert_eq!(speed.speed(), speed::DEFAULT_SLOW_MOTION);
+        speed.fast_forward = true;
+        speed.fast_forward_speed = 4.0;
+        assert_eq!(speed.speed(), 4.0);
+        assert!((speed::frame_duration().as_secs_f64() - 1.0 / 59.7275).abs() < 0.00001);
+
+        // Frame advance runs exactly one frame per press
+        assert!(speed.start_frame());
+        speed.advance();
+        assert!(speed.paused);
+        assert!(speed.start_frame());
+        assert!(!speed.start_frame());
+        assert!(!speed.toggle_pause());
+        assert!(speed.start_frame() && speed.start_frame());
+
+        // 10 presses a second at 60 frames is 3 frames down, 3 frames up
+        assert!(speed.toggle_autofire(joypad::A));
+        let held = joypad::A | joypad::B;
+        let mut pressed = Vec::new();
+        for _ in 0..6 {
+            pressed.push(speed.apply_autofire(held));
+            speed.end_frame();
+        }
+        assert_eq!(pressed, [held, held, held, joypad::B, joypad::B, joypad::B]);
+        assert!(!speed.toggle_autofire(joypad::A));
+        assert_eq!(speed.apply_autofire(held), held);
+
+        // Sound keeps pace by dropping or repeating whole stereo samples
+        let samples = [1, -1, 2, -2, 3, -3, 4, -4];
+        assert_eq!(speed::resample(&samples, 1.0), samples);
+        assert_eq!(speed::resample(&samples, 2.0), [1, -1, 3, -3]);
+        assert_eq!(speed::resample(&samples, 0.5), [1, -1, 1, -1, 2, -2, 2, -2, 3, -3, 3, -3, 4, -4, 4, -4]);
+        assert!(speed::resample(&samples, 0.0).is_empty());
+    }
+
+    #[test]
+    fn palettes_test() {
+        use crate::palettes::{self, Palettes, PaletteError};
+
+        let green = palettes::Palette::green();
+        assert_eq!(Pixel::LightestGreen.color(&green), [0x9B, 0xBC, 0x0F]);
+        assert_eq!(Pixel::from_shade(2).shade(), 2);
+
+        let parsed = Palettes::parse("; mine\nmint = e0f8d0 88c070 346856 #081820\n").unwrap();
+        assert_eq!(parsed[0].name, "mint");
+        assert_eq!(parsed[0].color(Pixel::DarkestGreen), [0x08, 0x18, 0x20]);
+        assert_eq!(parsed[0].sprites[1], parsed[0].background);
+        let twelve = format!("split = {} {} {}", "ffffff ".repeat(4), "ff0000 ".repeat(4), "0000ff ".repeat(4));
+        assert_eq!(Palettes::parse(&twelve).unwrap()[0].sprites[1][0], [0, 0, 0xFF]);
+        assert_eq!(Palettes::parse("bad = ffffff 000000"), Err(PaletteError::InvalidLine(1)));
+        assert_eq!(Palettes::parse("bad = ffffff 000000 12345 000000"), Err(PaletteError::InvalidColor(String::from("12345"))));
+
+        // Title checksums only count for Nintendo games
+        let mut rom = vec![0; 0x150];
+        rom[0x134..0x134 + 11].copy_from_slice(b"POKEMON RED");
+        assert_eq!(palettes::title_checksum(&rom), None);
+        rom[0x14B] = 0x33;
+        rom[0x144..0x146].copy_from_slice(b"01");
+        assert_eq!(palettes::title_checksum(&rom), Some(0x14));
+        assert_eq!(palettes::title_palette(&rom).background[1], [0xFF, 0x84, 0x84]);
+        assert_eq!(palettes::title_palette(&rom).sprites[0][1], [0x7B, 0xFF, 0x31]);
+
+        // Titles that share a checksum are told apart by their fourth letter
+        let mut shared = rom.clone();
+        shared[0x134..0x134 + 12].copy_from_slice(b"VEGAS STAKES");
+        assert_eq!(palettes::title_checksum(&shared), Some(0x61));
+        assert_eq!(palettes::title_palette(&shared).background[1], [0x7B, 0xFF, 0x31]);
+        shared[0x134..0x134 + 12].copy_from_slice(b"POKEMON BLUE");
+        assert_eq!(palettes::title_checksum(&shared), Some(0x61));
+        assert_eq!(palettes::title_palette(&shared).background[1], [0x63, 0xA5, 0xFF]);
+        shared[0x134..0x134 + 12].copy_from_slice(b"POKMEON BLUE");
+        assert_eq!(palettes::title_palette(&shared).background[1], [0x7B, 0xFF, 0x31]);
+        assert_eq!(palettes::title_palette(&shared).background[2], [0x00, 0x63, 0xC6]);
+
+        rom[0x134] = b'V';
+        assert_eq!(palettes::title_palette(&rom).background[1], [0x7B, 0xFF, 0x31]);
+
+        let mut list = Palettes::new();
+        list.list.extend(parsed);
+        list.set_rom(&rom);
+        assert!(list.select(palettes::AUTO));
+        assert_eq!(list.current().background[2], [0x00, 0x63, 0xC6]);
+        assert_eq!(list.cycle(), "mint");
+        assert_eq!(list.current().name, "mint");
+        assert_eq!(list.cycle(), "green");
+        assert!(!list.select("nope"));
+    }
+
+    #[test]
+    fn capture_test() {
+        use crate::capture::{self, Frame, Recorder};
+
+        assert_eq!(capture::crc32(b"IEND"), 0xAE426082);
+        assert_eq!(capture::adler32(b"Wikipedia"), 0x11E60398);
+
+        // The 10x10 example image from the gif spec walkthroughs
+        let rows = [
+            "1111122222", "1111122222", "1111122222", "1110000222", "1110000222",
+            "2220000111", "2220000111", "2222211111", "2222211111", "2222211111",
+        ];
+        let indices: Vec<u8> = rows.concat().bytes().map(|b| b - b'0').collect();
+        assert_eq!(capture::lzw(&indices, 2), vec![
+            0x8C, 0x2D, 0x99, 0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75,
+            0xEC, 0x95, 0xFA, 0xA8, 0xDE, 0x60, 0x8C, 0x04, 0x91, 0x4C, 0x01,
+        ]);
+
+        let frame = Frame::new(2, 1, vec![[1, 2, 3], [4, 5, 6]]);
+        let scaled = frame.scale(2);
+        assert_eq!((scaled.width, scaled.height), (4, 2));
+        assert_eq!(scaled.pixels[4..], [[1, 2, 3], [1, 2, 3], [4, 5, 6], [4, 5, 6]]);
+
+        let png = frame.to_png();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 2, 0, 0, 0, 1]);
+        assert_eq!(&png[png.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+
+        let pixels = [[Pixel::DarkestGreen; 160]; 144];
+        let screen = Frame::from_pixels(&pixels, &crate::palettes::Palette::green());
+        assert_eq!(screen.pixels[0], [0x0F, 0x38, 0x0F]);
+
+        let dir = std::env::temp_dir();
+        let gif_path = dir.join("jboy_capture_test.gif").to_string_lossy().into_owned();
+        let mut recorder = Recorder::start(&gif_path, 2, 1).unwrap();
+        for i in 0..60 {
+            let shade = if i < 30 { 0 } else { 0xFF };
+            recorder.add_frame(&Frame::new(2, 1, vec![[shade; 3], [shade; 3]]), &[0; 1470]).unwrap();
+        }
+        let wav_path = recorder.audio_path.clone();
+        recorder.finish().unwrap();
+
+        let gif = std::fs::read(&gif_path).unwrap();
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(gif.last(), Some(&0x3B));
+        // Two frames, since each color holds for half a second
+        assert_eq!(gif.windows(3).filter(|w| *w == [0x21, 0xF9, 0x04]).count(), 2);
+
+        let wav = std::fs::read(&wav_path).unwrap();
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(wav.len(), capture::wav_header(0).len() + 60 * 1470 * 2);
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize, wav.len() - 44);
+        let _ = std::fs::remove_file(gif_path);
+        let _ = std::fs::remove_file(wav_path);
+    }
+
+    #[test]
+    fn golden_test() {
+        use crate::capture::{self, Frame, PngError};
+        use crate::graphics::Screen;
+
+        let hex = |s: &str| (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect::<Vec<u8>>();
+
+        // Fixed and dynamic huffman blocks, compressed by zlib
+        let fixed = hex("78dacb48cdc9c957c8c020cbf38b725200a38a0af9");
+        assert_eq!(capture::inflate(&fixed[2..]).unwrap(), b"hello hello hello hello world");
+        let dynamic = hex("78da25cb410ac0200c04c0afecd7520d12581b89a1d2df2b781d98265d1ffff185742c7bab2fcc11960a960a3f3a4b388921d43c9c4645bb6d03e2ce176a");
+        let words: Vec<&str> = "gameboy lcd tile sprite palette window scroll vram oam".split(' ').collect();
+        let text: Vec<&str> = (0..10).map(|i| words[(i * 7) % 9]).collect();
+        assert_eq!(capture::inflate(&dynamic[2..]).unwrap(), text.join(" ").as_bytes());
+
+        // A 2 bit paletted png using the sub and paeth filters
+        let png = hex("89504e470d0a1a0a0000000d494844520000000400000002020300000002c695f00000000c504c5445ff000000ff000000ff0a141e228829040000000c4944415478da639466b90900013a00fa60ede3dd0000000049454e44ae426082");
+        let frame = Frame::from_png(&png).unwrap();
+        assert_eq!((frame.width, frame.height), (4, 2));
+        assert_eq!(frame.pixels, vec![
+            [255, 0, 0], [0, 255, 0], [0, 0, 255], [10, 20, 30],
+            [10, 20, 30], [10, 20, 30], [0, 255, 0], [255, 0, 0],
+        ]);
+        // 8 bit rgb using the up and average filters
+        let png = hex("89504e470d0a1a0a0000000d4948445200000002000000020802000000fdd49a73000000164944415478da63e01291d330b2617674f3d6d5d505000d2f022f998e716e0000000049454e44ae426082");
+        assert_eq!(Frame::from_png(&png).unwrap().pixels, vec![[10, 20, 30], [40, 50, 60], [70, 80, 90], [100, 110, 120]]);
+        assert_eq!(Frame::from_png(b"GIF89a"), Err(PngError::NotPng));
+
+        // Our own pngs read back the same
+        let frame = Frame::new(3, 2, vec![[1, 2, 3], [4, 5, 6], [7, 8, 9], [9, 8, 7], [6, 5, 4], [3, 2, 1]]);
+        assert_eq!(Frame::from_png(&frame.to_png()).unwrap(), frame);
+
+        let mut changed = frame.clone();
+        changed.pixels[4] = [0, 0, 0];
+        let (count, image) = golden::diff(&changed, &frame);
+        assert_eq!(count, 1);
+        assert_eq!(image.pixels[4], [0xFF, 0, 0]);
+        assert_eq!(image.pixels[0], [0x80, 0x81, 0x81]);
+        assert_eq!(golden::diff(&frame, &frame).0, 0);
+
+        let mut screen = Screen::new();
+        let blank = screen.hash();
+        screen.pixels[143][159] = Pixel::DarkGreen;
+        assert_ne!(screen.hash(), blank);
+
+        // A rom that just loops keeps the screen blank
+        let rom = golden::test_rom(&[0xC3, 0x00, 0x01]);
+        let mut gameboy = golden::run_frames(&rom, 2).unwrap();
+        assert_eq!(gameboy.cpu.registers.pc, 0x100);
+        assert_eq!(gameboy.cpu.screen.hash(), blank);
+
+        // Once something is drawn it no longer matches a blank golden
+        let path = std::env::temp_dir().join("jboy_golden_test.png");
+        let path = path.to_str().unwrap().to_string();
+        let args = |extra: &[&str]| ["blank.gb", "--png", &path, "--diff", &format!("{}.diff.png", path)].iter()
+            .chain(extra)
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>();
+        golden::GoldenTest::from_args(args(&["--update"]).into_iter()).check(&rom, &gameboy).unwrap();
+        let test = golden::GoldenTest::from_args(args(&["--hash", &format!("{:x}", blank)]).into_iter());
+        test.check(&rom, &gameboy).unwrap();
+        gameboy.cpu.screen.pixels[10][20] = Pixel::DarkestGreen;
+        gameboy.cpu.screen.pixels[100][150] = Pixel::DarkGreen;
+        assert!(matches!(test.check(&rom, &gameboy), Err(golden::GoldenError::Mismatch(2, _))));
+        let test = golden::GoldenTest::from_args(["--hash", &format!("{:x}", blank)].iter().map(|a| a.to_string()));
+        assert!(matches!(test.check(&rom, &gameboy), Err(golden::GoldenError::HashMismatch(hash)) if hash == gameboy.cpu.screen.hash()));
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(format!("{}.diff.png", path)).unwrap();
+    }
+
+    #[test]
+    fn gameboy_test() {
+        let rom = golden::test_rom(&[0xC3, 0x00, 0x01]);
+        let mut gameboy = GameBoy::new(&rom).unwrap();
+        assert_eq!(gameboy.rom().len(), 0x8000);
+
+        assert!(!gameboy.step());
+        assert!(gameboy.frame_cycles() > 0);
+        gameboy.run_frame();
+        assert_eq!((gameboy.frames, gameboy.frame_cycles()), (1, 0));
+        gameboy.run_frame();
+
+        // A frame is a little under 1/59.7 of a second of stereo audio
+        let audio = gameboy.take_audio();
+        assert_eq!(audio.len() as u64, gameboy::samples_until(2) * 2);
+        assert!((1470..1480).contains(&(audio.len() / 2)));
+        assert!(gameboy.take_audio().is_empty());
+
+        gameboy.set_buttons(joypad::A | joypad::START);
+        assert_eq!(gameboy.buttons(), joypad::A | joypad::START);
+        let state = gameboy.save_state();
+        gameboy.set_buttons(0);
+        gameboy.step();
+        gameboy.load_state(&state).unwrap();
+        assert_eq!(gameboy.buttons(), joypad::A | joypad::START);
+        assert_eq!(gameboy.frame_cycles(), 0);
+        assert!(gameboy.load_state(b"nope").is_err());
+
+        assert_eq!(gameboy.framebuffer()[0][0], Pixel::LightestGreen);
+        gameboy.load_cartridge(&[0; 0x8000]).unwrap();
+        assert_eq!((gameboy.frames, gameboy.rom()[0x100]), (0, 0));
+
+        // Roms that don't fit in the address space are turned away, not loaded
+        gameboy.run_frame();
+        assert_eq!(gameboy.load_cartridge(&[0; 0x10001]).err(), Some(CartridgeError::TooLarge(0x10001)));
+        assert_eq!(gameboy.frames, 1);
+        assert!(GameBoy::new(&[0; gameboy::MAX_ROM_SIZE]).is_ok());
+        gameboy.reset();
+        assert_eq!((gameboy.frames, gameboy.rom().len()), (0, 0x8000));
+    }
+
+    #[test]
+    fn frontend_test() {
+        let rom = golden::test_rom(&[0xC3, 0x00, 0x01]);
+        let mut gameboy = GameBoy::new(&rom).unwrap();
+
+        let mut null = NullFrontend::new(vec![joypad::A, 0, joypad::START | joypad::UP]);
+        assert!(!null.quit());
+        frontend::run_frame(&mut gameboy, &mut null);
+        assert_eq!((null.frames, gameboy.buttons()), (1, joypad::A));
+        assert_eq!(null.samples as u64, gameboy::samples_until(1) * 2);
+
+        // The rest of the script, then it stops by itself
+        null.run(&mut gameboy);
+        assert!(null.quit());
+        assert_eq!((null.frames, gameboy.frames), (3, 3));
+        assert_eq!(gameboy.buttons(), joypad::START | joypad::UP);
+        assert_eq!(null.last_frame.as_deref(), Some(gameboy.framebuffer()));
+        assert!(gameboy.take_audio().is_empty());
+    }
+
+    #[test]
+    fn stop_test() {
+        let rom = golden::test_rom(&[0x00, 0x10]);
+        let mut gameboy = GameBoy::new(&rom).unwrap();
+        assert!(!gameboy.stopped());
+
+        // The frame ends early and nothing runs after STOP
+        gameboy.run_frame();
+        assert!(gameboy.stopped());
+        assert_eq!((gameboy.frames, gameboy.cpu.registers.pc), (0, 0x102));
+        assert!(!gameboy.step());
+        assert_eq!(gameboy.cpu.registers.pc, 0x102);
+
+        let mut null = NullFrontend::new(vec![0; 5]);
+        null.run(&mut gameboy);
+        assert_eq!(null.frames, 0);
+
+        gameboy.load_cartridge(&rom).unwrap();
+        assert!(!gameboy.stopped());
+    }
+}
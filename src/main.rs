@@ -1,343 +1,865 @@
 mod sdl;
-mod cpu;
-mod util;
-mod emulation;
-mod graphics;
-
-use sdl::events::GBButton;
-use self::cpu::Cpu;
-use self::emulation::{fetch, run};
-use std::io::{BufReader, Read};
+mod terminal;
+
+use sdl::events::Hotkey;
+use jboy::GameBoy;
+use jboy::frontend::{AudioSink, InputSource, VideoSink};
+use jboy::{capture, debugger, disasm, golden, palettes, rewind, savestate, speed, symbols, trace};
+use jboy::rewind::RewindBuffer;
+use jboy::movie::{Movie, MoviePlayer};
+use jboy::debugger::Debugger;
+use jboy::debugger::gdb::GdbStub;
+use jboy::symbols::Symbols;
+use jboy::trace::{DiffEvent, Tracer, TraceDiff};
+use jboy::cheats::CheatList;
+use self::sdl::bindings::Bindings;
+use jboy::speed::{FramePacer, SpeedControl};
+use jboy::capture::{Frame, Recorder};
+use jboy::palettes::Palettes;
+use self::terminal::Terminal;
+use self::sdl::filters::Filter;
+use std::io::{self, BufReader, Read};
 use std::fs::File;
 
-fn main() {
-    let file = File::open("roms/logo.gb").unwrap();
-    let mut reader = BufReader::new(file);
-    let mut bytes = Vec::new();
-
-    let _ = reader.read_to_end(&mut bytes);
-
-    let mut handles = sdl::SdlHandles::new();
-    let mut cpu = Cpu::new_with_rom(&bytes);
-    
-    loop {
-        handles.events.update_events();
-        handles.canvas.update();
-        
-        let instruction = fetch(&mut cpu);
+struct Options {
+    rom_path: String,
+    rewind: bool,
+    rewind_interval: u32,
+    rewind_capacity: usize,
+    record: Option<String>,
+    record_from_slot: Option<u8>,
+    play: Option<String>,
+    debug: bool,
+    gdb_port: Option<u16>,
+    trace: Option<String>,
+    trace_range: Option<(u16, u16)>,
+    diff_trace: Option<String>,
+    diff_history: usize,
+    cheats: Option<String>,
+    bindings: String,
+    fast_forward_speed: f64,
+    slow_motion_speed: f64,
+    turbo_rate: u32,
+    filter: Filter,
+    integer_scaling: bool,
+    palette: Option<String>,
+    palettes: String,
+    ghosting: Option<f64>,
+    screenshot_scale: usize,
+    capture: Option<String>,
+    terminal: bool,
+}
 
-        println!("{}", instruction);
-        loop {
-            break;
-            handles.events.update_events();
-            if handles.events.is_pressed(crate::GBButton::A) {
-                break;
+impl Options {
+
+    fn from_args() -> Self {
+        let mut options = Self {
+            rom_path: String::from("roms/logo.gb"),
+            rewind: true,
+            rewind_interval: rewind::DEFAULT_INTERVAL,
+            rewind_capacity: rewind::DEFAULT_CAPACITY,
+            record: None,
+            record_from_slot: None,
+            play: None,
+            debug: false,
+            gdb_port: None,
+            trace: None,
+            trace_range: None,
+            diff_trace: None,
+            diff_history: trace::DEFAULT_HISTORY,
+            cheats: None,
+            bindings: String::from(sdl::bindings::DEFAULT_PATH),
+            fast_forward_speed: speed::DEFAULT_FAST_FORWARD,
+            slow_motion_speed: speed::DEFAULT_SLOW_MOTION,
+            turbo_rate: speed::DEFAULT_TURBO_RATE,
+            filter: Filter::Nearest,
+            integer_scaling: false,
+            palette: None,
+            palettes: String::from(palettes::DEFAULT_PATH),
+            ghosting: None,
+            screenshot_scale: 1,
+            capture: None,
+            terminal: false,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--no-rewind" => options.rewind = false,
+                "--rewind-interval" => {
+                    options.rewind_interval = args.next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--rewind-interval takes a number of frames");
+                },
+                "--rewind-mb" => {
+                    options.rewind_capacity = args.next()
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .expect("--rewind-mb takes a number of megabytes") * 1024 * 1024;
+                },
+                "--record" => options.record = Some(args.next().expect("--record takes a movie file")),
+                "--from-slot" => {
+                    options.record_from_slot = Some(args.next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--from-slot takes a save slot"));
+                },
+                "--debug" => options.debug = true,
+                "--gdb" => {
+                    options.gdb_port = Some(args.next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--gdb takes a port"));
+                },
+                "--trace" => options.trace = Some(args.next().expect("--trace takes a file, or - for stdout")),
+                "--trace-range" => {
+                    options.trace_range = Some(args.next()
+                        .and_then(|v| trace::parse_range(&v))
+                        .expect("--trace-range takes from:to in hex"));
+                },
+                "--diff-trace" => options.diff_trace = Some(args.next().expect("--diff-trace takes a reference trace file")),
+                "--diff-history" => {
+                    options.diff_history = args.next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--diff-history takes a number of instructions");
+                },
+                "--ff-speed" => {
+                    options.fast_forward_speed = args.next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--ff-speed takes a speed multiplier, 0 for unlimited");
+                },
+                "--slow-speed" => {
+                    options.slow_motion_speed = args.next()
+                        .and_then(|v| v.parse().ok())
+                        .filter(|v: &f64| *v > 0.0)
+                        .expect("--slow-speed takes a speed multiplier above 0");
+                },
+                "--turbo-rate" => {
+                    options.turbo_rate = args.next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--turbo-rate takes presses per second");
+                },
+                "--filter" => {
+                    options.filter = args.next()
+                        .and_then(|v| Filter::from_name(&v))
+                        .expect("--filter takes nearest, scale2x, scale3x, lcd or scanlines");
+                },
+                "--integer-scale" => options.integer_scaling = true,
+                "--ghosting" => {
+                    options.ghosting = Some(args.next()
+                        .and_then(|v| v.parse().ok())
+                        .filter(|v: &f64| (0.0..1.0).contains(v))
+                        .expect("--ghosting takes how much of the last frame stays, from 0 up to 1"));
+                },
+                "--palette" => options.palette = Some(args.next().expect("--palette takes a palette name")),
+                "--palettes" => options.palettes = args.next().expect("--palettes takes a palette file"),
+                "--screenshot-scale" => {
+                    options.screenshot_scale = args.next()
+                        .and_then(|v| v.parse().ok())
+                        .filter(|v: &usize| *v > 0)
+                        .expect("--screenshot-scale takes a whole number above 0");
+                },
+                "--capture" => options.capture = Some(args.next().expect("--capture takes a .gif, or any other file for raw frames")),
+                "--terminal" => options.terminal = true,
+                "--bindings" => options.bindings = args.next().expect("--bindings takes a bindings file"),
+                "--cheats" => options.cheats = Some(args.next().expect("--cheats takes a cheat file")),
+                "--play" => options.play = Some(args.next().expect("--play takes a movie file")),
+                _ => options.rom_path = arg,
             }
         }
 
-        run(&mut cpu, instruction);
+        options
     }
-}
 
+}
 
-
-#[cfg(test)]
-mod tests {
-    
-    use crate::cpu::{ Cpu, CondCode, RegCode };
-    
-    #[test]
-    fn load8_test() {
-        let mut cpu = Cpu::new();
-        
-        cpu.load8(RegCode::A, RegCode::Const8(8));
-        assert_eq!(cpu.registers.af.left, 8);
-
-        cpu.registers.bc.change_as_one(23);
-        cpu.load8(RegCode::BC, RegCode::A);
-        assert_eq!(cpu.memory[23], 8);
+fn main() {
+    if std::env::args().nth(1).as_deref() == Some("disasm") {
+        let path = std::env::args().nth(2).expect("disasm takes a rom file");
+        let rom = std::fs::read(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+        print!("{}", disasm::disassemble(&rom, &Symbols::load_for_rom(&path)));
+        return;
     }
-    
-    #[test]
-    fn load16_test() {
-        let mut cpu = Cpu::new();
-        
-        cpu.load16(RegCode::BC, RegCode::Const16(8));
-        assert_eq!(cpu.registers.bc.take_as_one(), 8);
-
-        println!("{}", (17 & 0xFF00) >> 4);
-
-        cpu.load16(RegCode::Const16(8), RegCode::Const16(0b00010001));
-        assert_eq!(cpu.memory[8], 1);
-        assert_eq!(cpu.memory[9], 1);
+    if std::env::args().nth(1).as_deref() == Some("golden") {
+        match golden::GoldenTest::from_args(std::env::args().skip(2)).run() {
+            Ok(()) => println!("Passed"),
+            Err(e) => {
+                println!("Failed: {}", e);
+                std::process::exit(1);
+            },
+        }
+        return;
     }
 
-    #[test]
-    fn increment8_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.increment8(RegCode::A);
-        assert_eq!(cpu.registers.af.left, 1);
+    let options = Options::from_args();
+    let rom_path = options.rom_path;
+    let file = File::open(&rom_path).unwrap();
+    let mut reader = BufReader::new(file);
+    let mut bytes = Vec::new();
 
-        cpu.increment8(RegCode::HL);
-        assert_eq!(cpu.memory[0], 1);
+    let _ = reader.read_to_end(&mut bytes);
+    let mut gameboy = GameBoy::new(&bytes).unwrap_or_else(|e| panic!("Failed to load {}: {}", rom_path, e));
+
+    if options.terminal {
+        let mut palettes = Palettes::new();
+        palettes.load(&options.palettes)
+            .unwrap_or_else(|e| panic!("Failed to load palettes {}: {}", options.palettes, e));
+        palettes.set_rom(&bytes);
+        if let Some(name) = options.palette {
+            if !palettes.select(&name) {
+                panic!("No palette called {}", name);
+            }
+        }
+        run_terminal(gameboy, &rom_path, palettes);
+        return;
     }
 
-    #[test]
-    fn increment16_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.increment16(RegCode::BC);
-        assert_eq!(cpu.registers.bc.take_as_one(), 1);
-        
-        cpu.increment16(RegCode::SP);
-        assert_eq!(cpu.registers.sp, 0);
+    let mut handles = sdl::SdlHandles::new();
+    handles.events.bindings = Bindings::load(&options.bindings)
+        .unwrap_or_else(|e| panic!("Failed to load bindings {}: {}", options.bindings, e));
+    handles.events.bindings_path = options.bindings;
+    handles.canvas.filter = options.filter;
+    handles.canvas.integer_scaling = options.integer_scaling;
+    handles.canvas.palettes.load(&options.palettes)
+        .unwrap_or_else(|e| panic!("Failed to load palettes {}: {}", options.palettes, e));
+    handles.canvas.palettes.set_rom(&bytes);
+    if let Some(decay) = options.ghosting {
+        handles.canvas.ghosting.enabled = true;
+        handles.canvas.ghosting.decay = decay;
     }
-
-    #[test]
-    fn decrement8_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.decrement8(RegCode::A);
-        assert_eq!(cpu.registers.af.left, u8::MAX);
-
-        cpu.decrement8(RegCode::HL);
-        assert_eq!(cpu.memory[0], 255);
+    if let Some(name) = options.palette {
+        if !handles.canvas.palettes.select(&name) {
+            panic!("No palette called {}", name);
+        }
     }
-
-    #[test]
-    fn decrement16_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.decrement16(RegCode::BC);
-        assert_eq!(cpu.registers.bc.take_as_one(), u16::MAX);
+    let mut slot = 0;
+    let mut rewind = if options.rewind {
+        Some(RewindBuffer::new(options.rewind_interval, options.rewind_capacity))
+    } else {
+        None
+    };
+
+    let mut player = None;
+    let mut recording = None;
+    if let Some(path) = options.play {
+        let movie = Movie::load(&path).unwrap_or_else(|e| panic!("Failed to load movie {}: {}", path, e));
+        gameboy.cpu = movie.begin(&bytes).unwrap_or_else(|e| panic!("Failed to start movie {}: {}", path, e));
+        player = Some(MoviePlayer::new(movie));
+        println!("Playing movie {}", path);
+    } else if let Some(path) = options.record {
+        let movie = match options.record_from_slot {
+            Some(from) => {
+                savestate::load_slot(&mut gameboy.cpu, &rom_path, from).unwrap_or_else(|e| panic!("Failed to load slot {}: {}", from, e));
+                Movie::from_state(&bytes, &gameboy.cpu)
+            },
+            None => Movie::from_power_on(&bytes),
+        };
+        println!("Recording movie to {}", path);
+        recording = Some((path, movie));
     }
 
-    #[test]
-    fn jump_relative_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.registers.pc = 100;
-        cpu.jump_relative(CondCode::Always, -10);
-        assert_eq!(cpu.registers.pc, 90);
-        cpu.jump_relative(CondCode::Always, 10);
-        assert_eq!(cpu.registers.pc, 100);
+    match options.trace.as_deref() {
+        Some("-") => gameboy.cpu.trace.enabled = true,
+        Some(path) => gameboy.cpu.trace = Tracer::to_file(path).unwrap_or_else(|e| panic!("Failed to create trace {}: {}", path, e)),
+        None => {},
+    }
+    gameboy.cpu.trace.range = options.trace_range;
+
+    let cheats = match options.cheats.as_deref() {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read cheats {}: {}", path, e));
+            CheatList::parse(&text)
+        },
+        None => CheatList::load_for_rom(&rom_path),
+    };
+    gameboy.cpu.cheats = cheats.unwrap_or_else(|e| panic!("Failed to load cheats: {}", e));
+    if !gameboy.cpu.cheats.is_empty() {
+        println!("Loaded {} cheats", gameboy.cpu.cheats.len());
     }
 
-    #[test]
-    fn rotate_left_carry_a_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.registers.af.left = 0b10000000;
-        cpu.rotate_left_carry_a();
-        assert!(cpu.registers.af.is_carry_high());
-
-        cpu.registers.af.left = 0b10101010;
-        cpu.rotate_left_carry_a();
-        assert!(cpu.registers.af.is_carry_high());
-        assert_eq!(cpu.registers.af.left, 0b01010101);
+    let symbols = Symbols::load_for_rom(&rom_path);
+    if !symbols.is_empty() {
+        println!("Loaded {} symbols from {}", symbols.len(), symbols::sym_path(&rom_path));
+    }
+    let mut debugger = if options.debug || options.gdb_port.is_some() {
+        let mut debugger = Debugger::new(true);
+        debugger.symbols = symbols.clone();
+        Some(debugger)
+    } else {
+        None
+    };
+    if let (Some(debugger), Some(port)) = (debugger.as_mut(), options.gdb_port) {
+        let stub = GdbStub::listen(port).unwrap_or_else(|e| panic!("Failed to listen on port {}: {}", port, e));
+        debugger.gdb = Some(stub);
     }
+    let mut trace_diff = options.diff_trace.map(|path| {
+        TraceDiff::open(&path, options.diff_history).unwrap_or_else(|e| panic!("Failed to open reference trace {}: {}", path, e))
+    });
+    let mut speed = SpeedControl::new();
+    speed.fast_forward_speed = options.fast_forward_speed;
+    speed.slow_motion_speed = options.slow_motion_speed;
+    speed.turbo_rate = options.turbo_rate;
+    let mut pacer = FramePacer::new();
+    let mut capture = options.capture.map(|path| {
+        start_capture(&path).unwrap_or_else(|e| panic!("Failed to start recording {}: {}", path, e))
+    });
+    let mut diverged = false;
+    let mut in_frame = false;
+    
+    loop {
+        handles.events.poll();
+        if handles.events.quit() {
+            break;
+        }
 
-    #[test]
-    fn rotate_right_carry_a_test() {
-        let mut cpu = Cpu::new();
+        for hotkey in handles.events.hotkeys.drain(..) {
+            match hotkey {
+                Hotkey::SelectSlot(i) => {
+                    slot = i;
+                    println!("Selected save slot {}", slot);
+                },
+                Hotkey::SaveState => match savestate::save_slot(&gameboy.cpu, &rom_path, slot) {
+                    Ok(()) => println!("Saved state to slot {}", slot),
+                    Err(e) => println!("Failed to save state: {}", e),
+                },
+                Hotkey::LoadState if recording.is_some() || player.is_some() => {
+                    println!("Can't load a state while a movie is running");
+                },
+                Hotkey::LoadState => match savestate::load_slot(&mut gameboy.cpu, &rom_path, slot) {
+                    Ok(()) => {
+                        gameboy.restart_frame();
+                        in_frame = false;
+                        println!("Loaded state from slot {}", slot);
+                    },
+                    Err(e) => println!("Failed to load state: {}", e),
+                },
+                Hotkey::ToggleTrace => println!("Trace {}", if gameboy.cpu.trace.toggle() { "on" } else { "off" }),
+                Hotkey::ToggleCheats => println!("Cheats {}", if gameboy.cpu.cheats.toggle() { "on" } else { "off" }),
+                Hotkey::MemoryViewer => handles.memory_viewer.toggle(&handles.video_subsystem),
+                Hotkey::VramViewer => handles.vram_viewer.toggle(&handles.video_subsystem),
+                Hotkey::IoViewer => handles.io_viewer.toggle(&handles.video_subsystem),
+                Hotkey::RamSearch => handles.ram_search.toggle(&handles.video_subsystem),
+                Hotkey::ToggleFullscreen => handles.canvas.toggle_fullscreen(),
+                Hotkey::CycleFilter => handles.canvas.cycle_filter(),
+                Hotkey::CyclePalette => println!("Palette: {}", handles.canvas.palettes.cycle()),
+                Hotkey::ToggleGhosting => handles.canvas.ghosting.toggle(),
+                Hotkey::Screenshot => {
+                    let path = capture::numbered_path(&rom_path, "png");
+                    match handles.canvas.capture().scale(options.screenshot_scale).save_png(&path) {
+                        Ok(()) => println!("Saved screenshot to {}", path),
+                        Err(e) => println!("Failed to save screenshot {}: {}", path, e),
+                    }
+                },
+                Hotkey::ToggleRecording => match capture.take() {
+                    Some(recorder) => finish_capture(recorder),
+                    None => {
+                        let path = capture::numbered_path(&rom_path, "gif");
+                        match start_capture(&path) {
+                            Ok(recorder) => capture = Some(recorder),
+                            Err(e) => println!("Failed to start recording {}: {}", path, e),
+                        }
+                    },
+                },
+                Hotkey::ToggleIntegerScaling => handles.canvas.toggle_integer_scaling(),
+                Hotkey::TogglePause => println!("{}", if speed.toggle_pause() { "Paused, advance a frame at a time" } else { "Resumed" }),
+                Hotkey::FrameAdvance => speed.advance(),
+                Hotkey::ToggleSlowMotion => println!("Slow motion {}", if speed.toggle_slow_motion() { "on" } else { "off" }),
+                Hotkey::ToggleAutofire(button) => println!("Autofire {}", if speed.toggle_autofire(button.mask()) { "on" } else { "off" }),
+                Hotkey::StopMovie => {
+                    finish_recording(recording.take());
+                    if player.take().is_some() {
+                        println!("Stopped movie playback");
+                    }
+                },
+            }
+        }
 
-        cpu.registers.af.left = 0b00000001;
-        cpu.rotate_right_carry_a();
-        assert!(cpu.registers.af.is_carry_high());
+        if let Some(debugger) = debugger.as_mut() {
+            debugger.poll(&mut gameboy.cpu);
+        }
+        let paused = debugger.as_ref().is_some_and(|d| d.paused);
+        speed.fast_forward = handles.events.fast_forward;
+        let multiplier = if paused || speed.paused { 1.0 } else { speed.speed() };
+        handles.audio.speed = multiplier;
+
+        match rewind.as_mut() {
+            Some(buffer) if handles.events.rewinding && recording.is_none() && player.is_none() && !paused => {
+                buffer.rewind_frame(&mut gameboy.cpu);
+                gameboy.restart_frame();
+                in_frame = false;
+            },
+            _ => {
+                // Inputs are latched once per emulated frame, even if the debugger splits it up
+                if !in_frame && !paused && speed.start_frame() {
+                    in_frame = true;
+                    let movie_input = player.as_mut().and_then(|p| p.next_input());
+                    if player.is_some() && movie_input.is_none() {
+                        println!("Movie playback finished");
+                        player = None;
+                    }
+
+                    gameboy.set_buttons(movie_input.unwrap_or_else(|| speed.apply_autofire(handles.events.buttons())));
+                    if let Some((_, movie)) = recording.as_mut() {
+                        movie.record_frame(gameboy.buttons());
+                    }
+                    gameboy.cpu.cheats.apply(&mut gameboy.cpu.memory);
+                }
+
+                let log_instructions = debugger.as_ref().is_some_and(|d| d.log_instructions);
+                while in_frame {
+                    if let Some(debugger) = debugger.as_mut() {
+                        if debugger.should_stop(&mut gameboy.cpu) {
+                            break;
+                        }
+                    }
+
+                    match trace_diff.as_mut().and_then(|d| d.check(&gameboy.cpu, &symbols)) {
+                        Some(DiffEvent::Diverged(report)) => {
+                            print!("{}", report);
+                            match debugger.as_mut() {
+                                Some(debugger) => debugger.stop(&mut gameboy.cpu, "Trace diverged"),
+                                None => diverged = true,
+                            }
+                            break;
+                        },
+                        Some(DiffEvent::Ended(lines)) => println!("Reference trace ended after {} lines with no divergence", lines),
+                        None => {},
+                    }
+
+                    if log_instructions {
+                        let pc = gameboy.cpu.registers.pc;
+                        println!("{}: {}", symbols.describe(pc), debugger::instruction_string(&gameboy.cpu, pc, &symbols));
+                    }
+                    let finished = gameboy.step();
+                    if let Some(e) = gameboy.cpu.trace.take_error() {
+                        println!("Failed to write trace, turning it off: {}", e);
+                    }
+
+                    if let Some(debugger) = debugger.as_mut() {
+                        debugger.after_instruction(&mut gameboy.cpu);
+                    }
+
+                    if gameboy.stopped() {
+                        in_frame = false;
+                        break;
+                    }
+                    if finished {
+                        in_frame = false;
+                        speed.end_frame();
+                        if let Some(buffer) = rewind.as_mut() {
+                            buffer.record_frame(&gameboy.cpu);
+                        }
+                        let audio = gameboy.take_audio();
+                        handles.audio.queue(&audio);
+                        if let Some(recorder) = capture.as_mut() {
+                            let frame = Frame::from_pixels(gameboy.framebuffer(), handles.canvas.palettes.current());
+                            if let Err(e) = recorder.add_frame(&frame, &audio) {
+                                println!("Stopped recording: {}", e);
+                                capture = None;
+                            }
+                        }
+                    }
+                }
+            },
+        }
 
-        cpu.registers.af.left = 0b10101010;
-        cpu.rotate_right_carry_a();
-        assert_eq!(cpu.registers.af.left, 0b11010101);
+        handles.canvas.present(gameboy.framebuffer());
+        handles.update_debug_windows(&mut gameboy.cpu);
+        pacer.wait(multiplier);
+        if diverged || gameboy.stopped() {
+            break;
+        }
     }
 
-    #[test]
-    fn rotate_right_a_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.registers.af.left = 0b00000001;
-        cpu.rotate_right_a();
-        assert!(cpu.registers.af.is_carry_high());
-        assert_eq!(cpu.registers.af.left, 0b10000000);
+    finish_recording(recording);
+    if let Some(recorder) = capture {
+        finish_capture(recorder);
     }
+    gameboy.cpu.trace.flush();
+}
 
-    #[test]
-    fn rotate_left_a_test() {
-        let mut cpu = Cpu::new();
+/*
+ *  Runs in the terminal instead of a window, with fewer hotkeys and none of
+ *  the debug tools apart from the register sidebar
+ */
+fn run_terminal(mut gameboy: GameBoy, rom_path: &str, palettes: Palettes) {
+    let symbols = Symbols::load_for_rom(rom_path);
+    let mut terminal = Terminal::new(palettes);
+    let mut speed = SpeedControl::new();
+    let mut pacer = FramePacer::new();
+    let mut slot = 0;
+
+    while !terminal.quit() && !gameboy.stopped() {
+        terminal.poll();
+        for hotkey in std::mem::take(&mut terminal.hotkeys) {
+            terminal.status = match hotkey {
+                terminal::Hotkey::SelectSlot(i) => {
+                    slot = i;
+                    format!("Selected save slot {}", slot)
+                },
+                terminal::Hotkey::SaveState => match savestate::save_slot(&gameboy.cpu, rom_path, slot) {
+                    Ok(()) => format!("Saved state to slot {}", slot),
+                    Err(e) => format!("Failed to save state: {}", e),
+                },
+                terminal::Hotkey::LoadState => match savestate::load_slot(&mut gameboy.cpu, rom_path, slot) {
+                    Ok(()) => {
+                        gameboy.restart_frame();
+                        format!("Loaded state from slot {}", slot)
+                    },
+                    Err(e) => format!("Failed to load state: {}", e),
+                },
+                terminal::Hotkey::TogglePause => String::from(if speed.toggle_pause() { "Paused" } else { "" }),
+                terminal::Hotkey::FrameAdvance => {
+                    speed.advance();
+                    String::from("Paused")
+                },
+                terminal::Hotkey::CyclePalette => format!("Palette: {}", terminal.palettes.cycle()),
+            };
+        }
 
-        cpu.registers.af.left = 0b10000000;
-        cpu.rotate_left_a();
-        assert!(cpu.registers.af.is_carry_high());
-        assert_eq!(cpu.registers.af.left, 0b00000001);
+        if speed.start_frame() {
+            gameboy.set_buttons(terminal.buttons());
+            gameboy.run_frame();
+            speed.end_frame();
+            gameboy.take_audio();
+        }
+        terminal.sidebar = terminal::sidebar(&gameboy.cpu, &symbols);
+        terminal.present(gameboy.framebuffer());
+        pacer.wait(1.0);
     }
+}
 
-    #[test]
-    fn add8_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.registers.bc.left = 0b10001000;
-        cpu.registers.af.left = 0b10001000;
-        cpu.add8(RegCode::B);
-        assert!(cpu.registers.af.is_carry_high());
-        assert!(cpu.registers.af.is_hcarry_high());
-        assert_eq!(cpu.registers.af.left, 0b00010000);
+fn start_capture(path: &str) -> io::Result<Recorder> {
+    let recorder = Recorder::start(path, sdl::canvas::WIDTH as usize, sdl::canvas::HEIGHT as usize)?;
+    println!("Recording video to {} and audio to {}", recorder.path, recorder.audio_path);
+    Ok(recorder)
+}
 
-        cpu.registers.af.left = 0;
-        cpu.add8(RegCode::Const8(0));
-        assert!(cpu.registers.af.is_zero_high());
+fn finish_capture(recorder: Recorder) {
+    let (path, frames) = (recorder.path.clone(), recorder.frames);
+    match recorder.finish() {
+        Ok(()) => println!("Saved {} frames to {}", frames, path),
+        Err(e) => println!("Failed to save recording {}: {}", path, e),
     }
+}
 
-    #[test]
-    fn add16_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.registers.af.flip_zero_flag();
-        cpu.registers.hl.change_as_one(0b1000100000000000);
-        cpu.registers.de.change_as_one(0b1000100000000000);
-        cpu.add16(RegCode::DE);
-        
-        assert!(cpu.registers.af.is_zero_high());
-        assert!(cpu.registers.af.is_carry_high());
-        assert!(cpu.registers.af.is_hcarry_high());
-        assert_eq!(cpu.registers.hl.take_as_one(), 0b0001000000000000);
+fn finish_recording(recording: Option<(String, Movie)>) {
+    if let Some((path, movie)) = recording {
+        match movie.save(&path) {
+            Ok(()) => println!("Saved {} frames of input to {}", movie.inputs.len(), path),
+            Err(e) => println!("Failed to save movie {}: {}", path, e),
+        }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    
+    use jboy::cpu::Cpu;
+    use crate::sdl::memory_viewer::{ MemoryViewer, Mode };
+    use crate::sdl::vram_viewer::{ self, VramViewer, View };
+    use jboy::graphics::{ self, Lcd, Pixel, Sprite };
+    use crate::sdl::io_viewer::IoViewer;
+    use jboy::io_registers;
+    use jboy::ram_search::{ RamSearch, Filter };
+    use crate::sdl::ram_search_viewer::{ self, RamSearchViewer };
+    use crate::sdl::bindings::{ Bindings, BindingError, Input };
+    use crate::sdl::events::GBButton;
+    use sdl2::controller::Button;
+    use crate::sdl::canvas;
+    use crate::sdl::filters;
+    use sdl2::pixels::Color;
+    use sdl2::rect::Rect;
+    use jboy::cpu::memory;
+    use sdl2::keyboard::Keycode;
+    use jboy::joypad;
 
     #[test]
-    fn sub_test() {
+    fn memory_viewer_test() {
         let mut cpu = Cpu::new();
+        let mut viewer = MemoryViewer::new();
+        assert_eq!(memory::region(0xFE10).2, "OAM");
+        assert_eq!(memory::region(0xFFFF).2, "IE");
 
-        cpu.sub(RegCode::Const8(90));
-        
-        assert_eq!(cpu.registers.af.left, 166);
-        assert!(cpu.registers.af.is_carry_high());
-
-        cpu.sub(RegCode::Const8(166));
-
-        assert_eq!(cpu.registers.af.left, 0);
-        assert!(cpu.registers.af.is_zero_high());
+        // G C000 Enter, then type 3E 12 over it
+        for key in [Keycode::G, Keycode::C, Keycode::Num0, Keycode::Num0, Keycode::Num0, Keycode::Return] {
+            viewer.handle_key(key, &mut cpu);
+        }
+        assert_eq!(viewer.cursor, 0xC000);
+        for key in [Keycode::Num3, Keycode::E, Keycode::Num1, Keycode::Num2] {
+            viewer.handle_key(key, &mut cpu);
+        }
+        assert_eq!(cpu.memory.peek(0xC000), 0x3E);
+        assert_eq!(cpu.memory.peek(0xC001), 0x12);
+        assert_eq!(viewer.cursor, 0xC002);
 
-        cpu.registers.af.left = 0b00010000;
-        cpu.sub(RegCode::Const8(0b00001000));
+        // Search wraps around back to what was just written
+        for key in [Keycode::S, Keycode::Num3, Keycode::E, Keycode::Num1, Keycode::Num2, Keycode::KpEnter] {
+            viewer.handle_key(key, &mut cpu);
+        }
+        assert_eq!(viewer.mode, Mode::Browse);
+        assert_eq!(viewer.cursor, 0xC000);
 
-        assert!(cpu.registers.af.is_hcarry_high());
+        viewer.handle_key(Keycode::Tab, &mut cpu);
+        assert_eq!(viewer.cursor, 0xE000);
     }
 
     #[test]
-    fn and_test() {
+    fn vram_viewer_test() {
+        // A tile whose rows read color ids 0 1 2 3 0 1 2 3 left to right
+        let tile = [0b01010101, 0b00110011].repeat(8);
+        assert_eq!(graphics::decode_tile(&tile)[0], [0, 1, 2, 3, 0, 1, 2, 3]);
+        assert_eq!(graphics::shade(0b11100100, 3), Pixel::DarkestGreen);
+        assert_eq!(graphics::shade(0b00011011, 3), Pixel::LightestGreen);
+
+        let mut lcd = Lcd::new();
+        lcd.update_with_byte(0b1001_0000);
+        assert_eq!(lcd.tile_address(0x80), 0x8800);
+        lcd.update_with_byte(0b1000_0000);
+        assert_eq!(lcd.tile_address(0x80), 0x8800);
+        assert_eq!(lcd.tile_address(0x7F), 0x97F0);
+        assert_eq!(lcd.tile_address(0x00), 0x9000);
+
+        // Sprite 1 uses tile 2, flipped in x, with OBP0 mapping colors straight through
         let mut cpu = Cpu::new();
-
-        cpu.registers.af.left = 0b01010000;
-        cpu.and(RegCode::Const8(0b10100000));
-
-        assert!(cpu.registers.af.is_zero_high());
-        assert!(cpu.registers.af.is_hcarry_high());
+        for (i, byte) in tile.iter().enumerate() {
+            cpu.memory.poke(0x8020 + i as u16, *byte);
+        }
+        for (i, byte) in [0x20, 0x10, 0x02, 0b0010_0000].iter().enumerate() {
+            cpu.memory.poke(0xFE04 + i as u16, *byte);
+        }
+        cpu.memory.poke(graphics::OBP0, 0b11100100);
+        let sprite = Sprite::read(&cpu, 1);
+        assert_eq!(sprite, Sprite { y: 0x20, x: 0x10, tile: 2, flags: 0b0010_0000 });
+        assert!(sprite.x_flip() && !sprite.y_flip());
+        assert!(sprite.visible(&lcd));
+
+        let pixels = vram_viewer::sprite_pixels(&cpu, &sprite, &lcd);
+        assert_eq!(pixels.len(), 64);
+        assert_eq!(&pixels[..4], &[Some(Pixel::DarkestGreen), Some(Pixel::DarkGreen), Some(Pixel::LightGreen), None]);
+
+        let mut viewer = VramViewer::new();
+        viewer.handle_key(Keycode::Num3);
+        assert_eq!(viewer.view, View::Sprites);
+        viewer.handle_key(Keycode::Tab);
+        assert_eq!(viewer.view, View::Tiles);
+        viewer.handle_key(Keycode::P);
+        assert_eq!(viewer.palette, 1);
     }
 
     #[test]
-    fn or_test() {
+    fn io_registers_test() {
         let mut cpu = Cpu::new();
+        let lcdc = io_registers::find("lcdc").unwrap();
+        assert_eq!(lcdc.addr, graphics::LCDC);
+
+        // The table decodes LCDC the same way the lcd does
+        for byte in [0x91u8, 0x00, 0xFF, 0b0101_1010] {
+            let mut lcd = Lcd::new();
+            lcd.update_with_byte(byte);
+            let flags = [lcd.enabled, lcd.display_select, lcd.window_enabled, lcd.tile_data,
+                lcd.background_select, lcd.sprite_size, lcd.sprites_enabled, lcd.background_enabled];
+            let fields: Vec<bool> = lcdc.fields.iter().map(|f| f.value(byte) != 0).collect();
+            assert_eq!(fields, flags);
+        }
 
-        cpu.registers.af.left = 0b01010000;
-        cpu.or(RegCode::Const8(0b01000000));
-
-        assert_eq!(cpu.registers.af.left, 0b01010000);
-
-        cpu.registers.af.left = 0;
-        cpu.or(RegCode::Const8(0));
-
-        assert!(cpu.registers.af.is_zero_high());
+        cpu.memory.poke(0xFF41, 0b0100_0110);
+        let stat = io_registers::find("STAT").unwrap();
+        assert_eq!(stat.describe(&cpu),
+            "FF41 STAT 46  LYC INT=ON  OAM INT=OFF  VBLANK INT=OFF  HBLANK INT=OFF  LYC=LY=ON  MODE=OAM");
+        assert_eq!(io_registers::find("TAC").unwrap().decode(0b101), ["ENABLE=ON", "CLOCK=262144HZ"]);
+        assert_eq!(io_registers::find("NR12").unwrap().decode(0xF3), ["VOLUME=15", "DIR=DOWN", "PACE=3"]);
+        assert_eq!(io_registers::find("BGP").unwrap().decode(0xE4), ["3=3", "2=2", "1=1", "0=0"]);
+        assert!(io_registers::find("NOPE").is_none());
+
+        let mut viewer = IoViewer::new();
+        viewer.update(&cpu);
+        let index = io_registers::REGISTERS.iter().position(|r| r.name == "LY").unwrap();
+        assert!(!viewer.recently_changed(index));
+        cpu.memory.poke(0xFF44, 0x12);
+        viewer.update(&cpu);
+        assert!(viewer.recently_changed(index));
     }
 
     #[test]
-    fn xor_test() {
+    fn ram_search_test() {
         let mut cpu = Cpu::new();
-
-        cpu.registers.af.left = 0b01010000;
-        cpu.xor(RegCode::Const8(0b01000000));
-
-        assert_eq!(cpu.registers.af.left, 0b00010000);
-        cpu.xor(RegCode::Const8(0b00010000));
-        
-        assert!(cpu.registers.af.is_zero_high());
+        let mut search = RamSearch::new();
+        cpu.memory.poke(0xC100, 3);
+        cpu.memory.poke(0xFF90, 7);
+        search.reset(&cpu.memory);
+        assert_eq!(search.candidates.len(), 0x2000 + 0x2000 + 0x7F);
+
+        // Lives go from 3 to 2, something else counts up
+        cpu.memory.poke(0xC100, 2);
+        cpu.memory.poke(0xFF90, 8);
+        search.filter(&cpu.memory, Filter::Changed);
+        assert_eq!(search.candidates, [0xC100, 0xFF90]);
+        search.filter(&cpu.memory, Filter::Equal);
+        assert_eq!(search.candidates.len(), 2);
+        assert_eq!(search.previous(0), 2);
+
+        cpu.memory.poke(0xC100, 1);
+        cpu.memory.poke(0xFF90, 9);
+        search.filter(&cpu.memory, Filter::Decreased);
+        assert_eq!(search.candidates, [0xC100]);
+        search.filter(&cpu.memory, Filter::Value(5));
+        assert!(search.candidates.is_empty());
+
+        // The viewer's value search, then freezing what's left
+        let mut viewer = RamSearchViewer::new();
+        viewer.handle_key(Keycode::R, &mut cpu);
+        cpu.memory.poke(0xC100, 0x42);
+        for key in [Keycode::V, Keycode::Num4, Keycode::Num2, Keycode::Return] {
+            viewer.handle_key(key, &mut cpu);
+        }
+        assert_eq!(viewer.search.candidates, [0xC100]);
+        viewer.handle_key(Keycode::F, &mut cpu);
+        assert_eq!(viewer.search.watches, [0xC100]);
+        assert_eq!(cpu.cheats.cheats[0].text, ram_search_viewer::freeze_code(0xC100, 0x42));
+        assert_eq!(cpu.cheats.cheats[0].text, "014200C1");
+
+        cpu.memory.poke(0xC100, 0);
+        cpu.cheats.apply(&mut cpu.memory);
+        assert_eq!(cpu.memory.peek(0xC100), 0x42);
+
+        viewer.handle_key(Keycode::M, &mut cpu);
+        assert_eq!(viewer.show, Some(0xC100));
     }
 
     #[test]
-    fn addc_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.registers.bc.left = 0b10001000;
-        cpu.registers.af.left = 0b10001000;
-        cpu.addc(RegCode::B);
-        assert!(cpu.registers.af.is_carry_high());
-        assert!(cpu.registers.af.is_hcarry_high());
-        assert_eq!(cpu.registers.af.left, 0b00010000);
-        
-        cpu.registers.af.flip_flags_down();
-        cpu.registers.af.left = 0;
-        cpu.addc(RegCode::Const8(0));
-        assert!(cpu.registers.af.is_zero_high());
-
-        cpu.registers.af.flip_carry_flag();
-        cpu.addc(RegCode::Const8(0));
-        assert_eq!(cpu.registers.af.left, 1);
+    fn bindings_test() {
+        let defaults = Bindings::new();
+        assert_eq!(defaults.button_for(Input::Key(Keycode::Return)), Some(GBButton::Start));
+        assert_eq!(defaults.button_for(Input::Key(Keycode::KpEnter)), Some(GBButton::Start));
+        assert_eq!(defaults.button_for(Input::Pad(Button::DPadUp)), Some(GBButton::Up));
+        assert_eq!(defaults.button_for(Input::Key(Keycode::F5)), None);
+
+        let mut bindings = Bindings::parse("; comment\nA = j, pad:b\nstart = Return\nthreshold = 8000\n").unwrap();
+        assert_eq!(bindings.threshold, 8000);
+        assert_eq!(bindings.button_for(Input::Key(Keycode::J)), Some(GBButton::A));
+        assert_eq!(bindings.button_for(Input::Pad(Button::B)), Some(GBButton::A));
+        assert_eq!(bindings.button_for(Input::Key(Keycode::Z)), None);
+
+        // Rebinding replaces the button's old key but keeps its pad button, and steals the key from start
+        bindings.rebind(GBButton::A, Input::Key(Keycode::Return)).unwrap();
+        assert_eq!(bindings.button_for(Input::Key(Keycode::Return)), Some(GBButton::A));
+        assert_eq!(bindings.button_for(Input::Key(Keycode::J)), None);
+        assert_eq!(bindings.button_for(Input::Pad(Button::B)), Some(GBButton::A));
+        assert_eq!(bindings.inputs.len(), 2);
+
+        assert_eq!(bindings.stick_mask(-9000, 0), joypad::LEFT);
+        assert_eq!(bindings.stick_mask(8000, 20000), joypad::RIGHT | joypad::DOWN);
+        assert_eq!(bindings.stick_mask(7999, -7999), 0);
+
+        assert!(matches!(Bindings::parse("jump = Z"), Err(BindingError::UnknownButton(_))));
+        assert!(matches!(Bindings::parse("a = pad:nope"), Err(BindingError::UnknownPadButton(_))));
+        assert!(matches!(Bindings::parse("a = Not A Key"), Err(BindingError::UnknownKey(_))));
+        assert_eq!(Bindings::parse("threshold = -5").err(), Some(BindingError::InvalidLine(1)));
+        assert_eq!(GBButton::from_name("Select"), Some(GBButton::Select));
+
+        // Keys the hotkeys use can't press buttons, or the hotkey would stop working
+        assert_eq!(Bindings::parse("a = F5").err(), Some(BindingError::HotkeyTaken(String::from("F5"))));
+        assert!(matches!(Bindings::parse("select = Space"), Err(BindingError::HotkeyTaken(_))));
+        assert!(matches!(Bindings::parse("b = 3"), Err(BindingError::HotkeyTaken(_))));
+        assert!(matches!(bindings.rebind(GBButton::B, Input::Key(Keycode::Tab)), Err(BindingError::HotkeyTaken(_))));
+        assert_eq!(bindings.button_for(Input::Key(Keycode::Tab)), None);
+        assert!(bindings.rebind(GBButton::B, Input::Pad(Button::Y)).is_ok());
     }
 
     #[test]
-    fn subc_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.subc(RegCode::Const8(90));
-        
-        assert_eq!(cpu.registers.af.left, 166);
-        assert!(cpu.registers.af.is_carry_high());
-        
-        cpu.registers.af.flip_flags_down();
-        cpu.subc(RegCode::Const8(166));
-
-        assert_eq!(cpu.registers.af.left, 0);
-        assert!(cpu.registers.af.is_zero_high());
-
-        cpu.registers.af.left = 0b00010000;
-        cpu.subc(RegCode::Const8(0b00001000));
-
-        assert!(cpu.registers.af.is_hcarry_high());
-        
-        cpu.registers.af.left = 90;
-        cpu.registers.af.flip_carry_flag();
-        cpu.subc(RegCode::Const8(89));
-        assert!(cpu.registers.af.is_zero_high());
+    fn canvas_test() {
+        // Wide windows letterbox on the sides, integer scaling rounds down and centers
+        assert_eq!(canvas::viewport(640, 288, false), Rect::new(160, 0, 320, 288));
+        assert_eq!(canvas::viewport(500, 500, false), Rect::new(0, 25, 500, 450));
+        assert_eq!(canvas::viewport(500, 500, true), Rect::new(10, 34, 480, 432));
+        assert_eq!(canvas::viewport(400, 300, true), Rect::new(40, 6, 320, 288));
+        assert_eq!(canvas::viewport(100, 100, true), Rect::new(-30, -22, 160, 144));
+
+        let (w, k) = (Color::RGB(255, 255, 255), Color::RGB(0, 0, 0));
+        let frame = [
+            k, w, w,
+            w, w, w,
+            w, w, k,
+        ];
+        assert_eq!(filters::Filter::Nearest.apply(&frame, 3), frame);
+
+        // The corner of the black pixel next to two white neighbours rounds off
+        let scaled = filters::Filter::Scale2x.apply(&frame, 3);
+        assert_eq!(scaled.len(), 36);
+        assert_eq!(&scaled[0..2], &[k, k]);
+        assert_eq!(&scaled[6..8], &[k, w]);
+        assert_eq!(filters::Filter::Scale3x.apply(&frame, 3).len(), 81);
+        assert_eq!(filters::Filter::Scale3x.apply(&[w; 4], 2), vec![w; 36]);
+
+        let grid = filters::Filter::LcdGrid.apply(&[w], 1);
+        assert_eq!(grid[0], w);
+        assert_eq!(grid[8], Color::RGB(191, 191, 191));
+        assert_eq!(filters::Filter::Scanlines.apply(&[w], 1), [w, w, Color::RGB(127, 127, 127), Color::RGB(127, 127, 127)]);
+
+        assert_eq!(filters::Filter::from_name("lcd"), Some(filters::Filter::LcdGrid));
+        assert_eq!(filters::Filter::Scanlines.next(), filters::Filter::Nearest);
     }
 
     #[test]
-    fn cp_test() {
-        let mut cpu = Cpu::new();
-
-        cpu.cp(RegCode::Const8(90));
-        
-        assert_ne!(cpu.registers.af.left, 166);
-        assert!(cpu.registers.af.is_carry_high());
-        
-        cpu.registers.af.left = 166;
-        cpu.cp(RegCode::Const8(166));
-
-        assert_ne!(cpu.registers.af.left, 0);
-        assert!(cpu.registers.af.is_zero_high());
-
-        cpu.registers.af.left = 0b00010000;
-        cpu.cp(RegCode::Const8(0b00001000));
-
-        assert!(cpu.registers.af.is_hcarry_high());
+    fn ghosting_test() {
+        use crate::sdl::filters::Ghosting;
+
+        let (black, white) = (Color::RGB(0, 0, 0), Color::RGB(0xFF, 0xFF, 0xFF));
+        let mut ghosting = Ghosting::new();
+        assert_eq!(ghosting.apply(vec![white]), vec![white]);
+        assert_eq!(ghosting.apply(vec![black]), vec![black]);
+
+        ghosting.toggle();
+        ghosting.decay = 0.5;
+        assert_eq!(ghosting.apply(vec![white, black]), vec![white, black]);
+        assert_eq!(ghosting.apply(vec![black, black]), vec![Color::RGB(0x80, 0x80, 0x80), black]);
+        assert_eq!(ghosting.apply(vec![black, black]), vec![Color::RGB(0x40, 0x40, 0x40), black]);
+
+        // Flickering every other frame settles in between
+        for i in 0..20 {
+            ghosting.apply(vec![if i % 2 == 0 { white } else { black }; 2]);
+        }
+        let settled = ghosting.apply(vec![white; 2])[0];
+        assert!((0x90..0xC0).contains(&settled.r));
     }
 
     #[test]
-    fn push_pop_test() {
-        let mut cpu = Cpu::new();
+    fn terminal_test() {
+        use crate::terminal::{ self, Key, Hotkey };
+        use jboy::palettes::Palette;
+
+        assert_eq!(terminal::parse_keys(b"zX\r\x1b[A\x1bOD\x1b[15~\x1b[18~\x1bOP\x03\x1b"),
+            [Key::Char('z'), Key::Char('x'), Key::Enter, Key::Up, Key::Left, Key::Function(5),
+            Key::Function(7), Key::Function(1), Key::CtrlC, Key::Escape]);
+        // Unknown sequences are skipped whole, a cut off one ends the read
+        assert_eq!(terminal::parse_keys(b"\x1b[1;5Hq\x1b[2"), [Key::Char('q')]);
+        assert_eq!(terminal::button_for(Key::Char(' ')), Some(joypad::SELECT));
+        assert_eq!(terminal::hotkey_for(Key::Char('3')), Some(Hotkey::SelectSlot(3)));
+        assert_eq!(terminal::hotkey_for(Key::Function(7)), Some(Hotkey::LoadState));
+
+        let mut frame = [[Pixel::LightestGreen; graphics::SCREEN_WIDTH]; graphics::SCREEN_HEIGHT];
+        frame[1][0] = Pixel::DarkestGreen;
+        let palette = Palette::gray();
+        let cells = terminal::cells(&frame, &palette);
+        assert_eq!(cells.len(), graphics::SCREEN_WIDTH * graphics::SCREEN_HEIGHT / 2);
+        assert_eq!(cells[0], ([0xFF; 3], [0x00; 3]));
+
+        // The first frame draws everything, after that only what changed
+        let full = terminal::draw(&cells, &[]);
+        assert!(full.starts_with("\x1b[1;1H\x1b[38;2;255;255;255;48;2;0;0;0m▀\x1b[38;2;255;255;255;48;2;255;255;255m▀▀"));
+        assert_eq!(full.matches('▀').count(), cells.len());
+        assert_eq!(full.matches("\x1b[2;1H").count(), 1);
+        assert_eq!(terminal::draw(&cells, &cells), "");
+        let mut changed = cells.clone();
+        changed[161] = ([0x00; 3], [0x00; 3]);
+        assert_eq!(terminal::draw(&changed, &cells), "\x1b[2;2H\x1b[38;2;0;0;0;48;2;0;0;0m▀\x1b[0m");
 
-        cpu.registers.bc.change_as_one(400);
-
-        cpu.push(RegCode::BC);
-        cpu.pop(RegCode::DE);
-
-        assert_eq!(cpu.registers.de.take_as_one(), 400);
+        let mut cpu = Cpu::new();
+        cpu.init_reg();
+        cpu.memory.poke(0x100, 0x00);
+        cpu.memory.poke(0x101, 0xC3);
+        let lines = terminal::sidebar(&cpu, &jboy::symbols::Symbols::new());
+        assert_eq!(lines[0], "PC 0100  SP FFFE");
+        assert_eq!(lines[1], "A  01    F  B0  Z-HC");
+        assert_eq!(lines[8], ">0100 NOP");
+        assert!(lines[9].starts_with(" 0101 JP "));
     }
 }
@@ -0,0 +1,186 @@
+use std::fs;
+
+use crate::cpu::Cpu;
+use crate::savestate::{self, StateReader, StateWriter, StateError};
+
+/*
+ *  Input movies
+ *  A movie is the joypad state for every frame, anchored to either power on
+ *  or an embedded save state. The core never reads the clock or any other
+ *  outside state, so replaying the same inputs from the same start always
+ *  reproduces the same run frame for frame
+ */
+
+pub const MOVIE_MAGIC: &[u8; 4] = b"JBMV";
+pub const MOVIE_VERSION: u8 = 1;
+
+#[derive(Debug)]
+pub enum MovieError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    RomMismatch,
+    State(StateError),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for MovieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "Not a movie file"),
+            MovieError::UnsupportedVersion(v) => write!(f, "Unsupported movie version {} (expected {})", v, MOVIE_VERSION),
+            MovieError::RomMismatch => write!(f, "Movie was recorded with a different rom"),
+            MovieError::State(e) => write!(f, "{}", e),
+            MovieError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<StateError> for MovieError {
+    fn from(e: StateError) -> Self {
+        MovieError::State(e)
+    }
+}
+
+impl From<std::io::Error> for MovieError {
+    fn from(e: std::io::Error) -> Self {
+        MovieError::Io(e)
+    }
+}
+
+pub enum MovieStart {
+    PowerOn,
+    SaveState(Vec<u8>),
+}
+
+pub struct Movie {
+    pub rom_hash: u32,
+    pub start: MovieStart,
+    pub inputs: Vec<u8>,
+}
+
+/// FNV-1a hash of the rom, used to catch movies played on the wrong game
+pub fn rom_hash(rom: &[u8]) -> u32 {
+    rom.iter().fold(0x811C9DC5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x01000193))
+}
+
+impl Movie {
+
+    /// Starts a recording from power on
+    pub fn from_power_on(rom: &[u8]) -> Self {
+        Self {
+            rom_hash: rom_hash(rom),
+            start: MovieStart::PowerOn,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Starts a recording from the machine's current state
+    pub fn from_state(rom: &[u8], cpu: &Cpu) -> Self {
+        Self {
+            rom_hash: rom_hash(rom),
+            start: MovieStart::SaveState(savestate::save(cpu)),
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn record_frame(&mut self, input: u8) {
+        self.inputs.push(input);
+    }
+
+    /// Builds the machine the movie starts from
+    pub fn begin(&self, rom: &[u8]) -> Result<Cpu, MovieError> {
+        if rom_hash(rom) != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        match &self.start {
+            MovieStart::PowerOn => Ok(Cpu::new_with_rom(rom)),
+            MovieStart::SaveState(state) => Ok(savestate::restore(state)?),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.write_bytes(MOVIE_MAGIC);
+        writer.write_u8(MOVIE_VERSION);
+        writer.write_u32(self.rom_hash);
+        match &self.start {
+            MovieStart::PowerOn => writer.write_u8(0),
+            MovieStart::SaveState(state) => {
+                writer.write_u8(1);
+                writer.write_u32(state.len() as u32);
+                writer.write_bytes(state);
+            },
+        }
+        writer.write_u32(self.inputs.len() as u32);
+        writer.write_bytes(&self.inputs);
+        writer.into_bytes()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        let mut reader = StateReader::new(data);
+
+        let mut magic = [0; 4];
+        reader.read_bytes(&mut magic).map_err(|_| MovieError::BadMagic)?;
+        if &magic != MOVIE_MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+
+        let version = reader.read_u8()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let rom_hash = reader.read_u32()?;
+        let start = match reader.read_u8()? {
+            0 => MovieStart::PowerOn,
+            _ => {
+                let mut state = vec![0; reader.read_u32()? as usize];
+                reader.read_bytes(&mut state)?;
+                MovieStart::SaveState(state)
+            },
+        };
+        let mut inputs = vec![0; reader.read_u32()? as usize];
+        reader.read_bytes(&mut inputs)?;
+
+        Ok(Self {
+            rom_hash,
+            start,
+            inputs,
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), MovieError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self, MovieError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+}
+
+pub struct MoviePlayer {
+    pub movie: Movie,
+    pub frame: usize,
+}
+
+impl MoviePlayer {
+
+    pub fn new(movie: Movie) -> Self {
+        Self {
+            movie,
+            frame: 0,
+        }
+    }
+
+    /// Input for the next frame, None once the movie has ended
+    pub fn next_input(&mut self) -> Option<u8> {
+        let input = self.movie.inputs.get(self.frame).copied();
+        if input.is_some() {
+            self.frame += 1;
+        }
+        input
+    }
+
+}
@@ -0,0 +1,435 @@
+use std::fs;
+
+use crate::graphics::Pixel;
+
+pub const DEFAULT_PATH: &str = "palettes.cfg";
+/// Name of the palette picked from the rom's title
+pub const AUTO: &str = "title";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PaletteError {
+    InvalidLine(usize),
+    InvalidColor(String),
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaletteError::InvalidLine(line) => write!(f, "Line {} should look like: name = 4 or 12 colors in hex", line),
+            PaletteError::InvalidColor(text) => write!(f, "{} is not a color, use RRGGBB in hex", text),
+        }
+    }
+}
+
+const fn rgb(hex: u32) -> [u8; 3] {
+    [(hex >> 16) as u8, (hex >> 8) as u8, hex as u8]
+}
+
+const fn colors(hex: [u32; 4]) -> [[u8; 3]; 4] {
+    [rgb(hex[0]), rgb(hex[1]), rgb(hex[2]), rgb(hex[3])]
+}
+
+/*
+ *  Palette
+ *  Four rgb colors, lightest first, for the background and each of the two
+ *  sprite palettes. The screen only keeps shades for now so everything is
+ *  drawn with the background colors, the sprite ones are there for when it
+ *  knows which layer a pixel came from
+ */
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Palette {
+    pub name: String,
+    pub background: [[u8; 3]; 4],
+    pub sprites: [[[u8; 3]; 4]; 2],
+}
+
+impl Palette {
+
+    /// A palette with the same colors for every layer
+    pub fn new(name: &str, background: [[u8; 3]; 4]) -> Self {
+        Self {
+            name: name.to_string(),
+            background,
+            sprites: [background; 2],
+        }
+    }
+
+    pub fn green() -> Self {
+        Self::new("green", colors([0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F]))
+    }
+
+    pub fn pocket() -> Self {
+        Self::new("pocket", colors([0xE0DBCD, 0xA89F94, 0x706B66, 0x2B2B26]))
+    }
+
+    pub fn light() -> Self {
+        Self::new("light", colors([0x00B584, 0x009A71, 0x00694A, 0x004F3B]))
+    }
+
+    pub fn gray() -> Self {
+        Self::new("gray", colors([0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000]))
+    }
+
+    pub fn color(&self, pixel: Pixel) -> [u8; 3] {
+        self.background[pixel.shade()]
+    }
+
+    /// Parses a config line's colors: 4 for every layer, or 12 for background then each sprite palette
+    fn parse(name: &str, text: &str, line: usize) -> Result<Self, PaletteError> {
+        let colors = text.split_whitespace()
+            .map(|c| {
+                let hex = c.trim_start_matches('#');
+                match (hex.len(), u32::from_str_radix(hex, 16)) {
+                    (6, Ok(value)) => Ok(rgb(value)),
+                    _ => Err(PaletteError::InvalidColor(c.to_string())),
+                }
+            })
+            .collect::<Result<Vec<[u8; 3]>, PaletteError>>()?;
+        let four = |i: usize| [colors[i], colors[i + 1], colors[i + 2], colors[i + 3]];
+        match colors.len() {
+            4 => Ok(Self::new(name, four(0))),
+            12 => Ok(Self {
+                name: name.to_string(),
+                background: four(0),
+                sprites: [four(4), four(8)],
+            }),
+            _ => Err(PaletteError::InvalidLine(line)),
+        }
+    }
+
+}
+
+/*
+ *  Title Palettes
+ *  Colors picked by a checksum of the rom's title, for Nintendo games,
+ *  the way the gameboy color boot rom does it. Titles that share a
+ *  checksum are told apart by their fourth letter, anything else gets
+ *  the first palette
+ */
+/// The boot rom's colors, four per palette, in 15 bit BGR
+const TITLE_COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, // 0
+    0x639F, 0x4279, 0x15B0, 0x04CB, // 1
+    0x7FFF, 0x6E31, 0x454A, 0x0000, // 2
+    0x7FFF, 0x1BEF, 0x0200, 0x0000, // 3
+    0x7FFF, 0x421F, 0x1CF2, 0x0000, // 4
+    0x7FFF, 0x5294, 0x294A, 0x0000, // 5
+    0x7FFF, 0x03FF, 0x012F, 0x0000, // 6
+    0x7FFF, 0x03EF, 0x01D6, 0x0000, // 7
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000, // 8
+    0x7E74, 0x03FF, 0x0180, 0x0000, // 9
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B, // 10
+    0x7ED6, 0x4BFF, 0x2175, 0x0000, // 11
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, // 12
+    0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0, // 13
+    0x03ED, 0x7FFF, 0x255F, 0x0000, // 14
+    0x036A, 0x021F, 0x03FF, 0x7FFF, // 15
+    0x7FFF, 0x01DF, 0x0112, 0x0000, // 16
+    0x231F, 0x035F, 0x00F2, 0x0009, // 17
+    0x7FFF, 0x03EA, 0x011F, 0x0000, // 18
+    0x299F, 0x001A, 0x000C, 0x0000, // 19
+    0x7FFF, 0x027F, 0x001F, 0x0000, // 20
+    0x7FFF, 0x03E0, 0x0206, 0x0120, // 21
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00, // 22
+    0x7FFF, 0x3FFF, 0x7E00, 0x001F, // 23
+    0x7FFF, 0x03FF, 0x001F, 0x0000, // 24
+    0x03FF, 0x001F, 0x000C, 0x0000, // 25
+    0x7FFF, 0x033F, 0x0193, 0x0000, // 26
+    0x0000, 0x4200, 0x037F, 0x7FFF, // 27
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000, // 28
+    0x7FFF, 0x1BEF, 0x6180, 0x0000, // 29
+];
+
+/// Where the first sprite, second sprite and background colors start in TITLE_COLORS
+type Combination = (usize, usize, usize);
+
+/// A combination made of whole palettes
+const fn whole(sprite0: usize, sprite1: usize, background: usize) -> Combination {
+    (sprite0 * 4, sprite1 * 4, background * 4)
+}
+
+/// The boot rom's combinations, a few of which start halfway through a palette
+const COMBINATIONS: [Combination; 51] = [
+    whole(4, 4, 29),
+    whole(18, 18, 18),
+    whole(20, 20, 20),
+    whole(24, 24, 24),
+    whole(9, 9, 9),
+    whole(0, 0, 0),
+    whole(27, 27, 27),
+    whole(5, 5, 5),
+    whole(12, 12, 12),
+    whole(26, 26, 26),
+    whole(16, 8, 8), // 10
+    whole(4, 28, 28),
+    whole(4, 2, 2),
+    whole(3, 4, 4),
+    whole(4, 29, 29),
+    whole(28, 4, 28),
+    whole(2, 17, 2),
+    whole(16, 16, 8),
+    whole(4, 4, 7),
+    whole(4, 4, 18),
+    whole(4, 4, 20), // 20
+    whole(19, 19, 9),
+    (4 * 4 - 1, 4 * 4 - 1, 11 * 4),
+    whole(17, 17, 2),
+    whole(4, 4, 2),
+    whole(4, 4, 3),
+    whole(28, 28, 0),
+    whole(3, 3, 0),
+    whole(0, 0, 1),
+    whole(18, 22, 18),
+    whole(20, 22, 20), // 30
+    whole(24, 22, 24),
+    whole(16, 22, 8),
+    whole(17, 4, 13),
+    (28 * 4 - 1, 0, 14 * 4),
+    (28 * 4 - 1, 4 * 4, 15 * 4),
+    whole(19, 22, 9),
+    whole(16, 28, 10),
+    whole(4, 23, 28),
+    whole(17, 22, 2),
+    whole(4, 0, 2), // 40
+    whole(4, 28, 3),
+    whole(28, 3, 0),
+    whole(3, 28, 4),
+    whole(21, 28, 4),
+    whole(3, 28, 0),
+    whole(25, 3, 28),
+    whole(0, 28, 8),
+    whole(4, 3, 28),
+    whole(28, 3, 6),
+    whole(4, 28, 29), // 50
+];
+
+/// Title checksums and the combination each one picks, in the boot rom's order
+const TITLES: [(u8, u8); 94] = [
+    (0x00, 0),  // anything else
+    (0x88, 4),  // ALLEY WAY
+    (0x16, 5),  // YAKUMAN
+    (0x36, 35), // BASEBALL
+    (0xD1, 34), // TENNIS
+    (0xDB, 3),  // TETRIS
+    (0xF2, 31), // QIX
+    (0x3C, 15), // DR.MARIO
+    (0x8C, 10), // RADARMISSION
+    (0x92, 5),  // F1RACE
+    (0x3D, 19), // YOSSY NO TAMAGO
+    (0x5C, 36),
+    (0x58, 7),  // X
+    (0xC9, 37), // MARIOLAND2
+    (0x3E, 30), // YOSSY NO COOKIE
+    (0x70, 44), // ZELDA
+    (0x1D, 21),
+    (0x59, 32),
+    (0x69, 31), // TETRIS FLASH
+    (0x19, 20), // DONKEY KONG
+    (0x35, 5),  // MARIO'S PICROSS
+    (0xA8, 33),
+    (0x14, 13), // POKEMON RED
+    (0xAA, 14), // POKEMON GREEN
+    (0x75, 5),  // PICROSS 2
+    (0x95, 29), // YOSSY NO PANEPON
+    (0x99, 5),  // KIRAKIRA KIDS
+    (0x34, 18), // GAMEBOY GALLERY
+    (0x6F, 9),  // POCKETCAMERA
+    (0x15, 3),
+    (0xFF, 2),  // BALLOON KID
+    (0x97, 26), // KINGOFTHEZOO
+    (0x4B, 25), // DMG FOOTBALL
+    (0x90, 25), // WORLD CUP
+    (0x17, 41), // OTHELLO
+    (0x10, 42), // SUPER RC PRO-AM
+    (0x39, 26), // DYNABLASTER
+    (0xF7, 45), // BOY AND BLOB GB2
+    (0xF6, 42), // MEGAMAN
+    (0xA2, 45), // STAR WARS-NOA
+    (0x49, 36),
+    (0x4E, 38), // WAVERACE
+    (0x43, 26),
+    (0x68, 42), // LOLO2
+    (0xE0, 30), // YOSHI'S COOKIE
+    (0x8B, 41), // MYSTIC QUEST
+    (0xF0, 34),
+    (0xCE, 34), // TOPRANKINGTENNIS
+    (0x0C, 5),  // MANSELL
+    (0x29, 42), // MEGAMAN3
+    (0xE8, 6),  // SPACE INVADERS
+    (0xB7, 5),  // GAME&WATCH
+    (0x86, 33), // DONKEYKONGLAND95
+    (0x9A, 25), // ASTEROIDS/MISCMD
+    (0x52, 42), // STREET FIGHTER 2
+    (0x01, 42), // DEFENDER/JOUST
+    (0x9D, 40), // KILLERINSTINCT95
+    (0x71, 2),  // TETRIS BLAST
+    (0x9C, 16), // PINOCCHIO
+    (0xBD, 25),
+    (0x5D, 42), // BA.TOSHINDEN
+    (0x6D, 42), // NETTOU KOF 95
+    (0x67, 5),
+    (0x3F, 0),  // TETRIS PLUS
+    (0x6B, 39), // DONKEYKONGLAND 3
+    // From here on the fourth letter has to match too
+    (0xB3, 36),
+    (0x46, 22), // SUPER MARIOLAND
+    (0x28, 25), // GOLF
+    (0xA5, 6),  // SOLARSTRIKER
+    (0xC6, 32), // GBWARS
+    (0xD3, 12), // KAERUNOTAMENI
+    (0x27, 36),
+    (0x61, 11), // POKEMON BLUE
+    (0x18, 39), // DONKEYKONGLAND
+    (0x66, 18), // GAMEBOY GALLERY2
+    (0x6A, 39), // DONKEYKONGLAND 2
+    (0xBF, 24), // KID ICARUS
+    (0x0D, 31), // TETRIS2
+    (0xF4, 50),
+    (0xB3, 17), // MOGURANYA
+    (0x46, 46),
+    (0x28, 6),  // GALAGA&GALAXIAN
+    (0xA5, 27), // BT2RAGNAROKWORLD
+    (0xC6, 0),  // KEN GRIFFEY JR
+    (0xD3, 47),
+    (0x27, 41), // MAGNETIC SOCCER
+    (0x61, 41), // VEGAS STAKES
+    (0x18, 0),
+    (0x66, 0),  // MILLI/CENTI/PEDE
+    (0x6A, 19), // MARIO & YOSHI
+    (0xBF, 34), // SOCCER
+    (0x0D, 23), // POKEBOM
+    (0xF4, 18), // G&W GALLERY
+    (0xB3, 29), // TETRIS ATTACK
+];
+
+/// Index in TITLES of the first checksum that's shared with another title
+const FIRST_SHARED: usize = 65;
+
+/// Fourth title letter of each title from FIRST_SHARED on
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+/// Scales a 15 bit BGR color up to rgb
+fn bgr555(color: u16) -> [u8; 3] {
+    let channel = |shift: u16| {
+        let c = ((color >> shift) & 0x1F) as u8;
+        (c << 3) | (c >> 2)
+    };
+    [channel(0), channel(5), channel(10)]
+}
+
+/// Sum of the title bytes in the header, for Nintendo games
+pub fn title_checksum(rom: &[u8]) -> Option<u8> {
+    let header = rom.get(0x134..0x150)?;
+    let old_licensee = header[0x14B - 0x134];
+    let new_licensee = &header[0x144 - 0x134..0x146 - 0x134];
+    if old_licensee != 0x01 && !(old_licensee == 0x33 && new_licensee == b"01") {
+        return None;
+    }
+    Some(header[..0x10].iter().fold(0u8, |sum, b| sum.wrapping_add(*b)))
+}
+
+/// The combination the boot rom picks for a rom's title
+fn title_combination(rom: &[u8]) -> Combination {
+    let index = title_checksum(rom).and_then(|sum| {
+        let fourth = rom[0x134 + 3];
+        TITLES.iter().enumerate()
+            .find(|(i, (s, _))| *s == sum && (*i < FIRST_SHARED || FOURTH_LETTERS[i - FIRST_SHARED] == fourth))
+            .map(|(_, (_, index))| *index)
+    });
+    COMBINATIONS[index.unwrap_or(0) as usize]
+}
+
+/// The palette picked by a rom's title
+pub fn title_palette(rom: &[u8]) -> Palette {
+    let (sprite0, sprite1, background) = title_combination(rom);
+    let four = |start: usize| {
+        let c = &TITLE_COLORS[start..start + 4];
+        [bgr555(c[0]), bgr555(c[1]), bgr555(c[2]), bgr555(c[3])]
+    };
+    Palette {
+        name: AUTO.to_string(),
+        background: four(background),
+        sprites: [four(sprite0), four(sprite1)],
+    }
+}
+
+/*
+ *  Palettes
+ *  The built in palettes, the one picked from the rom, then any from a
+ *  config file with a line per palette, e.g.
+ *  "mint = e0f8d0 88c070 346856 081820". Twelve colors instead of four
+ *  give the sprites their own colors
+ */
+pub struct Palettes {
+    pub list: Vec<Palette>,
+    pub selected: usize,
+}
+
+impl Default for Palettes {
+    fn default() -> Self {
+        Self {
+            list: vec![Palette::green(), Palette::pocket(), Palette::light(), Palette::gray(), title_palette(&[])],
+            selected: 0,
+        }
+    }
+}
+
+impl Palettes {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Vec<Palette>, PaletteError> {
+        let mut palettes = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, colors) = line.split_once('=').ok_or(PaletteError::InvalidLine(i + 1))?;
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(PaletteError::InvalidLine(i + 1));
+            }
+            palettes.push(Palette::parse(name, colors, i + 1)?);
+        }
+        Ok(palettes)
+    }
+
+    /// Adds the palettes from a file, if there is one
+    pub fn load(&mut self, path: &str) -> Result<(), PaletteError> {
+        if let Ok(text) = fs::read_to_string(path) {
+            self.list.extend(Self::parse(&text)?);
+        }
+        Ok(())
+    }
+
+    /// Picks the title palette for a rom
+    pub fn set_rom(&mut self, rom: &[u8]) {
+        if let Some(palette) = self.list.iter_mut().find(|p| p.name == AUTO) {
+            *palette = title_palette(rom);
+        }
+    }
+
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.list.iter().position(|p| p.name == name) {
+            Some(i) => {
+                self.selected = i;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Selects the next palette and returns its name
+    pub fn cycle(&mut self) -> &str {
+        self.selected = (self.selected + 1) % self.list.len();
+        &self.current().name
+    }
+
+    pub fn current(&self) -> &Palette {
+        &self.list[self.selected]
+    }
+
+}
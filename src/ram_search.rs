@@ -0,0 +1,83 @@
+use crate::cpu::memory::Memory;
+
+/// The ram a game keeps its state in: cartridge ram, work ram and high ram
+pub const SEARCH_RANGES: [(u16, u16); 3] = [
+    (0xA000, 0xBFFF),
+    (0xC000, 0xDFFF),
+    (0xFF80, 0xFFFE),
+];
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Filter {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    Value(u8),
+}
+
+impl Filter {
+
+    fn keeps(&self, before: u8, now: u8) -> bool {
+        match *self {
+            Filter::Equal => now == before,
+            Filter::Changed => now != before,
+            Filter::Increased => now > before,
+            Filter::Decreased => now < before,
+            Filter::Value(value) => now == value,
+        }
+    }
+
+}
+
+/*
+ *  Ram Search
+ *  The classic cheat search: take a snapshot of ram, play a bit, then keep
+ *  only the addresses that changed the way the thing you're after did. Each
+ *  filter compares against the values from the last step, so a few rounds
+ *  usually narrow it down to a handful of addresses
+ */
+#[derive(Default)]
+pub struct RamSearch {
+    pub candidates: Vec<u16>,
+    previous: Vec<u8>,
+    pub watches: Vec<u16>,
+}
+
+impl RamSearch {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts over with every ram address as a candidate
+    pub fn reset(&mut self, memory: &Memory) {
+        self.candidates = SEARCH_RANGES.iter()
+            .flat_map(|(start, end)| *start..=*end)
+            .collect();
+        self.previous = self.candidates.iter().map(|a| memory.peek(*a)).collect();
+    }
+
+    /// Keeps the candidates that pass the filter, and remembers their values for the next one
+    pub fn filter(&mut self, memory: &Memory, filter: Filter) {
+        let (candidates, previous) = self.candidates.iter()
+            .zip(self.previous.iter())
+            .filter(|(addr, before)| filter.keeps(**before, memory.peek(**addr)))
+            .map(|(addr, _)| (*addr, memory.peek(*addr)))
+            .unzip();
+        self.candidates = candidates;
+        self.previous = previous;
+    }
+
+    /// Value a candidate had at the last step
+    pub fn previous(&self, index: usize) -> u8 {
+        self.previous[index]
+    }
+
+    pub fn watch(&mut self, addr: u16) {
+        if !self.watches.contains(&addr) {
+            self.watches.push(addr);
+        }
+    }
+
+}
@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+
+use crate::cpu::Cpu;
+use crate::savestate;
+
+pub const DEFAULT_INTERVAL: u32 = 4;
+pub const DEFAULT_CAPACITY: usize = 32 * 1024 * 1024;
+
+/*
+ *  Rewind buffer
+ *  Keeps the newest snapshot whole and every older one as a compressed
+ *  delta against the snapshot after it, so stepping back is one delta and
+ *  dropping the oldest snapshot is free
+ */
+pub struct RewindBuffer {
+    pub interval: u32,
+    pub capacity: usize,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+    frame_counter: u32,
+    rewind_counter: u32,
+}
+
+impl RewindBuffer {
+
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity,
+            newest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+            frame_counter: 0,
+            rewind_counter: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Memory used by the snapshots in bytes
+    pub fn used(&self) -> usize {
+        self.delta_bytes + self.newest.as_ref().map_or(0, |s| s.len())
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            if newest.len() == state.len() {
+                let delta = compress_delta(&newest, &state);
+                self.delta_bytes += delta.len();
+                self.deltas.push_back(delta);
+            } else {
+                self.deltas.clear();
+                self.delta_bytes = 0;
+            }
+        }
+        self.newest = Some(state);
+
+        while self.used() > self.capacity {
+            match self.deltas.pop_front() {
+                Some(delta) => self.delta_bytes -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Takes the newest snapshot, making the one before it the newest
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.delta_bytes -= delta.len();
+            let mut previous = newest.clone();
+            apply_delta(&mut previous, &delta);
+            self.newest = Some(previous);
+        }
+        Some(newest)
+    }
+
+    /// Called once per emulated frame, snapshots every `interval` frames
+    pub fn record_frame(&mut self, cpu: &Cpu) {
+        if self.frame_counter.is_multiple_of(self.interval) {
+            self.push(savestate::save(cpu));
+        }
+        self.frame_counter = self.frame_counter.wrapping_add(1);
+        self.rewind_counter = 0;
+    }
+
+    /*
+     *  Called once per displayed frame while rewinding
+     *  Steps back one snapshot every `interval` frames so rewinding plays
+     *  at the same speed the game did
+     *  Returns false once the oldest snapshot has been reached
+     */
+    pub fn rewind_frame(&mut self, cpu: &mut Cpu) -> bool {
+        let step = self.rewind_counter.is_multiple_of(self.interval);
+        self.rewind_counter = self.rewind_counter.wrapping_add(1);
+        self.frame_counter = 0;
+        if !step {
+            return !self.is_empty();
+        }
+
+        match self.pop() {
+            Some(state) => {
+                savestate::load(cpu, &state).unwrap();
+                if self.is_empty() {
+                    // Keep the oldest snapshot so holding rewind stays put on it
+                    self.push(state);
+                    return false;
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+}
+
+fn write_varint(out: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        out.push((val as u8) | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        val |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return val;
+        }
+        shift += 7;
+    }
+}
+
+/*
+ *  Delta compression
+ *  The xor of two snapshots is encoded as alternating runs of unchanged
+ *  bytes (just a length) and changed bytes (a length and the xor values)
+ *  Xor makes the same delta work in both directions
+ */
+pub fn compress_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < to.len() {
+        let start = i;
+        while i < to.len() && from[i] == to[i] {
+            i += 1;
+        }
+        write_varint(&mut out, i - start);
+
+        let start = i;
+        while i < to.len() && from[i] != to[i] {
+            i += 1;
+        }
+        write_varint(&mut out, i - start);
+        out.extend(from[start..i].iter().zip(&to[start..i]).map(|(a, b)| a ^ b));
+    }
+
+    out
+}
+
+pub fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut i = 0;
+
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let changed = read_varint(delta, &mut pos);
+        for byte in &mut state[i..i + changed] {
+            *byte ^= delta[pos];
+            pos += 1;
+        }
+        i += changed;
+    }
+}
@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::Path;
+
+use crate::cpu::Cpu;
+
+/*
+ *  Save states
+ *  A state is a small header (magic + version) followed by every component
+ *  writing itself out in a fixed order through the SaveState trait
+ */
+
+pub const STATE_MAGIC: &[u8; 4] = b"JBST";
+pub const STATE_VERSION: u8 = 2;
+pub const SLOT_COUNT: u8 = 10;
+
+#[derive(Debug)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnexpectedEnd,
+    InvalidSlot(u8),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "Not a save state file"),
+            StateError::UnsupportedVersion(v) => write!(f, "Unsupported save state version {} (expected {})", v, STATE_VERSION),
+            StateError::UnexpectedEnd => write!(f, "Save state ended unexpectedly"),
+            StateError::InvalidSlot(s) => write!(f, "Invalid save slot {} (0-{})", s, SLOT_COUNT - 1),
+            StateError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for StateError {
+    fn from(e: std::io::Error) -> Self {
+        StateError::Io(e)
+    }
+}
+
+/// Save State trait
+///
+/// Anything holding emulated machine state implements this so it can be
+/// written into and restored from a save state
+pub trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub fn write_u16(&mut self, val: u16) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, val: u32) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_bool(&mut self, val: bool) {
+        self.buf.push(val as u8);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+        }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        let val = *self.data.get(self.pos).ok_or(StateError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(val)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        let low = self.read_u8()?;
+        let high = self.read_u8()?;
+        Ok(u16::from_le_bytes([low, high]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        self.read_bytes(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_bytes(&mut self, out: &mut [u8]) -> Result<(), StateError> {
+        let end = self.pos + out.len();
+        if end > self.data.len() {
+            return Err(StateError::UnexpectedEnd);
+        }
+        out.copy_from_slice(&self.data[self.pos..end]);
+        self.pos = end;
+        Ok(())
+    }
+
+}
+
+/// Serializes the whole machine into a versioned binary blob
+pub fn save(cpu: &Cpu) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(STATE_MAGIC);
+    writer.write_u8(STATE_VERSION);
+    cpu.save_state(&mut writer);
+    writer.into_bytes()
+}
+
+fn read_header(reader: &mut StateReader) -> Result<(), StateError> {
+    let mut magic = [0; 4];
+    reader.read_bytes(&mut magic).map_err(|_| StateError::BadMagic)?;
+    if &magic != STATE_MAGIC {
+        return Err(StateError::BadMagic);
+    }
+
+    let version = reader.read_u8()?;
+    if version != STATE_VERSION {
+        return Err(StateError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+/// Builds a new machine from a blob made by `save`
+pub fn restore(data: &[u8]) -> Result<Cpu, StateError> {
+    let mut reader = StateReader::new(data);
+    read_header(&mut reader)?;
+
+    let mut cpu = Cpu::new();
+    cpu.load_state(&mut reader)?;
+    Ok(cpu)
+}
+
+/*
+ *  Restores a blob made by `save` into an existing machine
+ *  The blob is read into a new machine first so this one is left untouched
+ *  if it is invalid, and anything that isn't machine state (like watchpoints) is kept
+ */
+pub fn load(cpu: &mut Cpu, data: &[u8]) -> Result<(), StateError> {
+    cpu.take_state(restore(data)?);
+    Ok(())
+}
+
+/// Save slots live next to the rom, e.g. roms/game.gb -> roms/game.ss3
+pub fn slot_path(rom_path: &str, slot: u8) -> String {
+    Path::new(rom_path)
+        .with_extension(format!("ss{}", slot))
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub fn save_slot(cpu: &Cpu, rom_path: &str, slot: u8) -> Result<(), StateError> {
+    if slot >= SLOT_COUNT {
+        return Err(StateError::InvalidSlot(slot));
+    }
+    fs::write(slot_path(rom_path, slot), save(cpu))?;
+    Ok(())
+}
+
+pub fn load_slot(cpu: &mut Cpu, rom_path: &str, slot: u8) -> Result<(), StateError> {
+    if slot >= SLOT_COUNT {
+        return Err(StateError::InvalidSlot(slot));
+    }
+    let data = fs::read(slot_path(rom_path, slot))?;
+    load(cpu, &data)
+}
@@ -2,10 +2,20 @@ extern crate sdl2;
 
 use sdl2::{ Sdl, VideoSubsystem, video::Window };
 
-use self::{canvas::CanvasUtils, events::EventHandler};
+use self::{audio::AudioOutput, canvas::CanvasUtils, events::EventHandler, memory_viewer::MemoryViewer, vram_viewer::VramViewer, io_viewer::IoViewer, ram_search_viewer::RamSearchViewer};
+use jboy::cpu::Cpu;
 
 pub mod canvas;
+pub mod audio;
+pub mod filters;
 pub mod events;
+pub mod bindings;
+pub mod font;
+pub mod debug_window;
+pub mod memory_viewer;
+pub mod vram_viewer;
+pub mod io_viewer;
+pub mod ram_search_viewer;
 
 pub const PIXEL_SIZE: u32 = 3;
 
@@ -13,7 +23,12 @@ pub struct SdlHandles {
     pub sdl_context: Sdl,
     pub video_subsystem: VideoSubsystem,
     pub canvas: CanvasUtils,
+    pub audio: AudioOutput,
     pub events: EventHandler,
+    pub memory_viewer: MemoryViewer,
+    pub vram_viewer: VramViewer,
+    pub io_viewer: IoViewer,
+    pub ram_search: RamSearchViewer,
 }
 
 impl SdlHandles {
@@ -22,17 +37,48 @@ impl SdlHandles {
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
         let window = video_subsystem.window("Chip-8 Emulator", 160*PIXEL_SIZE, 144*PIXEL_SIZE)
+            .resizable()
             .build()
             .unwrap();
+        let main_window = window.id();
         let canvas = CanvasUtils::new(window);
-        let events = EventHandler::new(&sdl_context);
+        let events = EventHandler::new(&sdl_context, main_window);
+        let audio = AudioOutput::new(&sdl_context);
 
         Self {
             sdl_context,
             video_subsystem,
             canvas,
+            audio,
             events,
+            memory_viewer: MemoryViewer::new(),
+            vram_viewer: VramViewer::new(),
+            io_viewer: IoViewer::new(),
+            ram_search: RamSearchViewer::new(),
         }
     }
 
+    /*
+     *  Hands the debug windows their events and redraws them
+     *  Called once per frame after the main window has been updated
+     */
+    pub fn update_debug_windows(&mut self, cpu: &mut Cpu) {
+        for event in self.events.window_events.drain(..) {
+            self.memory_viewer.handle_event(&event, cpu);
+            self.vram_viewer.handle_event(&event);
+            self.io_viewer.handle_event(&event);
+            self.ram_search.handle_event(&event, cpu);
+        }
+        if let Some(addr) = self.ram_search.show.take() {
+            if self.memory_viewer.window_id().is_none() {
+                self.memory_viewer.toggle(&self.video_subsystem);
+            }
+            self.memory_viewer.show(addr);
+        }
+        self.memory_viewer.draw(cpu);
+        self.vram_viewer.draw(cpu, self.canvas.palettes.current());
+        self.io_viewer.draw(cpu);
+        self.ram_search.draw(cpu);
+    }
+
 }
@@ -0,0 +1,59 @@
+extern crate sdl2;
+
+use sdl2::Sdl;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+
+use jboy::frontend::AudioSink;
+use jboy::gameboy::{SAMPLE_RATE, CHANNELS};
+use jboy::speed;
+
+/// A tenth of a second in bytes, more than that waiting to play is dropped
+const MAX_QUEUED: u32 = SAMPLE_RATE / 10 * CHANNELS as u32 * 2;
+
+/*
+ *  Audio Output
+ *  Queues samples on the default device. Without a sound device the
+ *  emulator carries on silently. Sound is resampled to the speed the game
+ *  runs at, and if the device still falls behind what's waiting is dropped
+ *  instead of piling up as lag
+ */
+pub struct AudioOutput {
+    queue: Option<AudioQueue<i16>>,
+    /// Speed multiplier the frames are running at
+    pub speed: f64,
+}
+
+impl AudioOutput {
+
+    pub fn new(sdl_context: &Sdl) -> Self {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(CHANNELS as u8),
+            samples: None,
+        };
+        let queue = sdl_context.audio().and_then(|audio| audio.open_queue::<i16, _>(None, &desired));
+        match queue {
+            Ok(queue) => {
+                queue.resume();
+                Self { queue: Some(queue), speed: 1.0 }
+            },
+            Err(e) => {
+                println!("No sound: {}", e);
+                Self { queue: None, speed: 1.0 }
+            },
+        }
+    }
+
+}
+
+impl AudioSink for AudioOutput {
+    fn queue(&mut self, samples: &[i16]) {
+        if let Some(queue) = &self.queue {
+            if queue.size() < MAX_QUEUED {
+                if let Err(e) = queue.queue_audio(&speed::resample(samples, self.speed)) {
+                    println!("Failed to queue audio: {}", e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+extern crate sdl2;
+
+use std::fs;
+
+use sdl2::{controller::Button, keyboard::Keycode};
+
+use super::events::GBButton;
+
+pub const DEFAULT_PATH: &str = "bindings.cfg";
+/// How far a stick has to lean before it counts as a dpad press, out of 32767
+pub const DEFAULT_THRESHOLD: i16 = 16000;
+
+const DEFAULTS: &str = "\
+a = Z, pad:a
+b = X, pad:b
+select = Right Shift, pad:back
+start = Return, Keypad Enter, pad:start
+up = Up, pad:dpup
+down = Down, pad:dpdown
+left = Left, pad:dpleft
+right = Right, pad:dpright
+";
+
+/// Keys the hotkeys use, other than the number row which picks save slots
+const HOTKEYS: [Keycode; 24] = [
+    Keycode::F1, Keycode::F2, Keycode::F3, Keycode::F4, Keycode::F5, Keycode::F6,
+    Keycode::F7, Keycode::F8, Keycode::F9, Keycode::F10, Keycode::F11, Keycode::F12,
+    Keycode::Backspace, Keycode::Tab, Keycode::Space, Keycode::Period, Keycode::Backquote,
+    Keycode::Q, Keycode::W, Keycode::Equals, Keycode::Minus, Keycode::G,
+    Keycode::PrintScreen, Keycode::Insert,
+];
+
+/// Bound keys are handled before hotkeys, so binding one of these would switch its hotkey off
+pub fn is_hotkey(key: Keycode) -> bool {
+    HOTKEYS.contains(&key) || (Keycode::Num0 as i32..=Keycode::Num9 as i32).contains(&(key as i32))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BindingError {
+    InvalidLine(usize),
+    UnknownButton(String),
+    UnknownKey(String),
+    UnknownPadButton(String),
+    HotkeyTaken(String),
+    Io(String),
+}
+
+impl std::fmt::Display for BindingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindingError::InvalidLine(line) => write!(f, "Line {} should look like: button = key, pad:button", line),
+            BindingError::UnknownButton(name) => write!(f, "{} is not a gameboy button", name),
+            BindingError::UnknownKey(name) => write!(f, "{} is not a key", name),
+            BindingError::UnknownPadButton(name) => write!(f, "{} is not a gamepad button", name),
+            BindingError::HotkeyTaken(name) => write!(f, "{} is a hotkey and can't press a button", name),
+            BindingError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Input {
+    Key(Keycode),
+    Pad(Button),
+}
+
+impl Input {
+
+    fn parse(text: &str) -> Result<Self, BindingError> {
+        let input = match text.strip_prefix("pad:") {
+            Some(name) => Button::from_string(name)
+                .map(Input::Pad)
+                .ok_or_else(|| BindingError::UnknownPadButton(name.to_string()))?,
+            None => Keycode::from_name(text)
+                .map(Input::Key)
+                .ok_or_else(|| BindingError::UnknownKey(text.to_string()))?,
+        };
+        input.check()?;
+        Ok(input)
+    }
+
+    /// Fails for keys the hotkeys need
+    fn check(&self) -> Result<(), BindingError> {
+        match self {
+            Input::Key(key) if is_hotkey(*key) => Err(BindingError::HotkeyTaken(key.name())),
+            _ => Ok(()),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Input::Key(key) => key.name(),
+            Input::Pad(button) => format!("pad:{}", button.string()),
+        }
+    }
+
+}
+
+/*
+ *  Bindings
+ *  Which keys and gamepad buttons press each gameboy button, read from a
+ *  config file with a line per button, e.g. "start = Return, pad:start".
+ *  Key names are SDL's, and "threshold = 16000" sets how far the left
+ *  stick has to lean to press the dpad. Hotkeys can't be bound
+ */
+pub struct Bindings {
+    pub inputs: Vec<(GBButton, Input)>,
+    pub threshold: i16,
+}
+
+impl Bindings {
+
+    pub fn new() -> Self {
+        Self::parse(DEFAULTS).unwrap()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, BindingError> {
+        let mut bindings = Self {
+            inputs: Vec::new(),
+            threshold: DEFAULT_THRESHOLD,
+        };
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, inputs) = line.split_once('=').ok_or(BindingError::InvalidLine(i + 1))?;
+            let name = name.trim();
+            if name == "threshold" {
+                bindings.threshold = inputs.trim().parse().ok()
+                    .filter(|t: &i16| *t > 0)
+                    .ok_or(BindingError::InvalidLine(i + 1))?;
+                continue;
+            }
+            let button = GBButton::from_name(name).ok_or_else(|| BindingError::UnknownButton(name.to_string()))?;
+            for input in inputs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                bindings.inputs.push((button, Input::parse(input)?));
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Loads bindings from a file, or the defaults if there isn't one
+    pub fn load(path: &str) -> Result<Self, BindingError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(_) => Ok(Self::new()),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), BindingError> {
+        fs::write(path, self.to_text()).map_err(|e| BindingError::Io(e.to_string()))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for button in GBButton::ALL {
+            let names: Vec<String> = self.inputs.iter()
+                .filter(|(b, _)| *b == button)
+                .map(|(_, input)| input.name())
+                .collect();
+            text.push_str(&format!("{} = {}\n", button.name(), names.join(", ")));
+        }
+        text.push_str(&format!("threshold = {}\n", self.threshold));
+        text
+    }
+
+    pub fn button_for(&self, input: Input) -> Option<GBButton> {
+        self.inputs.iter().find(|(_, i)| *i == input).map(|(b, _)| *b)
+    }
+
+    /*
+     *  Makes an input press a button. Whatever of the same kind (key or pad
+     *  button) was bound to it before is dropped, and the input stops
+     *  pressing any other button. Hotkeys can't be bound
+     */
+    pub fn rebind(&mut self, button: GBButton, input: Input) -> Result<(), BindingError> {
+        input.check()?;
+        let same_kind = |i: &Input| matches!((i, &input), (Input::Key(_), Input::Key(_)) | (Input::Pad(_), Input::Pad(_)));
+        self.inputs.retain(|(b, i)| *i != input && !(*b == button && same_kind(i)));
+        self.inputs.push((button, input));
+        Ok(())
+    }
+
+    /// Dpad buttons the left stick is leaning towards
+    pub fn stick_mask(&self, x: i16, y: i16) -> u8 {
+        let mut mask = 0;
+        if x <= -self.threshold { mask |= GBButton::Left.mask(); }
+        if x >= self.threshold { mask |= GBButton::Right.mask(); }
+        if y <= -self.threshold { mask |= GBButton::Up.mask(); }
+        if y >= self.threshold { mask |= GBButton::Down.mask(); }
+        mask
+    }
+
+}
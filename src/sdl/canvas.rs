@@ -1,20 +1,52 @@
 extern crate sdl2;
 
-use sdl2::{video::Window, pixels::Color, rect::Rect};
+use sdl2::{pixels::{Color, PixelFormatEnum}, rect::Rect};
+use sdl2::render::{Canvas, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
 
-use super::PIXEL_SIZE;
+use jboy::capture::Frame;
+use jboy::frontend::{Framebuffer, VideoSink};
+use jboy::graphics::{Pixel, SCREEN_WIDTH, SCREEN_HEIGHT};
+use jboy::palettes::Palettes;
+use super::filters::{Filter, Ghosting};
 
-#[derive(PartialEq, Eq, Clone, Copy)]
-pub enum Pixel {
-    LightestGreen,
-    LightGreen,
-    DarkGreen,
-    DarkestGreen,
+pub const WIDTH: u32 = SCREEN_WIDTH as u32;
+pub const HEIGHT: u32 = SCREEN_HEIGHT as u32;
+/*
+ *  Viewport
+ *  Where the screen goes in a window of a given size: as large as fits
+ *  while keeping the gameboy's 10:9 shape, optionally only at whole
+ *  multiples of 160x144 so every pixel is the same size, and centered
+ */
+pub fn viewport(window_width: u32, window_height: u32, integer_scaling: bool) -> Rect {
+    let scale = (window_width as f64 / WIDTH as f64).min(window_height as f64 / HEIGHT as f64);
+    let scale = if integer_scaling { scale.floor().max(1.0) } else { scale };
+    let (width, height) = ((WIDTH as f64 * scale) as u32, (HEIGHT as f64 * scale) as u32);
+    Rect::new(
+        (window_width as i32 - width as i32) / 2,
+        (window_height as i32 - height as i32) / 2,
+        width.max(1),
+        height.max(1),
+    )
 }
 
+/*
+ *  Canvas Utils
+ *  Owns the main window. Every present maps pixel_data to colors with the
+ *  selected palette, blends in the last frame if ghosting is on, runs the
+ *  selected filter and uploads the result into a streaming texture the size
+ *  of the filtered frame, which the gpu then stretches into the viewport.
+ *  The texture borrows the creator kept next to the canvas and is freed as
+ *  soon as it's been drawn
+ */
 pub struct CanvasUtils {
-    handle: sdl2::render::Canvas<Window>,
+    handle: Canvas<Window>,
+    texture_creator: TextureCreator<WindowContext>,
     pub pixel_data: [[Pixel; 160]; 144],
+    pub filter: Filter,
+    pub integer_scaling: bool,
+    pub palettes: Palettes,
+    pub ghosting: Ghosting,
 }
 
 impl CanvasUtils {
@@ -23,41 +55,83 @@ impl CanvasUtils {
         let handle = window.into_canvas()
             .build()
             .unwrap();
+        let texture_creator = handle.texture_creator();
 
         let pixel_data = [[Pixel::LightestGreen; 160]; 144];
 
         Self {
             handle,
+            texture_creator,
             pixel_data,
+            filter: Filter::Nearest,
+            integer_scaling: false,
+            palettes: Palettes::new(),
+            ghosting: Ghosting::new(),
         }
     }
 
     pub fn clear_screen(&mut self) {
-        self.handle.set_draw_color(Color::RGB(0, 0, 0));
-        self.handle.clear();
     }
 
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.handle.window_mut();
+        let state = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(e) = window.set_fullscreen(state) {
+            println!("Failed to change fullscreen: {}", e);
+        }
+    }
+
+    pub fn cycle_filter(&mut self) {
+        self.filter = self.filter.next();
+        println!("Filter: {}", self.filter.name());
+    }
+
+    pub fn toggle_integer_scaling(&mut self) {
+        self.integer_scaling = !self.integer_scaling;
+        println!("Integer scaling {}", if self.integer_scaling { "on" } else { "off" });
+    }
+
+    /// The current frame as colors, row by row
+    pub fn frame(&self) -> Vec<Color> {
+        let palette = self.palettes.current();
+        self.pixel_data.iter()
+            .flatten()
+            .map(|p| {
+                let [r, g, b] = p.color(palette);
+                Color::RGB(r, g, b)
+            })
+            .collect()
+    }
+
+    /// The current frame at native resolution, for screenshots and recordings
+    pub fn capture(&self) -> Frame {
+        Frame::from_pixels(&self.pixel_data, self.palettes.current())
+    }
 
     pub fn update(&mut self) {
-        for y in 0..self.pixel_data.len() {
-            for x in 0..self.pixel_data[y].len() {
-                
-                match self.pixel_data[y][x] {
-                    Pixel::LightestGreen => { self.handle.set_draw_color(Color::RGB(0x9B, 0xBC, 0x0F))},
-                    Pixel::LightGreen => { self.handle.set_draw_color(Color::RGB(0x8B, 0xAC, 0x0F))},
-                    Pixel::DarkGreen => { self.handle.set_draw_color(Color::RGB(0x30, 0x62, 0x30))},
-                    Pixel::DarkestGreen => { self.handle.set_draw_color(Color::RGB(0x0F, 0x38, 0x0F))},
-                }
-                
-                self.handle.fill_rect(Rect::new(
-                        (x as u32*PIXEL_SIZE) as i32, 
-                        (y as u32*PIXEL_SIZE) as i32,
-                        PIXEL_SIZE,
-                        PIXEL_SIZE
-                    )).unwrap();
-
-            }
-        }
+        let scale = self.filter.scale() as u32;
+        let frame = self.ghosting.apply(self.frame());
+        let frame = self.filter.apply(&frame, WIDTH as usize);
+        self.clear_screen();
+        let mut texture = self.texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, WIDTH * scale, HEIGHT * scale)
+            .unwrap();
+
+        let bytes: Vec<u8> = frame.iter().flat_map(|c| [c.r, c.g, c.b]).collect();
+        texture.update(None, &bytes, (WIDTH * scale) as usize * 3).unwrap();
+
+        let (width, height) = self.handle.output_size().unwrap();
+        self.handle.copy(&texture, None, viewport(width, height, self.integer_scaling)).unwrap();
         self.handle.present();
     }
 }
+
+impl VideoSink for CanvasUtils {
+    fn present(&mut self, frame: &Framebuffer) {
+        self.pixel_data = *frame;
+        self.update();
+    }
+}
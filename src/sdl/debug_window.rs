@@ -0,0 +1,144 @@
+extern crate sdl2;
+
+use sdl2::{VideoSubsystem, event::Event, pixels::Color, rect::Rect, render::Canvas, video::Window};
+
+use jboy::graphics::Pixel;
+use jboy::palettes::Palette;
+use super::font::{self, GLYPH_WIDTH, GLYPH_HEIGHT};
+
+/// Size of a font pixel in the debug windows
+pub const TEXT_SCALE: u32 = 2;
+/// Width and height of a character cell, glyphs plus a pixel of spacing
+pub const CHAR_WIDTH: u32 = (GLYPH_WIDTH + 1) * TEXT_SCALE;
+pub const CHAR_HEIGHT: u32 = (GLYPH_HEIGHT + 1) * TEXT_SCALE;
+
+pub const BACKGROUND: Color = Color::RGB(0x10, 0x10, 0x18);
+pub const TEXT: Color = Color::RGB(0xD0, 0xD0, 0xD0);
+pub const DIM: Color = Color::RGB(0x70, 0x70, 0x80);
+pub const HIGHLIGHT: Color = Color::RGB(0xFF, 0x60, 0x40);
+pub const ACCENT: Color = Color::RGB(0x60, 0xC0, 0xFF);
+
+/// The window an event belongs to, if it belongs to one
+pub fn event_window(event: &Event) -> Option<u32> {
+    match event {
+        Event::KeyDown { window_id, .. } |
+        Event::KeyUp { window_id, .. } |
+        Event::Window { window_id, .. } |
+        Event::MouseButtonDown { window_id, .. } |
+        Event::MouseButtonUp { window_id, .. } |
+        Event::MouseMotion { window_id, .. } |
+        Event::MouseWheel { window_id, .. } |
+        Event::TextInput { window_id, .. } => Some(*window_id),
+        _ => None,
+    }
+}
+
+/*
+ *  Debug Window
+ *  A plain window with helpers for drawing text and boxes, shared by the
+ *  memory, vram and register viewers
+ */
+pub struct DebugWindow {
+    canvas: Canvas<Window>,
+    pub id: u32,
+}
+
+impl DebugWindow {
+
+    pub fn new(video: &VideoSubsystem, title: &str, width: u32, height: u32) -> Self {
+        let window = video.window(title, width, height)
+            .build()
+            .unwrap();
+        let id = window.id();
+        let canvas = window.into_canvas()
+            .build()
+            .unwrap();
+
+        Self {
+            canvas,
+            id,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.canvas.set_draw_color(BACKGROUND);
+        self.canvas.clear();
+    }
+
+    pub fn present(&mut self) {
+        self.canvas.present();
+    }
+
+    pub fn fill(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color) {
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rect(Rect::new(x, y, width, height)).unwrap();
+    }
+
+    pub fn outline(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color) {
+        self.canvas.set_draw_color(color);
+        self.canvas.draw_rect(Rect::new(x, y, width, height)).unwrap();
+    }
+
+    /// Limits drawing to an area, or lifts the limit with None
+    pub fn clip(&mut self, area: Option<Rect>) {
+        self.canvas.set_clip_rect(area);
+    }
+
+    /// Fills many same sized squares in one color, for drawing pixel art
+    pub fn fill_many(&mut self, rects: &[Rect], color: Color) {
+        if rects.is_empty() {
+            return;
+        }
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rects(rects).unwrap();
+    }
+
+    /*
+     *  Draws gameboy pixels row by row with the top left corner at x, y
+     *  None is left transparent. Pixels are grouped by shade so each shade
+     *  is a single draw call
+     */
+    pub fn pixels(&mut self, x: i32, y: i32, scale: u32, width: usize, pixels: &[Option<Pixel>], palette: &Palette) {
+        let shades = [Pixel::LightestGreen, Pixel::LightGreen, Pixel::DarkGreen, Pixel::DarkestGreen];
+        let mut rects: [Vec<Rect>; 4] = Default::default();
+        for (i, pixel) in pixels.iter().enumerate() {
+            if let Some(pixel) = pixel {
+                let shade = shades.iter().position(|s| s == pixel).unwrap();
+                rects[shade].push(Rect::new(
+                    x + ((i % width) as u32 * scale) as i32,
+                    y + ((i / width) as u32 * scale) as i32,
+                    scale,
+                    scale,
+                ));
+            }
+        }
+        for (shade, rects) in shades.iter().zip(rects.iter()) {
+            let [r, g, b] = shade.color(palette);
+            self.fill_many(rects, Color::RGB(r, g, b));
+        }
+    }
+
+    /// Draws text with its top left corner at a character cell
+    pub fn text(&mut self, column: u32, row: u32, text: &str, color: Color) {
+        let mut rects = Vec::new();
+        for (i, c) in text.chars().enumerate() {
+            let x = (column + i as u32) * CHAR_WIDTH;
+            let y = row * CHAR_HEIGHT;
+            let rows = font::glyph(c).or_else(|| font::glyph('?')).unwrap();
+            for (gy, bits) in rows.iter().enumerate() {
+                for gx in 0..GLYPH_WIDTH {
+                    if bits & (0b100 >> gx) != 0 {
+                        rects.push(Rect::new(
+                            (x + gx * TEXT_SCALE) as i32,
+                            (y + gy as u32 * TEXT_SCALE) as i32,
+                            TEXT_SCALE,
+                            TEXT_SCALE,
+                        ));
+                    }
+                }
+            }
+        }
+        self.fill_many(&rects, color);
+    }
+
+}
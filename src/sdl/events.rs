@@ -1,13 +1,38 @@
 extern crate sdl2;
 
-use sdl2::{event::Event, EventPump, Sdl, keyboard::Keycode};
+use sdl2::{event::{Event, WindowEvent}, EventPump, GameControllerSubsystem, Sdl, keyboard::Keycode};
+use sdl2::controller::{Axis, GameController};
 
+use jboy::frontend::InputSource;
+use jboy::joypad;
+use super::bindings::{Bindings, Input};
+use super::debug_window;
+
+/*
+ *  Event Handler
+ *  Keeps track of which keys and gamepad buttons are held down between
+ *  frames, turns them into gameboy buttons through the bindings, and
+ *  collects hotkeys for the main loop
+ */
 pub struct EventHandler {
     event_pump: EventPump,
-    pub events: Vec<GBButton>,
+    controllers: GameControllerSubsystem,
+    pads: Vec<GameController>,
+    held: Vec<Input>,
+    stick: (i16, i16),
+    pub bindings: Bindings,
+    pub bindings_path: String,
+    /// The button waiting for a new input, as an index into GBButton::ALL
+    rebinding: Option<usize>,
+    pub hotkeys: Vec<Hotkey>,
+    pub window_events: Vec<Event>,
+    pub rewinding: bool,
+    pub fast_forward: bool,
+    pub quit: bool,
+    main_window: u32,
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum GBButton {
     A,
     B,
@@ -19,6 +44,77 @@ pub enum GBButton {
     Right,
 }
 
+impl GBButton {
+
+    pub const ALL: [GBButton; 8] = [
+        GBButton::A,
+        GBButton::B,
+        GBButton::Select,
+        GBButton::Start,
+        GBButton::Up,
+        GBButton::Down,
+        GBButton::Left,
+        GBButton::Right,
+    ];
+
+    /// Name used in the bindings file
+    pub fn name(&self) -> &'static str {
+        match self {
+            GBButton::A => "a",
+            GBButton::B => "b",
+            GBButton::Select => "select",
+            GBButton::Start => "start",
+            GBButton::Up => "up",
+            GBButton::Down => "down",
+            GBButton::Left => "left",
+            GBButton::Right => "right",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|b| b.name().eq_ignore_ascii_case(name)).copied()
+    }
+
+    pub fn mask(&self) -> u8 {
+        match self {
+            GBButton::A => joypad::A,
+            GBButton::B => joypad::B,
+            GBButton::Select => joypad::SELECT,
+            GBButton::Start => joypad::START,
+            GBButton::Up => joypad::UP,
+            GBButton::Down => joypad::DOWN,
+            GBButton::Left => joypad::LEFT,
+            GBButton::Right => joypad::RIGHT,
+        }
+    }
+
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum Hotkey {
+    SaveState,
+    LoadState,
+    SelectSlot(u8),
+    StopMovie,
+    ToggleTrace,
+    ToggleCheats,
+    MemoryViewer,
+    VramViewer,
+    IoViewer,
+    RamSearch,
+    TogglePause,
+    FrameAdvance,
+    ToggleSlowMotion,
+    ToggleAutofire(GBButton),
+    ToggleFullscreen,
+    CycleFilter,
+    CyclePalette,
+    ToggleGhosting,
+    Screenshot,
+    ToggleRecording,
+    ToggleIntegerScaling,
+}
+
 macro_rules! keycode_object{
     ($code:pat) => {
         Event::KeyDown {
@@ -30,45 +126,228 @@ macro_rules! keycode_object{
 
 impl EventHandler {
     
-    pub fn new(sdl_context: &Sdl) -> Self {
+    pub fn new(sdl_context: &Sdl, main_window: u32) -> Self {
         let event_pump = sdl_context.event_pump().unwrap();
+        // Pads already plugged in show up as ControllerDeviceAdded events too
+        let controllers = sdl_context.game_controller().unwrap();
 
         Self {
             event_pump,
-            events: Vec::new(),
+            controllers,
+            pads: Vec::new(),
+            held: Vec::new(),
+            stick: (0, 0),
+            bindings: Bindings::new(),
+            bindings_path: String::from(super::bindings::DEFAULT_PATH),
+            rebinding: None,
+            hotkeys: Vec::new(),
+            window_events: Vec::new(),
+            rewinding: false,
+            fast_forward: false,
+            quit: false,
+            main_window,
         }
     }
 
-    pub fn is_pressed(&self, button: GBButton) -> bool {
-        self.events.contains(&button)
+    /// Held buttons as a joypad bitmask
+    pub fn joypad_state(&self) -> u8 {
+        let held = self.held.iter()
+            .filter_map(|input| self.bindings.button_for(*input))
+            .fold(0, |mask, button| mask | button.mask());
+        held | self.bindings.stick_mask(self.stick.0, self.stick.1)
     }
 
-    pub fn update_events(&mut self) {
-        self.events.clear();
-
-        for event in self.event_pump.poll_iter() {
-
-            let found_code: Option<GBButton> = match event {
-                keycode_object!(Keycode::Z) => Some(GBButton::A),
-                keycode_object!(Keycode::X) => Some(GBButton::B),
-                keycode_object!(Keycode::Up) => Some(GBButton::Up),
-                keycode_object!(Keycode::Down) => Some(GBButton::Down),
-                keycode_object!(Keycode::Left) => Some(GBButton::Left),
-                keycode_object!(Keycode::Right) => Some(GBButton::Right),
-                keycode_object!(Keycode::KpEnter) => Some(GBButton::Start),
-                keycode_object!(Keycode::RShift) => Some(GBButton::Select),
-
-                Event::Quit { .. } => {
-                    std::process::exit(0);
+    /// Asks for a new input for every button in turn, F8 starts it
+    pub fn start_rebinding(&mut self) {
+        self.held.clear();
+        self.rebinding = Some(0);
+        println!("Rebinding, press Escape to stop. Press a key or pad button for {}", GBButton::ALL[0].name());
+    }
+
+    fn rebind(&mut self, input: Input) {
+        let index = match self.rebinding {
+            Some(index) => index,
+            None => return,
+        };
+        if let Err(e) = self.bindings.rebind(GBButton::ALL[index], input) {
+            println!("{}, press another for {}", e, GBButton::ALL[index].name());
+            return;
+        }
+
+        if index + 1 < GBButton::ALL.len() {
+            self.rebinding = Some(index + 1);
+            println!("Press a key or pad button for {}", GBButton::ALL[index + 1].name());
+        } else {
+            self.rebinding = None;
+            match self.bindings.save(&self.bindings_path) {
+                Ok(()) => println!("Saved bindings to {}", self.bindings_path),
+                Err(e) => println!("Failed to save bindings: {}", e),
+            }
+        }
+    }
+
+    /// Handles input bound to gameboy buttons, returns false for events that aren't
+    fn handle_input(&mut self, event: &Event) -> bool {
+        let (input, down) = match *event {
+            Event::KeyDown { keycode: Some(key), repeat, .. } => {
+                if self.rebinding.is_some() {
+                    match key {
+                        Keycode::Escape => {
+                            self.rebinding = None;
+                            println!("Stopped rebinding");
+                        },
+                        _ if !repeat => self.rebind(Input::Key(key)),
+                        _ => {},
+                    }
+                    return true;
+                }
+                (Input::Key(key), true)
+            },
+            Event::KeyUp { keycode: Some(key), .. } => (Input::Key(key), false),
+            Event::ControllerButtonDown { button, .. } if self.rebinding.is_some() => {
+                self.rebind(Input::Pad(button));
+                return true;
+            },
+            Event::ControllerButtonDown { button, .. } => (Input::Pad(button), true),
+            Event::ControllerButtonUp { button, .. } => (Input::Pad(button), false),
+            Event::ControllerAxisMotion { axis: Axis::LeftX, value, .. } => {
+                self.stick.0 = value;
+                return true;
+            },
+            Event::ControllerAxisMotion { axis: Axis::LeftY, value, .. } => {
+                self.stick.1 = value;
+                return true;
+            },
+            Event::ControllerDeviceAdded { which, .. } => {
+                match self.controllers.open(which) {
+                    Ok(pad) => {
+                        println!("Connected {}", pad.name());
+                        self.pads.push(pad);
+                    },
+                    Err(e) => println!("Failed to open gamepad: {}", e),
                 }
+                return true;
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.pads.retain(|pad| pad.instance_id() != which);
+                self.held.retain(|input| matches!(input, Input::Key(_)));
+                self.stick = (0, 0);
+                println!("Gamepad disconnected");
+                return true;
+            },
+            // Releases are lost while another window has focus
+            Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                self.held.clear();
+                return false;
+            },
+            _ => return false,
+        };
+
+        if self.bindings.button_for(input).is_none() {
+            return false;
+        }
+        if down {
+            if !self.held.contains(&input) {
+                self.held.push(input);
+            }
+        } else {
+            self.held.retain(|i| *i != input);
+        }
+        true
+    }
+
+    pub fn update_events(&mut self) {
+        self.hotkeys.clear();
+
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
+
+            // Anything aimed at a debug window is left for that window to handle
+            if debug_window::event_window(&event).is_some_and(|id| id != self.main_window) {
+                self.window_events.push(event);
+                continue;
+            }
+
+            // Bound keys press gameboy buttons instead of being hotkeys
+            if self.handle_input(&event) {
+                continue;
+            }
 
-                _ => {None}
-            }; 
+            match event {
+                keycode_object!(Keycode::Backspace) => {
+                    self.rewinding = true;
+                },
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => {
+                    self.rewinding = false;
+                },
+                // Toggles ignore key repeat, holding them would flip back and forth
+                keycode_object!(Keycode::Tab) => self.fast_forward = true,
+                Event::KeyUp { keycode: Some(Keycode::Tab), .. } => self.fast_forward = false,
+                Event::KeyDown { keycode: Some(Keycode::Space), repeat: false, .. } => self.hotkeys.push(Hotkey::TogglePause),
+                keycode_object!(Keycode::Period) => self.hotkeys.push(Hotkey::FrameAdvance),
+                Event::KeyDown { keycode: Some(Keycode::Backquote), repeat: false, .. } => self.hotkeys.push(Hotkey::ToggleSlowMotion),
+                Event::KeyDown { keycode: Some(Keycode::Q), repeat: false, .. } => self.hotkeys.push(Hotkey::ToggleAutofire(GBButton::A)),
+                Event::KeyDown { keycode: Some(Keycode::W), repeat: false, .. } => self.hotkeys.push(Hotkey::ToggleAutofire(GBButton::B)),
+                Event::KeyDown { keycode: Some(Keycode::Equals), repeat: false, .. } => self.hotkeys.push(Hotkey::ToggleIntegerScaling),
+                keycode_object!(Keycode::F11) => self.hotkeys.push(Hotkey::ToggleFullscreen),
+                keycode_object!(Keycode::F12) => self.hotkeys.push(Hotkey::CycleFilter),
+                Event::KeyDown { keycode: Some(Keycode::Minus), repeat: false, .. } => self.hotkeys.push(Hotkey::CyclePalette),
+                Event::KeyDown { keycode: Some(Keycode::G), repeat: false, .. } => self.hotkeys.push(Hotkey::ToggleGhosting),
+                Event::KeyDown { keycode: Some(Keycode::PrintScreen), repeat: false, .. } => self.hotkeys.push(Hotkey::Screenshot),
+                Event::KeyDown { keycode: Some(Keycode::Insert), repeat: false, .. } => self.hotkeys.push(Hotkey::ToggleRecording),
+                keycode_object!(Keycode::F1) => {
+                    self.hotkeys.push(Hotkey::MemoryViewer);
+                },
+                keycode_object!(Keycode::F2) => {
+                    self.hotkeys.push(Hotkey::VramViewer);
+                },
+                keycode_object!(Keycode::F3) => {
+                    self.hotkeys.push(Hotkey::IoViewer);
+                },
+                keycode_object!(Keycode::F4) => {
+                    self.hotkeys.push(Hotkey::ToggleCheats);
+                },
+                keycode_object!(Keycode::F5) => {
+                    self.hotkeys.push(Hotkey::SaveState);
+                },
+                keycode_object!(Keycode::F6) => {
+                    self.hotkeys.push(Hotkey::RamSearch);
+                },
+                keycode_object!(Keycode::F7) => {
+                    self.hotkeys.push(Hotkey::LoadState);
+                },
+                keycode_object!(Keycode::F8) => self.start_rebinding(),
+                keycode_object!(Keycode::F9) => {
+                    self.hotkeys.push(Hotkey::ToggleTrace);
+                },
+                keycode_object!(Keycode::F10) => {
+                    self.hotkeys.push(Hotkey::StopMovie);
+                },
+                Event::KeyDown { keycode: Some(code), .. } if (Keycode::Num0 as i32..=Keycode::Num9 as i32).contains(&(code as i32)) => {
+                    self.hotkeys.push(Hotkey::SelectSlot((code as i32 - Keycode::Num0 as i32) as u8));
+                },
 
-            if found_code.is_some() {
-                self.events.push(found_code.unwrap());
+                Event::Quit { .. } | Event::Window { win_event: WindowEvent::Close, .. } => {
+                    self.quit = true;
+                },
+
+                _ => {},
             }
         }
     }
 
 }
+
+impl InputSource for EventHandler {
+    fn poll(&mut self) {
+        self.update_events();
+    }
+
+    fn buttons(&self) -> u8 {
+        self.joypad_state()
+    }
+
+    fn quit(&self) -> bool {
+        self.quit
+    }
+}
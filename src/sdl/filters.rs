@@ -0,0 +1,184 @@
+extern crate sdl2;
+
+use sdl2::pixels::Color;
+
+/*
+ *  Filters
+ *  Scale the framebuffer on the cpu before it's uploaded, so effects that
+ *  need to know about neighbouring pixels still look sharp. The texture is
+ *  then stretched to the window with nearest neighbour
+ */
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Filter {
+    Nearest,
+    Scale2x,
+    Scale3x,
+    LcdGrid,
+    Scanlines,
+}
+
+impl Filter {
+
+    pub const ALL: [Filter; 5] = [
+        Filter::Nearest,
+        Filter::Scale2x,
+        Filter::Scale3x,
+        Filter::LcdGrid,
+        Filter::Scanlines,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Nearest => "nearest",
+            Filter::Scale2x => "scale2x",
+            Filter::Scale3x => "scale3x",
+            Filter::LcdGrid => "lcd",
+            Filter::Scanlines => "scanlines",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|f| f.name() == name).copied()
+    }
+
+    pub fn next(&self) -> Self {
+        let i = Self::ALL.iter().position(|f| f == self).unwrap();
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// How many times larger the output is on each side
+    pub fn scale(&self) -> usize {
+        match self {
+            Filter::Nearest => 1,
+            Filter::Scale2x | Filter::Scanlines => 2,
+            Filter::Scale3x | Filter::LcdGrid => 3,
+        }
+    }
+
+    /// Runs the filter over a frame of width pixels per row
+    pub fn apply(&self, frame: &[Color], width: usize) -> Vec<Color> {
+        let height = frame.len() / width;
+        let scale = self.scale();
+        let mut out = vec![Color::RGB(0, 0, 0); frame.len() * scale * scale];
+        let out_width = width * scale;
+
+        // The pixel at an offset from (x, y), edges repeat outwards
+        let at = |x: usize, y: usize, dx: isize, dy: isize| {
+            let x = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+            let y = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+            frame[y * width + x]
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let block = match self {
+                    Filter::Nearest => vec![at(x, y, 0, 0)],
+                    Filter::Scale2x => scale2x(&|dx, dy| at(x, y, dx, dy)),
+                    Filter::Scale3x => scale3x(&|dx, dy| at(x, y, dx, dy)),
+                    Filter::LcdGrid => {
+                        let (p, edge) = (at(x, y, 0, 0), darken(at(x, y, 0, 0), 3, 4));
+                        vec![p, p, edge, p, p, edge, edge, edge, edge]
+                    },
+                    Filter::Scanlines => {
+                        let (p, line) = (at(x, y, 0, 0), darken(at(x, y, 0, 0), 1, 2));
+                        vec![p, p, line, line]
+                    },
+                };
+                for (i, color) in block.into_iter().enumerate() {
+                    let (bx, by) = (i % scale, i / scale);
+                    out[(y * scale + by) * out_width + x * scale + bx] = color;
+                }
+            }
+        }
+        out
+    }
+
+}
+
+pub const DEFAULT_GHOSTING: f64 = 0.5;
+
+/*
+ *  Ghosting
+ *  The DMG's screen is slow to change, so a pixel fades between colors over
+ *  a few frames. Games lean on this for transparency by flickering sprites
+ *  every other frame. Each frame gets decay of the last one shown mixed into
+ *  it, and since that one was blended too older frames fade out gradually
+ */
+pub struct Ghosting {
+    pub enabled: bool,
+    /// How much of the previous frame stays, 0 to 1
+    pub decay: f64,
+    previous: Vec<Color>,
+}
+
+impl Ghosting {
+
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            decay: DEFAULT_GHOSTING,
+            previous: Vec::new(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.previous.clear();
+        println!("Ghosting {}", if self.enabled { "on" } else { "off" });
+    }
+
+    /// Blends a frame with the ones before it, passing it through untouched while off
+    pub fn apply(&mut self, frame: Vec<Color>) -> Vec<Color> {
+        if !self.enabled {
+            return frame;
+        }
+        if self.previous.len() != frame.len() {
+            self.previous = frame.clone();
+            return frame;
+        }
+        let mix = |now: u8, before: u8| (now as f64 * (1.0 - self.decay) + before as f64 * self.decay).round() as u8;
+        let blended: Vec<Color> = frame.iter().zip(self.previous.iter())
+            .map(|(now, before)| Color::RGB(mix(now.r, before.r), mix(now.g, before.g), mix(now.b, before.b)))
+            .collect();
+        self.previous = blended.clone();
+        blended
+    }
+
+}
+
+fn darken(color: Color, num: u16, den: u16) -> Color {
+    let f = |c: u8| (c as u16 * num / den) as u8;
+    Color::RGB(f(color.r), f(color.g), f(color.b))
+}
+
+/// EPX: each pixel becomes 2x2, taking a neighbour's color where two neighbours agree on an edge
+fn scale2x(at: &dyn Fn(isize, isize) -> Color) -> Vec<Color> {
+    let (p, a, b, c, d) = (at(0, 0), at(0, -1), at(1, 0), at(-1, 0), at(0, 1));
+    vec![
+        if c == a && c != d && a != b { a } else { p },
+        if a == b && a != c && b != d { b } else { p },
+        if d == c && d != b && c != a { c } else { p },
+        if b == d && b != a && d != c { d } else { p },
+    ]
+}
+
+/// AdvMAME3x, the same idea as Scale2x with a 3x3 block
+fn scale3x(at: &dyn Fn(isize, isize) -> Color) -> Vec<Color> {
+    let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+    let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+    let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+    if b == h || d == f {
+        return vec![e; 9];
+    }
+    vec![
+        if d == b { d } else { e },
+        if (d == b && e != c) || (b == f && e != a) { b } else { e },
+        if b == f { f } else { e },
+        if (d == b && e != g) || (d == h && e != a) { d } else { e },
+        e,
+        if (b == f && e != i) || (h == f && e != c) { f } else { e },
+        if d == h { d } else { e },
+        if (d == h && e != i) || (h == f && e != g) { h } else { e },
+        if h == f { f } else { e },
+    ]
+}
@@ -0,0 +1,95 @@
+extern crate sdl2;
+
+use sdl2::{VideoSubsystem, event::{Event, WindowEvent}};
+
+use jboy::cpu::Cpu;
+use jboy::io_registers::REGISTERS;
+use super::debug_window::{self, DebugWindow, CHAR_WIDTH, CHAR_HEIGHT};
+
+const COLUMNS: u32 = 104;
+/// How many frames a register stays highlighted after it changes
+const HIGHLIGHT_FRAMES: u32 = 30;
+
+/*
+ *  I/O Viewer
+ *  Lists every hardware register with its bits decoded, updated live.
+ *  Registers that changed recently are highlighted
+ */
+pub struct IoViewer {
+    window: Option<DebugWindow>,
+    previous: Vec<u8>,
+    since_change: Vec<u32>,
+}
+
+impl IoViewer {
+
+    pub fn new() -> Self {
+        Self {
+            window: None,
+            previous: Vec::new(),
+            since_change: vec![HIGHLIGHT_FRAMES; REGISTERS.len()],
+        }
+    }
+
+    pub fn toggle(&mut self, video: &VideoSubsystem) {
+        let lines = REGISTERS.len() as u32 + 2;
+        self.window = match self.window {
+            Some(_) => None,
+            None => Some(DebugWindow::new(video, "I/O Registers", COLUMNS * CHAR_WIDTH, lines * CHAR_HEIGHT)),
+        };
+    }
+
+    pub fn window_id(&self) -> Option<u32> {
+        self.window.as_ref().map(|w| w.id)
+    }
+
+    /// Handles an event if it was sent to this window
+    pub fn handle_event(&mut self, event: &Event) {
+        if debug_window::event_window(event) != self.window_id() {
+            return;
+        }
+        if let Event::Window { win_event: WindowEvent::Close, .. } = event {
+            self.window = None;
+        }
+    }
+
+    /// Notes which registers changed since the last frame
+    pub fn update(&mut self, cpu: &Cpu) {
+        let current: Vec<u8> = REGISTERS.iter().map(|r| cpu.memory.peek(r.addr)).collect();
+        for (i, byte) in current.iter().enumerate() {
+            if self.previous.get(i).is_some_and(|b| b != byte) {
+                self.since_change[i] = 0;
+            } else if self.since_change[i] < HIGHLIGHT_FRAMES {
+                self.since_change[i] += 1;
+            }
+        }
+        self.previous = current;
+    }
+
+    pub fn recently_changed(&self, index: usize) -> bool {
+        self.since_change[index] < HIGHLIGHT_FRAMES
+    }
+
+    /// Redraws the window, called once per frame
+    pub fn draw(&mut self, cpu: &Cpu) {
+        if self.window.is_none() {
+            return;
+        }
+        self.update(cpu);
+
+        let mut lines = Vec::new();
+        for (i, register) in REGISTERS.iter().enumerate() {
+            let color = if self.recently_changed(i) { debug_window::HIGHLIGHT } else { debug_window::TEXT };
+            lines.push((register.describe(cpu), color));
+        }
+
+        let window = self.window.as_mut().unwrap();
+        window.clear();
+        window.text(0, 0, "ADDR NAME VALUE  FIELDS", debug_window::ACCENT);
+        for (i, (line, color)) in lines.iter().enumerate() {
+            window.text(0, i as u32 + 2, line, *color);
+        }
+        window.present();
+    }
+
+}
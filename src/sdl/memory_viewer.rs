@@ -0,0 +1,284 @@
+extern crate sdl2;
+
+use sdl2::{VideoSubsystem, event::{Event, WindowEvent}, keyboard::Keycode};
+
+use jboy::cpu::Cpu;
+use jboy::cpu::memory::{self, REGIONS};
+use super::debug_window::{self, DebugWindow, CHAR_WIDTH, CHAR_HEIGHT};
+
+const ROWS: u16 = 32;
+const BYTES_PER_ROW: u16 = 16;
+const COLUMNS: u32 = 86;
+const LINES: u32 = ROWS as u32 + 4;
+
+const HELP: &str = "ARROWS/PGUP/PGDN MOVE  TAB REGION  G GO TO  S SEARCH  N NEXT  0-F EDIT";
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Mode {
+    Browse,
+    GoTo,
+    Search,
+}
+
+/// Hex digit typed with a key, if it is one
+pub fn hex_digit(key: Keycode) -> Option<u8> {
+    let digit = match key {
+        Keycode::Num0 | Keycode::Kp0 => 0x0,
+        Keycode::Num1 | Keycode::Kp1 => 0x1,
+        Keycode::Num2 | Keycode::Kp2 => 0x2,
+        Keycode::Num3 | Keycode::Kp3 => 0x3,
+        Keycode::Num4 | Keycode::Kp4 => 0x4,
+        Keycode::Num5 | Keycode::Kp5 => 0x5,
+        Keycode::Num6 | Keycode::Kp6 => 0x6,
+        Keycode::Num7 | Keycode::Kp7 => 0x7,
+        Keycode::Num8 | Keycode::Kp8 => 0x8,
+        Keycode::Num9 | Keycode::Kp9 => 0x9,
+        Keycode::A => 0xA,
+        Keycode::B => 0xB,
+        Keycode::C => 0xC,
+        Keycode::D => 0xD,
+        Keycode::E => 0xE,
+        Keycode::F => 0xF,
+        _ => return None,
+    };
+    Some(digit)
+}
+
+/// Parses a string of hex digits into bytes, e.g. "3E12" -> [0x3E, 0x12]
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    if text.is_empty() || !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/*
+ *  Memory Viewer
+ *  A live hex view of the whole address space in its own window. Bytes that
+ *  changed since the last frame are highlighted, and typing two hex digits
+ *  over the cursor writes a new byte
+ */
+pub struct MemoryViewer {
+    window: Option<DebugWindow>,
+    pub cursor: u16,
+    top: u16,
+    pub mode: Mode,
+    input: String,
+    nibble: Option<u8>,
+    search: Vec<u8>,
+    previous: Vec<u8>,
+    status: String,
+}
+
+impl MemoryViewer {
+
+    pub fn new() -> Self {
+        Self {
+            window: None,
+            cursor: 0,
+            top: 0,
+            mode: Mode::Browse,
+            input: String::new(),
+            nibble: None,
+            search: Vec::new(),
+            previous: Vec::new(),
+            status: String::new(),
+        }
+    }
+
+    pub fn toggle(&mut self, video: &VideoSubsystem) {
+        self.window = match self.window {
+            Some(_) => None,
+            None => Some(DebugWindow::new(video, "Memory", COLUMNS * CHAR_WIDTH, LINES * CHAR_HEIGHT)),
+        };
+    }
+
+    /// Moves the cursor to an address and scrolls it into view
+    pub fn show(&mut self, addr: u16) {
+        self.move_cursor(addr);
+        self.scroll_to_cursor();
+    }
+
+    pub fn window_id(&self) -> Option<u32> {
+        self.window.as_ref().map(|w| w.id)
+    }
+
+    /// Handles an event if it was sent to this window
+    pub fn handle_event(&mut self, event: &Event, cpu: &mut Cpu) {
+        if debug_window::event_window(event) != self.window_id() {
+            return;
+        }
+        match event {
+            Event::KeyDown { keycode: Some(key), .. } => self.handle_key(*key, cpu),
+            Event::Window { win_event: WindowEvent::Close, .. } => self.window = None,
+            _ => {},
+        }
+    }
+
+    pub fn handle_key(&mut self, key: Keycode, cpu: &mut Cpu) {
+        self.status.clear();
+        match self.mode {
+            Mode::Browse => self.browse_key(key, cpu),
+            Mode::GoTo | Mode::Search => self.input_key(key, cpu),
+        }
+        self.scroll_to_cursor();
+    }
+
+    fn browse_key(&mut self, key: Keycode, cpu: &mut Cpu) {
+        let page = ROWS * BYTES_PER_ROW;
+        match key {
+            Keycode::Up => self.move_cursor(self.cursor.wrapping_sub(BYTES_PER_ROW)),
+            Keycode::Down => self.move_cursor(self.cursor.wrapping_add(BYTES_PER_ROW)),
+            Keycode::Left => self.move_cursor(self.cursor.wrapping_sub(1)),
+            Keycode::Right => self.move_cursor(self.cursor.wrapping_add(1)),
+            Keycode::PageUp => self.move_cursor(self.cursor.wrapping_sub(page)),
+            Keycode::PageDown => self.move_cursor(self.cursor.wrapping_add(page)),
+            Keycode::Tab => {
+                let (_, end, _) = memory::region(self.cursor);
+                self.move_cursor(end.wrapping_add(1));
+            },
+            Keycode::G => self.start_input(Mode::GoTo),
+            Keycode::S => self.start_input(Mode::Search),
+            Keycode::N => self.find_next(cpu),
+            Keycode::Escape => self.nibble = None,
+            _ => if let Some(digit) = hex_digit(key) {
+                match self.nibble.take() {
+                    None => self.nibble = Some(digit),
+                    Some(high) => {
+                        cpu.memory.poke(self.cursor, (high << 4) | digit);
+                        self.cursor = self.cursor.wrapping_add(1);
+                    },
+                }
+            },
+        }
+    }
+
+    fn input_key(&mut self, key: Keycode, cpu: &mut Cpu) {
+        match key {
+            Keycode::Escape => self.mode = Mode::Browse,
+            Keycode::Backspace => {
+                self.input.pop();
+            },
+            Keycode::Return | Keycode::KpEnter => {
+                let mode = self.mode;
+                self.mode = Mode::Browse;
+                match mode {
+                    Mode::GoTo => match u16::from_str_radix(&self.input, 16) {
+                        Ok(addr) => self.move_cursor(addr),
+                        Err(_) => self.status = String::from("Invalid address"),
+                    },
+                    Mode::Search => match parse_bytes(&self.input) {
+                        Some(bytes) => {
+                            self.search = bytes;
+                            self.find_next(cpu);
+                        },
+                        None => self.status = String::from("Search takes whole bytes in hex"),
+                    },
+                    Mode::Browse => {},
+                }
+            },
+            _ => if let Some(digit) = hex_digit(key) {
+                let limit = if self.mode == Mode::GoTo { 4 } else { 32 };
+                if self.input.len() < limit {
+                    self.input.push_str(&format!("{:X}", digit));
+                }
+            },
+        }
+    }
+
+    fn start_input(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.input.clear();
+        self.nibble = None;
+    }
+
+    fn move_cursor(&mut self, addr: u16) {
+        self.cursor = addr;
+        self.nibble = None;
+    }
+
+    fn find_next(&mut self, cpu: &Cpu) {
+        if self.search.is_empty() {
+            self.status = String::from("Nothing to search for");
+            return;
+        }
+        match cpu.memory.find(self.cursor.wrapping_add(1), &self.search) {
+            Some(addr) => {
+                self.move_cursor(addr);
+                self.status = format!("Found at {:04X}", addr);
+            },
+            None => self.status = String::from("Not found"),
+        }
+    }
+
+    fn scroll_to_cursor(&mut self) {
+        let row = self.cursor - self.cursor % BYTES_PER_ROW;
+        let span = (ROWS - 1) * BYTES_PER_ROW;
+        if row.wrapping_sub(self.top) > span {
+            // Just above the view scrolls up, anywhere else scrolls down
+            self.top = if self.top.wrapping_sub(row) <= span {
+                row
+            } else {
+                row.wrapping_sub(span)
+            };
+        }
+    }
+
+    /// Redraws the window, called once per frame
+    pub fn draw(&mut self, cpu: &Cpu) {
+        let window = match self.window.as_mut() {
+            Some(window) => window,
+            None => return,
+        };
+        let current = cpu.memory.snapshot();
+        let changed = |addr: u16| self.previous.get(addr as usize).is_some_and(|b| *b != current[addr as usize]);
+
+        window.clear();
+        let (start, end, name) = memory::region(self.cursor);
+        window.text(0, 0, &format!("{:04X}: {:02X}  {} {:04X}-{:04X}", self.cursor, current[self.cursor as usize], name, start, end), debug_window::ACCENT);
+
+        for row in 0..ROWS {
+            let line = 2 + row as u32;
+            let addr = self.top.wrapping_add(row * BYTES_PER_ROW);
+            window.text(0, line, &format!("{:04X}", addr), debug_window::DIM);
+
+            let mut ascii = String::new();
+            for i in 0..BYTES_PER_ROW {
+                let at = addr.wrapping_add(i);
+                let byte = current[at as usize];
+                let column = 6 + i as u32 * 3;
+                if at == self.cursor {
+                    window.fill((column * CHAR_WIDTH) as i32 - 2, (line * CHAR_HEIGHT) as i32 - 2,
+                        CHAR_WIDTH * 2 + 2, CHAR_HEIGHT, debug_window::DIM);
+                }
+                let text = match (at == self.cursor, self.nibble) {
+                    (true, Some(high)) => format!("{:X}_", high),
+                    _ => format!("{:02X}", byte),
+                };
+                let color = if changed(at) { debug_window::HIGHLIGHT } else { debug_window::TEXT };
+                window.text(column, line, &text, color);
+                ascii.push(if (0x20..0x7F).contains(&byte) { byte as char } else { '.' });
+            }
+            window.text(55, line, &ascii, debug_window::DIM);
+
+            if let Some((_, _, name)) = REGIONS.iter().find(|(start, _, _)| *start >= addr && *start - addr < BYTES_PER_ROW) {
+                window.text(72, line, &format!("<{}", name), debug_window::ACCENT);
+            }
+        }
+
+        let footer = match self.mode {
+            Mode::GoTo => format!("GO TO: {}_", self.input),
+            Mode::Search => format!("SEARCH: {}_", self.input),
+            Mode::Browse if !self.status.is_empty() => self.status.clone(),
+            Mode::Browse => String::from(HELP),
+        };
+        window.text(0, LINES - 1, &footer, debug_window::TEXT);
+        window.present();
+
+        self.previous = current;
+    }
+
+}
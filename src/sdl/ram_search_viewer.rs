@@ -0,0 +1,200 @@
+extern crate sdl2;
+
+use sdl2::{VideoSubsystem, event::{Event, WindowEvent}, keyboard::Keycode};
+
+use jboy::cheats::Code;
+use jboy::cpu::Cpu;
+use jboy::ram_search::{Filter, RamSearch};
+use super::debug_window::{self, DebugWindow, CHAR_WIDTH, CHAR_HEIGHT};
+use super::memory_viewer::hex_digit;
+
+const COLUMNS: u32 = 52;
+const LINES: u32 = 46;
+const CANDIDATE_ROWS: usize = 24;
+const WATCH_ROWS: usize = 12;
+
+const HELP: [&str; 2] = [
+    "R RESET  E EQUAL  C CHANGED  I UP  D DOWN  V VALUE",
+    "UP/DN SELECT  W WATCH  F FREEZE  M SHOW  X CLEAR",
+];
+
+/// GameShark code that keeps a byte at a value
+pub fn freeze_code(addr: u16, value: u8) -> String {
+    let [low, high] = addr.to_le_bytes();
+    format!("01{:02X}{:02X}{:02X}", value, low, high)
+}
+
+/*
+ *  Ram Search Viewer
+ *  Runs a RamSearch from the keyboard and lists what's left. Candidates can
+ *  be watched, frozen with a GameShark cheat, or opened in the memory viewer
+ */
+pub struct RamSearchViewer {
+    window: Option<DebugWindow>,
+    pub search: RamSearch,
+    pub selected: usize,
+    /// Some while typing a value to search for
+    input: Option<String>,
+    /// An address to open in the memory viewer, picked up by SdlHandles
+    pub show: Option<u16>,
+    status: String,
+}
+
+impl RamSearchViewer {
+
+    pub fn new() -> Self {
+        Self {
+            window: None,
+            search: RamSearch::new(),
+            selected: 0,
+            input: None,
+            show: None,
+            status: String::new(),
+        }
+    }
+
+    pub fn toggle(&mut self, video: &VideoSubsystem) {
+        self.window = match self.window {
+            Some(_) => None,
+            None => Some(DebugWindow::new(video, "RAM Search", COLUMNS * CHAR_WIDTH, LINES * CHAR_HEIGHT)),
+        };
+    }
+
+    pub fn window_id(&self) -> Option<u32> {
+        self.window.as_ref().map(|w| w.id)
+    }
+
+    /// Handles an event if it was sent to this window
+    pub fn handle_event(&mut self, event: &Event, cpu: &mut Cpu) {
+        if debug_window::event_window(event) != self.window_id() {
+            return;
+        }
+        match event {
+            Event::KeyDown { keycode: Some(key), .. } => self.handle_key(*key, cpu),
+            Event::Window { win_event: WindowEvent::Close, .. } => self.window = None,
+            _ => {},
+        }
+    }
+
+    pub fn handle_key(&mut self, key: Keycode, cpu: &mut Cpu) {
+        self.status.clear();
+        if let Some(input) = self.input.as_mut() {
+            match key {
+                Keycode::Escape => self.input = None,
+                Keycode::Backspace => {
+                    input.pop();
+                },
+                Keycode::Return | Keycode::KpEnter => match u8::from_str_radix(input, 16) {
+                    Ok(value) => {
+                        self.input = None;
+                        self.run_filter(cpu, Filter::Value(value));
+                    },
+                    Err(_) => self.status = String::from("Type a byte in hex"),
+                },
+                _ => if let Some(digit) = hex_digit(key) {
+                    if input.len() < 2 {
+                        input.push_str(&format!("{:X}", digit));
+                    }
+                },
+            }
+            return;
+        }
+
+        let selected = self.search.candidates.get(self.selected).copied();
+        match key {
+            Keycode::R => {
+                self.search.reset(&cpu.memory);
+                self.selected = 0;
+            },
+            Keycode::E => self.run_filter(cpu, Filter::Equal),
+            Keycode::C => self.run_filter(cpu, Filter::Changed),
+            Keycode::I => self.run_filter(cpu, Filter::Increased),
+            Keycode::D => self.run_filter(cpu, Filter::Decreased),
+            Keycode::V => self.input = Some(String::new()),
+            Keycode::Up => self.selected = self.selected.saturating_sub(1),
+            Keycode::Down => self.selected = (self.selected + 1).min(self.search.candidates.len().saturating_sub(1)),
+            Keycode::X => self.search.watches.clear(),
+            Keycode::W | Keycode::F | Keycode::M => {
+                let addr = match selected {
+                    Some(addr) => addr,
+                    None => {
+                        self.status = String::from("Nothing selected");
+                        return;
+                    },
+                };
+                match key {
+                    Keycode::W => self.search.watch(addr),
+                    Keycode::F => self.freeze(cpu, addr),
+                    _ => self.show = Some(addr),
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn run_filter(&mut self, cpu: &Cpu, filter: Filter) {
+        if self.search.candidates.is_empty() {
+            self.search.reset(&cpu.memory);
+        }
+        self.search.filter(&cpu.memory, filter);
+        self.selected = 0;
+    }
+
+    /// Watches an address and adds a cheat holding its current value
+    pub fn freeze(&mut self, cpu: &mut Cpu, addr: u16) {
+        self.search.watch(addr);
+        let code = freeze_code(addr, cpu.memory.peek(addr));
+        match cpu.cheats.add(&code, &format!("Freeze ${:04X}", addr)) {
+            Ok(()) => self.status = format!("Frozen as cheat {}", cpu.cheats.len() - 1),
+            Err(e) => self.status = e.to_string(),
+        }
+    }
+
+    fn frozen(cpu: &Cpu, addr: u16) -> bool {
+        cpu.cheats.enabled && cpu.cheats.cheats.iter()
+            .filter(|c| c.enabled)
+            .flat_map(|c| c.codes.iter())
+            .any(|code| matches!(code, Code::GameShark { addr: a, .. } if *a == addr))
+    }
+
+    /// Redraws the window, called once per frame
+    pub fn draw(&mut self, cpu: &Cpu) {
+        let window = match self.window.as_mut() {
+            Some(window) => window,
+            None => return,
+        };
+        let candidates = &self.search.candidates;
+
+        window.clear();
+        window.text(0, 0, &format!("{} CANDIDATES", candidates.len()), debug_window::ACCENT);
+        window.text(0, 1, "ADDR  LAST  NOW", debug_window::DIM);
+
+        let first = self.selected.saturating_sub(CANDIDATE_ROWS - 1);
+        for (row, index) in (first..candidates.len()).take(CANDIDATE_ROWS).enumerate() {
+            let addr = candidates[index];
+            let (before, now) = (self.search.previous(index), cpu.memory.peek(addr));
+            let color = if now != before { debug_window::HIGHLIGHT } else { debug_window::TEXT };
+            let marker = if index == self.selected { ">" } else { " " };
+            window.text(0, 2 + row as u32, &format!("{}{:04X}   {:02X}   {:02X}", marker, addr, before, now), color);
+        }
+
+        let top = 3 + CANDIDATE_ROWS as u32;
+        window.text(0, top, "WATCHES", debug_window::ACCENT);
+        for (row, addr) in self.search.watches.iter().take(WATCH_ROWS).enumerate() {
+            let value = cpu.memory.peek(*addr);
+            let frozen = if Self::frozen(cpu, *addr) { "FROZEN" } else { "" };
+            window.text(1, top + 1 + row as u32, &format!("{:04X}  {:02X}  {:3}  {}", addr, value, value, frozen), debug_window::TEXT);
+        }
+
+        match (&self.input, self.status.is_empty()) {
+            (Some(input), _) => window.text(0, LINES - 2, &format!("VALUE: {}_", input), debug_window::TEXT),
+            (None, false) => window.text(0, LINES - 2, &self.status, debug_window::TEXT),
+            (None, true) => {
+                window.text(0, LINES - 2, HELP[0], debug_window::DIM);
+                window.text(0, LINES - 1, HELP[1], debug_window::DIM);
+            },
+        }
+        window.present();
+    }
+
+}
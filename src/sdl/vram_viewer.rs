@@ -0,0 +1,251 @@
+extern crate sdl2;
+
+use sdl2::{VideoSubsystem, event::{Event, WindowEvent}, keyboard::Keycode, rect::Rect};
+
+use jboy::cpu::Cpu;
+use jboy::graphics::{self, Lcd, Pixel, Sprite, BGP, OBP0, OBP1, SCX, SCY, WX, WY, TILE_COUNT, SPRITE_COUNT};
+use jboy::palettes::Palette;
+use super::debug_window::{self, DebugWindow, CHAR_WIDTH, CHAR_HEIGHT};
+
+const WIDTH: u32 = 544;
+const HEIGHT: u32 = 552;
+/// Where the views start drawing, below the header line
+const TOP: i32 = 2 * CHAR_HEIGHT as i32;
+
+const TILE_SCALE: u32 = 2;
+const TILE_COLUMNS: usize = 16;
+const MAP_SIZE: u32 = 256;
+
+/// Palettes the tiles and tilemaps can be drawn with, None draws color ids as shades
+const PALETTES: [(&str, Option<u16>); 4] = [
+    ("BGP", Some(BGP)),
+    ("OBP0", Some(OBP0)),
+    ("OBP1", Some(OBP1)),
+    ("NONE", None),
+];
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum View {
+    Tiles,
+    Tilemaps,
+    Sprites,
+}
+
+/// Every tile in vram as color ids, indexed from 0x8000
+fn all_tiles(cpu: &Cpu) -> Vec<[[u8; 8]; 8]> {
+    (0..TILE_COUNT)
+        .map(|i| graphics::read_tile(cpu, 0x8000 + i as u16 * 16))
+        .collect()
+}
+
+/*
+ *  Sprite Preview
+ *  A sprite's pixels as the lcd would draw them, flipped and colored with its
+ *  palette. Color 0 is transparent, so it comes back as None
+ */
+pub fn sprite_pixels(cpu: &Cpu, sprite: &Sprite, lcd: &Lcd) -> Vec<Option<Pixel>> {
+    let height = lcd.sprite_height() as usize;
+    // 8x16 sprites ignore the low bit of the tile index
+    let first = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+    let palette = cpu.memory.peek(sprite.palette());
+
+    let mut pixels = Vec::with_capacity(8 * height);
+    for y in 0..height {
+        let row = if sprite.y_flip() { height - 1 - y } else { y };
+        let tile = graphics::read_tile(cpu, 0x8000 + (first as u16 + (row / 8) as u16) * 16);
+        for x in 0..8 {
+            let column = if sprite.x_flip() { 7 - x } else { x };
+            let color = tile[row % 8][column];
+            pixels.push(if color == 0 { None } else { Some(graphics::shade(palette, color)) });
+        }
+    }
+    pixels
+}
+
+/*
+ *  Vram Viewer
+ *  Debug views of what's in video memory: every tile, both background
+ *  tilemaps with the visible area outlined, and the sprites in OAM
+ */
+pub struct VramViewer {
+    window: Option<DebugWindow>,
+    pub view: View,
+    pub palette: usize,
+}
+
+impl VramViewer {
+
+    pub fn new() -> Self {
+        Self {
+            window: None,
+            view: View::Tiles,
+            palette: 0,
+        }
+    }
+
+    pub fn toggle(&mut self, video: &VideoSubsystem) {
+        self.window = match self.window {
+            Some(_) => None,
+            None => Some(DebugWindow::new(video, "VRAM", WIDTH, HEIGHT)),
+        };
+    }
+
+    pub fn window_id(&self) -> Option<u32> {
+        self.window.as_ref().map(|w| w.id)
+    }
+
+    /// Handles an event if it was sent to this window
+    pub fn handle_event(&mut self, event: &Event) {
+        if debug_window::event_window(event) != self.window_id() {
+            return;
+        }
+        match event {
+            Event::KeyDown { keycode: Some(key), .. } => self.handle_key(*key),
+            Event::Window { win_event: WindowEvent::Close, .. } => self.window = None,
+            _ => {},
+        }
+    }
+
+    pub fn handle_key(&mut self, key: Keycode) {
+        match key {
+            Keycode::Num1 => self.view = View::Tiles,
+            Keycode::Num2 => self.view = View::Tilemaps,
+            Keycode::Num3 => self.view = View::Sprites,
+            Keycode::Tab => self.view = match self.view {
+                View::Tiles => View::Tilemaps,
+                View::Tilemaps => View::Sprites,
+                View::Sprites => View::Tiles,
+            },
+            Keycode::P => self.palette = (self.palette + 1) % PALETTES.len(),
+            _ => {},
+        }
+    }
+
+    /// The shade for a color id in the selected palette
+    fn shade(&self, cpu: &Cpu, color: u8) -> Pixel {
+        match PALETTES[self.palette].1 {
+            Some(register) => graphics::shade(cpu.memory.peek(register), color),
+            None => Pixel::from_shade(color),
+        }
+    }
+
+    /// Redraws the window, called once per frame
+    pub fn draw(&mut self, cpu: &Cpu, palette: &Palette) {
+        let mut window = match self.window.take() {
+            Some(window) => window,
+            None => return,
+        };
+        let lcd = Lcd::from_memory(cpu);
+
+        window.clear();
+        let header = match self.view {
+            View::Sprites => String::from("1 TILES  2 MAPS  [3 SPRITES]"),
+            View::Tiles => format!("[1 TILES]  2 MAPS  3 SPRITES   P PALETTE: {}", PALETTES[self.palette].0),
+            View::Tilemaps => format!("1 TILES  [2 MAPS]  3 SPRITES   P PALETTE: {}", PALETTES[self.palette].0),
+        };
+        window.text(0, 0, &header, debug_window::ACCENT);
+
+        match self.view {
+            View::Tiles => self.draw_tiles(&mut window, cpu, palette),
+            View::Tilemaps => self.draw_tilemaps(&mut window, cpu, &lcd, palette),
+            View::Sprites => self.draw_sprites(&mut window, cpu, &lcd, palette),
+        }
+        window.present();
+        self.window = Some(window);
+    }
+
+    fn draw_tiles(&self, window: &mut DebugWindow, cpu: &Cpu, palette: &Palette) {
+        let size = (8 * TILE_SCALE + 1) as i32;
+        for (i, tile) in all_tiles(cpu).iter().enumerate() {
+            let pixels: Vec<Option<Pixel>> = tile.iter()
+                .flatten()
+                .map(|color| Some(self.shade(cpu, *color)))
+                .collect();
+            let x = 8 + (i % TILE_COLUMNS) as i32 * size;
+            let y = TOP + (i / TILE_COLUMNS) as i32 * size;
+            window.pixels(x, y, TILE_SCALE, 8, &pixels, palette);
+        }
+
+        // The three blocks of 128 tiles
+        let right = (8 + TILE_COLUMNS as i32 * size) as u32 / CHAR_WIDTH + 1;
+        for (block, addr) in [0x8000, 0x8800, 0x9000].iter().enumerate() {
+            let row = TOP as u32 / CHAR_HEIGHT + (block as u32 * 8 * size as u32) / CHAR_HEIGHT;
+            window.text(right, row, &format!("${:04X}", addr), debug_window::DIM);
+        }
+    }
+
+    fn draw_tilemaps(&self, window: &mut DebugWindow, cpu: &Cpu, lcd: &Lcd, palette: &Palette) {
+        let tiles = all_tiles(cpu);
+        let lefts = [8, 8 + MAP_SIZE as i32 + 16];
+        let top = TOP + CHAR_HEIGHT as i32;
+
+        for (map, left) in [0x9800u16, 0x9C00].iter().zip(lefts) {
+            let mut uses = Vec::new();
+            if lcd.background_map() == *map { uses.push("BG"); }
+            if lcd.window_map() == *map { uses.push("WIN"); }
+            window.text(left as u32 / CHAR_WIDTH, 2, &format!("${:04X} {}", map, uses.join(" ")), debug_window::TEXT);
+
+            let mut pixels = vec![None; (MAP_SIZE * MAP_SIZE) as usize];
+            for i in 0..32 * 32 {
+                let index = cpu.memory.peek(map + i as u16);
+                let tile = &tiles[((lcd.tile_address(index) - 0x8000) / 16) as usize];
+                for (y, row) in tile.iter().enumerate() {
+                    for (x, color) in row.iter().enumerate() {
+                        let at = (i / 32 * 8 + y) * MAP_SIZE as usize + i % 32 * 8 + x;
+                        pixels[at] = Some(self.shade(cpu, *color));
+                    }
+                }
+            }
+            window.pixels(left, top, 1, MAP_SIZE as usize, &pixels, palette);
+        }
+
+        // The screen wraps around the background map, so draw it four times clipped to the map
+        let (scx, scy) = (cpu.memory.peek(SCX) as i32, cpu.memory.peek(SCY) as i32);
+        let background = lefts[(lcd.background_map() == 0x9C00) as usize];
+        window.clip(Some(Rect::new(background, top, MAP_SIZE, MAP_SIZE)));
+        for (dx, dy) in [(0, 0), (-256, 0), (0, -256), (-256, -256)] {
+            window.outline(background + scx + dx, top + scy + dy, 160, 144, debug_window::HIGHLIGHT);
+        }
+        window.clip(None);
+
+        // The window always starts at the top left of its map
+        let (wx, wy) = (cpu.memory.peek(WX) as i32 - 7, cpu.memory.peek(WY) as i32);
+        if lcd.window_enabled && wx < 160 && wy < 144 {
+            let left = lefts[(lcd.window_map() == 0x9C00) as usize];
+            window.outline(left, top, (160 - wx.max(0)) as u32, (144 - wy) as u32, debug_window::ACCENT);
+        }
+
+        let line = (top as u32 + MAP_SIZE) / CHAR_HEIGHT + 1;
+        window.text(1, line, &format!("SCX {:02X}  SCY {:02X}  WX {:02X}  WY {:02X}", scx, scy, wx + 7, wy), debug_window::TEXT);
+        window.text(1, line + 1, &format!("TILE DATA ${:04X}  BG {}  WINDOW {}",
+            lcd.tile_address(0),
+            if lcd.background_enabled { "ON" } else { "OFF" },
+            if lcd.window_enabled { "ON" } else { "OFF" }), debug_window::TEXT);
+        window.text(1, line + 3, "RED: SCREEN  BLUE: WINDOW", debug_window::DIM);
+    }
+
+    fn draw_sprites(&self, window: &mut DebugWindow, cpu: &Cpu, lcd: &Lcd, palette: &Palette) {
+        let entry_height = 2 * CHAR_HEIGHT as i32;
+        let column_width = WIDTH as i32 / 2;
+        for index in 0..SPRITE_COUNT {
+            let sprite = Sprite::read(cpu, index);
+            let x = 8 + (index / 20) as i32 * column_width;
+            let y = TOP + (index % 20) as i32 * entry_height;
+
+            window.fill(x, y, 8, lcd.sprite_height() as u32, debug_window::DIM);
+            window.pixels(x, y, 1, 8, &sprite_pixels(cpu, &sprite, lcd), palette);
+
+            let color = if sprite.visible(lcd) { debug_window::TEXT } else { debug_window::DIM };
+            let column = (x as u32 + 16) / CHAR_WIDTH;
+            let row = y as u32 / CHAR_HEIGHT;
+            window.text(column, row, &format!("{:02} X:{:03} Y:{:03} T:{:02X}", index, sprite.x, sprite.y, sprite.tile), color);
+            window.text(column, row + 1, &format!("   F:{:02X} {} {}{} {}",
+                sprite.flags,
+                if sprite.palette() == OBP1 { "OBP1" } else { "OBP0" },
+                if sprite.x_flip() { 'X' } else { '-' },
+                if sprite.y_flip() { 'Y' } else { '-' },
+                if sprite.behind_background() { "BEHIND" } else { "" }), debug_window::DIM);
+        }
+    }
+
+}
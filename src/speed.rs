@@ -0,0 +1,190 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::emulation::CYCLES_PER_FRAME;
+use crate::gameboy::CHANNELS;
+
+/// Cycles per second of the gameboy's clock
+pub const CLOCK_SPEED: u32 = 4194304;
+pub const DEFAULT_FAST_FORWARD: f64 = 0.0;
+pub const DEFAULT_SLOW_MOTION: f64 = 0.25;
+pub const DEFAULT_TURBO_RATE: u32 = 10;
+
+/// Real time one emulated frame takes at normal speed, about 16.7ms
+pub fn frame_duration() -> Duration {
+    Duration::from_secs_f64(CYCLES_PER_FRAME as f64 / CLOCK_SPEED as f64)
+}
+
+/*
+ *  Fits a frame's sound into the real time it took at a speed multiplier,
+ *  dropping or repeating whole stereo samples. Sped up sound plays higher
+ *  and slowed down sound lower, like a tape. At 0 there's no telling how
+ *  fast frames go by, so it's muted
+ */
+pub fn resample(samples: &[i16], speed: f64) -> Vec<i16> {
+    if speed == 1.0 {
+        return samples.to_vec();
+    }
+    if speed <= 0.0 {
+        return Vec::new();
+    }
+    let channels = CHANNELS as usize;
+    let frames = samples.len() / channels;
+    let wanted = (frames as f64 / speed).round() as usize;
+    (0..wanted)
+        .map(|i| ((i as f64 * speed) as usize).min(frames - 1) * channels)
+        .flat_map(|start| samples[start..start + channels].iter().copied())
+        .collect()
+}
+
+/*
+ *  Frame Pacer
+ *  Sleeps between frames so the game runs at the speed asked for. When it
+ *  falls more than a frame behind, say after the window was dragged, it
+ *  starts counting again from now instead of rushing to catch up
+ */
+pub struct FramePacer {
+    next: Instant,
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self {
+            next: Instant::now(),
+        }
+    }
+}
+
+impl FramePacer {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits out the rest of a frame at a speed multiplier, 0 doesn't wait at all
+    pub fn wait(&mut self, speed: f64) {
+        let now = Instant::now();
+        if speed <= 0.0 {
+            self.next = now;
+            return;
+        }
+        let frame = frame_duration().div_f64(speed);
+        self.next += frame;
+        if self.next > now {
+            thread::sleep(self.next - now);
+        } else if now - self.next > frame {
+            self.next = now;
+        }
+    }
+
+}
+
+/*
+ *  Speed Control
+ *  Fast forward, slow motion, frame advance and autofire. Autofire works
+ *  off the emulated frame count rather than the clock, so it replays the
+ *  same way in movies. Frontends pass sound through resample() at speed()
+ *  so it keeps up with the picture
+ */
+pub struct SpeedControl {
+    /// Held down with the fast forward hotkey
+    pub fast_forward: bool,
+    /// Speed while fast forwarding, 0 for as fast as possible
+    pub fast_forward_speed: f64,
+    pub slow_motion: bool,
+    pub slow_motion_speed: f64,
+    /// While paused, frames only run one at a time through advance()
+    pub paused: bool,
+    advance: bool,
+    /// Joypad bits that fire on and off while held
+    pub autofire: u8,
+    /// Presses per second for autofire
+    pub turbo_rate: u32,
+    pub frames: u64,
+}
+
+impl Default for SpeedControl {
+    fn default() -> Self {
+        Self {
+            fast_forward: false,
+            fast_forward_speed: DEFAULT_FAST_FORWARD,
+            slow_motion: false,
+            slow_motion_speed: DEFAULT_SLOW_MOTION,
+            paused: false,
+            advance: false,
+            autofire: 0,
+            turbo_rate: DEFAULT_TURBO_RATE,
+            frames: 0,
+        }
+    }
+}
+
+impl SpeedControl {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Speed multiplier for the pacer, fast forward wins over slow motion
+    pub fn speed(&self) -> f64 {
+        if self.fast_forward {
+            self.fast_forward_speed
+        } else if self.slow_motion {
+            self.slow_motion_speed
+        } else {
+            1.0
+        }
+    }
+
+    /// Returns whether the game is now paused
+    pub fn toggle_pause(&mut self) -> bool {
+        self.paused = !self.paused;
+        self.advance = false;
+        self.paused
+    }
+
+    /// Runs a single frame, pausing first if the game is running
+    pub fn advance(&mut self) {
+        self.paused = true;
+        self.advance = true;
+    }
+
+    /// Returns whether slow motion is now on
+    pub fn toggle_slow_motion(&mut self) -> bool {
+        self.slow_motion = !self.slow_motion;
+        self.slow_motion
+    }
+
+    /// Returns whether autofire is now on for the buttons
+    pub fn toggle_autofire(&mut self, mask: u8) -> bool {
+        self.autofire ^= mask;
+        self.autofire & mask != 0
+    }
+
+    /// Whether a new frame should start, called before each one
+    pub fn start_frame(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+        std::mem::take(&mut self.advance)
+    }
+
+    /// Counts a finished frame
+    pub fn end_frame(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Releases autofire buttons every other half period
+    pub fn apply_autofire(&self, pressed: u8) -> u8 {
+        if self.autofire == 0 || self.turbo_rate == 0 {
+            return pressed;
+        }
+        let half_period = (60 / (self.turbo_rate as u64 * 2)).max(1);
+        if (self.frames / half_period) % 2 == 1 {
+            pressed & !self.autofire
+        } else {
+            pressed
+        }
+    }
+
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/*
+ *  Symbols
+ *  Loaded from the .sym files RGBDS writes, one "bank:address name" per line
+ *  with ; starting a comment. There's no mapper yet, so the cpu only ever
+ *  sees bank 0 at 0x0000-0x3FFF and bank 1 at 0x4000-0x7FFF
+ */
+#[derive(Clone, Default)]
+pub struct Symbols {
+    names: HashMap<(usize, u16), String>,
+    addresses: HashMap<String, (usize, u16)>,
+}
+
+/// The sym file next to a rom, e.g. roms/game.gb -> roms/game.sym
+pub fn sym_path(rom_path: &str) -> String {
+    Path::new(rom_path)
+        .with_extension("sym")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Bank the cpu currently sees at an address
+pub fn bank_of(addr: u16) -> usize {
+    match addr {
+        0x4000..=0x7FFF => 1,
+        _ => 0,
+    }
+}
+
+impl Symbols {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a sym file, lines that aren't symbols are skipped
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Self::new();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            let mut parts = line.split_whitespace();
+            let (location, name) = match (parts.next(), parts.next()) {
+                (Some(location), Some(name)) => (location, name),
+                _ => continue,
+            };
+            let parsed = location.split_once(':').and_then(|(bank, addr)| {
+                Some((usize::from_str_radix(bank, 16).ok()?, u16::from_str_radix(addr, 16).ok()?))
+            });
+            if let Some((bank, addr)) = parsed {
+                symbols.insert(bank, addr, name);
+            }
+        }
+        symbols
+    }
+
+    /// Loads the sym file next to a rom, or no symbols if there isn't one
+    pub fn load_for_rom(rom_path: &str) -> Self {
+        match fs::read_to_string(sym_path(rom_path)) {
+            Ok(text) => Self::parse(&text),
+            Err(_) => Self::new(),
+        }
+    }
+
+    pub fn insert(&mut self, bank: usize, addr: u16, name: &str) {
+        // Keep the first name for an address, later ones are usually local labels
+        self.names.entry((bank, addr)).or_insert_with(|| name.to_string());
+        self.addresses.insert(name.to_string(), (bank, addr));
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    pub fn name(&self, bank: usize, addr: u16) -> Option<&str> {
+        self.names.get(&(bank, addr)).map(|n| n.as_str())
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<(usize, u16)> {
+        self.addresses.get(name).copied()
+    }
+
+    /// Address of a symbol, if it is in a bank the cpu can see
+    pub fn visible_address(&self, name: &str) -> Option<u16> {
+        match self.lookup(name)? {
+            (bank, addr) if addr >= 0x8000 || bank == bank_of(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// An address in a bank as "name" or "$1234"
+    pub fn format_banked(&self, bank: usize, addr: u16) -> String {
+        match self.name(bank, addr) {
+            Some(name) => name.to_string(),
+            None => format!("${:04X}", addr),
+        }
+    }
+
+    /// Name the cpu sees at an address, ram symbols match in any bank
+    pub fn visible_name(&self, addr: u16) -> Option<&str> {
+        match addr {
+            0x8000..=0xFFFF => self.name(0, addr).or_else(|| self.name(1, addr)),
+            _ => self.name(bank_of(addr), addr),
+        }
+    }
+
+    /// An address as the cpu sees it, as "name" or "$1234"
+    pub fn format(&self, addr: u16) -> String {
+        match self.visible_name(addr) {
+            Some(name) => name.to_string(),
+            None => format!("${:04X}", addr),
+        }
+    }
+
+    /*
+     *  An address with the closest symbol at or before it, e.g. "$0153 <Main+3>"
+     *  Used for locations in code, where being inside a function is useful
+     */
+    pub fn describe(&self, addr: u16) -> String {
+        let bank = bank_of(addr);
+        let nearest = self.names.iter()
+            .filter(|((b, a), _)| *b == bank && *a <= addr && addr - *a < 0x1000)
+            .max_by_key(|((_, a), _)| *a);
+
+        match nearest {
+            Some(((_, a), name)) if *a == addr => format!("${:04X} <{}>", addr, name),
+            Some(((_, a), name)) => format!("${:04X} <{}+{}>", addr, name, addr - a),
+            None => format!("${:04X}", addr),
+        }
+    }
+
+}
@@ -0,0 +1,329 @@
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use jboy::cpu::Cpu;
+use jboy::debugger;
+use jboy::emulation::decode_at;
+use jboy::frontend::{Framebuffer, InputSource, VideoSink};
+use jboy::graphics::{LCDC, SCREEN_WIDTH};
+use jboy::joypad;
+use jboy::palettes::{Palette, Palettes};
+use jboy::symbols::Symbols;
+
+/// Column the register sidebar starts in, counting from 1
+pub const SIDEBAR_COLUMN: usize = SCREEN_WIDTH + 3;
+/// Terminals send no key releases, so a press holds the button this many frames
+pub const HOLD_FRAMES: u32 = 10;
+/// Instructions listed from pc down in the sidebar
+const INSTRUCTIONS: usize = 8;
+
+/// The upper and lower pixel of one character
+pub type Cell = ([u8; 3], [u8; 3]);
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Backspace,
+    Escape,
+    Function(u8),
+    CtrlC,
+}
+
+/*
+ *  Splits what the terminal sent into keys. Arrows and function keys
+ *  come as escape sequences, in both the normal and application cursor
+ *  forms. Anything else that starts with escape is dropped
+ */
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        i += 1;
+        let key = match byte {
+            0x1B if i == bytes.len() => Key::Escape,
+            0x1B if bytes[i] == b'[' || bytes[i] == b'O' => {
+                let start = i + 1;
+                let end = match bytes[start..].iter().position(|b| b.is_ascii_alphabetic() || *b == b'~') {
+                    Some(len) => start + len,
+                    None => break,
+                };
+                i = end + 1;
+                let code = std::str::from_utf8(&bytes[start..end]).unwrap_or("");
+                match (bytes[end], code) {
+                    (b'A', _) => Key::Up,
+                    (b'B', _) => Key::Down,
+                    (b'C', _) => Key::Right,
+                    (b'D', _) => Key::Left,
+                    (b'P'..=b'S', _) => Key::Function(bytes[end] - b'P' + 1),
+                    (b'~', "15") => Key::Function(5),
+                    (b'~', "17" | "18" | "19" | "20" | "21") => Key::Function(code.parse::<u8>().unwrap() - 11),
+                    (b'~', "23" | "24") => Key::Function(code.parse::<u8>().unwrap() - 12),
+                    _ => continue,
+                }
+            },
+            0x1B => Key::Escape,
+            0x03 => Key::CtrlC,
+            b'\r' | b'\n' => Key::Enter,
+            0x7F | 0x08 => Key::Backspace,
+            _ if byte.is_ascii() => Key::Char((byte as char).to_ascii_lowercase()),
+            _ => continue,
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+/// The button a key presses, Right Shift can't be seen so select is on space
+pub fn button_for(key: Key) -> Option<u8> {
+    match key {
+        Key::Char('z') => Some(joypad::A),
+        Key::Char('x') => Some(joypad::B),
+        Key::Char(' ') => Some(joypad::SELECT),
+        Key::Enter => Some(joypad::START),
+        Key::Up => Some(joypad::UP),
+        Key::Down => Some(joypad::DOWN),
+        Key::Left => Some(joypad::LEFT),
+        Key::Right => Some(joypad::RIGHT),
+        _ => None,
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Hotkey {
+    SaveState,
+    LoadState,
+    SelectSlot(u8),
+    TogglePause,
+    FrameAdvance,
+    CyclePalette,
+}
+
+pub fn hotkey_for(key: Key) -> Option<Hotkey> {
+    match key {
+        Key::Function(5) => Some(Hotkey::SaveState),
+        Key::Function(7) => Some(Hotkey::LoadState),
+        Key::Char(c @ '0'..='9') => Some(Hotkey::SelectSlot(c as u8 - b'0')),
+        Key::Char('p') => Some(Hotkey::TogglePause),
+        Key::Char('.') => Some(Hotkey::FrameAdvance),
+        Key::Char('-') => Some(Hotkey::CyclePalette),
+        _ => None,
+    }
+}
+
+/// The frame as characters, row by row, two pixels stacked in each
+pub fn cells(frame: &Framebuffer, palette: &Palette) -> Vec<Cell> {
+    frame.chunks(2)
+        .flat_map(|rows| rows[0].iter().zip(rows[1].iter()))
+        .map(|(top, bottom)| (top.color(palette), bottom.color(palette)))
+        .collect()
+}
+
+/*
+ *  Draws the characters that changed since the last frame with upper half
+ *  blocks, the upper pixel in the foreground color and the lower one in the
+ *  background. Colors and the cursor are only sent when they change, which
+ *  keeps a still screen down to a few bytes over ssh
+ */
+pub fn draw(cells: &[Cell], previous: &[Cell]) -> String {
+    let mut out = String::new();
+    let mut cursor = None;
+    let mut colors = None;
+    for (i, cell) in cells.iter().enumerate() {
+        if previous.get(i) == Some(cell) {
+            continue;
+        }
+        let (row, column) = (i / SCREEN_WIDTH, i % SCREEN_WIDTH);
+        if cursor != Some(i) {
+            let _ = write!(out, "\x1b[{};{}H", row + 1, column + 1);
+        }
+        if colors != Some(*cell) {
+            let ([r, g, b], [r2, g2, b2]) = *cell;
+            let _ = write!(out, "\x1b[38;2;{};{};{};48;2;{};{};{}m", r, g, b, r2, g2, b2);
+            colors = Some(*cell);
+        }
+        out.push('▀');
+        // The cursor wraps at the terminal's edge, not the screen's
+        cursor = if column + 1 < SCREEN_WIDTH { Some(i + 1) } else { None };
+    }
+    if !out.is_empty() {
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+/// Registers, a few flags and io registers, and the code from pc on
+pub fn sidebar(cpu: &Cpu, symbols: &Symbols) -> Vec<String> {
+    let r = &cpu.registers;
+    let mut lines = vec![
+        format!("PC {:04X}  SP {:04X}", r.pc, r.sp),
+        format!("A  {:02X}    F  {:02X}  {}", r.af.left, r.af.right, debugger::flags_string(cpu)),
+        format!("B  {:02X}    C  {:02X}", r.bc.left, r.bc.right),
+        format!("D  {:02X}    E  {:02X}", r.de.left, r.de.right),
+        format!("H  {:02X}    L  {:02X}", r.hl.left, r.hl.right),
+        format!("LY {:02X}    LCDC {:02X}", cpu.memory.peek(0xFF44), cpu.memory.peek(LCDC)),
+        format!("IF {:02X}    IE {:02X}", cpu.memory.peek(0xFF0F), cpu.memory.peek(0xFFFF)),
+        String::new(),
+    ];
+    let mut addr = r.pc;
+    for i in 0..INSTRUCTIONS {
+        let marker = if i == 0 { '>' } else { ' ' };
+        lines.push(format!("{}{:04X} {}", marker, addr, debugger::instruction_string(cpu, addr, symbols)));
+        addr = decode_at(cpu, addr).map(|d| d.next()).unwrap_or(addr.wrapping_add(1));
+    }
+    lines
+}
+
+/*
+ *  Terminal
+ *  Plays in a terminal that does truecolor, 160x144 in half blocks with the
+ *  sidebar to its right, so it wants 190 columns and 72 rows. Keys are read
+ *  unbuffered from the tty on a thread, and since a terminal only reports
+ *  presses a button stays down for HOLD_FRAMES after each one, which key
+ *  repeat keeps topped up while it's held
+ */
+pub struct Terminal {
+    keys: Receiver<Vec<u8>>,
+    tty: Option<String>,
+    /// Frames left for each joypad bit
+    held: [u32; 8],
+    pub hotkeys: Vec<Hotkey>,
+    quit: bool,
+    pub palettes: Palettes,
+    pub sidebar: Vec<String>,
+    /// Shown under the sidebar, the terminal's stand in for println
+    pub status: String,
+    previous: Vec<Cell>,
+    previous_sidebar: Vec<String>,
+}
+
+impl Terminal {
+
+    pub fn new(palettes: Palettes) -> Self {
+        // Without echo and line buffering keys arrive as they're pressed, and Ctrl+C comes through as a key
+        let tty = Command::new("stty").arg("-g").stderr(Stdio::null()).output().ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+        if tty.is_none() || !stty(&["-icanon", "-echo", "-isig", "min", "1"]) {
+            println!("stdin isn't a terminal, keys won't work");
+        }
+
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 64];
+            let mut stdin = std::io::stdin().lock();
+            while let Ok(count) = stdin.read(&mut buffer) {
+                if count == 0 || sender.send(buffer[..count].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // The alternate screen leaves the shell's scrollback alone
+        print!("\x1b[?1049h\x1b[?25l");
+        let _ = std::io::stdout().flush();
+        let _ = clearscreen::clear();
+
+        Self {
+            keys,
+            tty,
+            held: [0; 8],
+            hotkeys: Vec::new(),
+            quit: false,
+            palettes,
+            sidebar: Vec::new(),
+            status: String::new(),
+            previous: Vec::new(),
+            previous_sidebar: Vec::new(),
+        }
+    }
+
+    fn handle_key(&mut self, key: Key) {
+        if let Some(mask) = button_for(key) {
+            self.held[mask.trailing_zeros() as usize] = HOLD_FRAMES;
+        } else if let Some(hotkey) = hotkey_for(key) {
+            self.hotkeys.push(hotkey);
+        } else if matches!(key, Key::Char('q') | Key::CtrlC) {
+            self.quit = true;
+        }
+    }
+
+    /// The sidebar with the status a line below it
+    fn sidebar_lines(&self) -> Vec<String> {
+        let mut lines = self.sidebar.clone();
+        lines.push(String::new());
+        lines.push(self.status.clone());
+        lines
+    }
+
+    fn draw_sidebar(&self, lines: &[String]) -> String {
+        let mut out = String::new();
+        for (row, line) in lines.iter().enumerate() {
+            if self.previous_sidebar.get(row) != Some(line) {
+                let _ = write!(out, "\x1b[{};{}H{}\x1b[K", row + 1, SIDEBAR_COLUMN, line);
+            }
+        }
+        out
+    }
+
+}
+
+fn stty(args: &[&str]) -> bool {
+    Command::new("stty").args(args).status().is_ok_and(|status| status.success())
+}
+
+impl InputSource for Terminal {
+    fn poll(&mut self) {
+        for held in self.held.iter_mut() {
+            *held = held.saturating_sub(1);
+        }
+        while let Ok(bytes) = self.keys.try_recv() {
+            for key in parse_keys(&bytes) {
+                self.handle_key(key);
+            }
+        }
+    }
+
+    fn buttons(&self) -> u8 {
+        self.held.iter().enumerate()
+            .filter(|(_, frames)| **frames > 0)
+            .fold(0, |mask, (bit, _)| mask | 1 << bit)
+    }
+
+    fn quit(&self) -> bool {
+        self.quit
+    }
+}
+
+impl VideoSink for Terminal {
+    fn present(&mut self, frame: &Framebuffer) {
+        let cells = cells(frame, self.palettes.current());
+        let mut out = draw(&cells, &self.previous);
+        let lines = self.sidebar_lines();
+        out.push_str(&self.draw_sidebar(&lines));
+        self.previous = cells;
+        self.previous_sidebar = lines;
+
+        let mut stdout = std::io::stdout().lock();
+        let _ = stdout.write_all(out.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+        if let Some(tty) = &self.tty {
+            stty(&[tty]);
+        }
+    }
+}
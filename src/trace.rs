@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Lines, Write};
+
+use crate::cpu::Cpu;
+use crate::debugger::instruction_string;
+use crate::emulation::decode_at;
+use crate::symbols::Symbols;
+
+/*
+ *  Tracer
+ *  Writes one line per executed instruction in the gameboy-doctor format,
+ *  the machine state right before the instruction at PC runs:
+ *  A:00 F:11 B:22 C:33 D:44 E:55 H:66 L:77 SP:8888 PC:9999 PCMEM:AA,BB,CC,DD
+ */
+pub struct Tracer {
+    pub enabled: bool,
+    pub range: Option<(u16, u16)>,
+    out: Box<dyn Write + Send>,
+    error: Option<io::Error>,
+}
+
+/// The gameboy-doctor line for the cpu's current state
+pub fn doctor_line(cpu: &Cpu) -> String {
+    let r = &cpu.registers;
+    let pc = r.pc;
+    let mem = |i: u16| cpu.memory.peek(pc.wrapping_add(i));
+    format!("A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        r.af.left, r.af.right, r.bc.left, r.bc.right, r.de.left, r.de.right,
+        r.hl.left, r.hl.right, r.sp, pc, mem(0), mem(1), mem(2), mem(3))
+}
+
+/// Parses an address range written as "from:to", both ends included
+pub fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (from, to) = text.split_once(':')?;
+    let parse = |t: &str| u16::from_str_radix(t.trim_start_matches("0x").trim_start_matches('$'), 16).ok();
+    Some((parse(from)?, parse(to)?))
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            range: None,
+            out: Box::new(io::stdout()),
+            error: None,
+        }
+    }
+}
+
+impl Tracer {
+
+    /// A disabled tracer that writes to stdout once turned on
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An enabled tracer writing to a file
+    pub fn to_file(path: &str) -> io::Result<Self> {
+        Ok(Self::to_writer(Box::new(BufWriter::new(File::create(path)?))))
+    }
+
+    /// An enabled tracer writing anywhere
+    pub fn to_writer(out: Box<dyn Write + Send>) -> Self {
+        Self {
+            enabled: true,
+            range: None,
+            out,
+            error: None,
+        }
+    }
+
+    /// Whether the instruction at pc should be traced
+    pub fn wants(&self, pc: u16) -> bool {
+        self.enabled && self.range.is_none_or(|(from, to)| pc >= from && pc <= to)
+    }
+
+    /// Writes a line, turning the trace off if that fails
+    pub fn write_line(&mut self, line: &str) {
+        if let Err(e) = writeln!(self.out, "{}", line) {
+            self.enabled = false;
+            self.error = Some(e);
+        }
+    }
+
+    /// The write that turned the trace off, if one did since the last call
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Turns the trace on or off, returns whether it's now on
+    pub fn toggle(&mut self) -> bool {
+        self.enabled = !self.enabled;
+        self.flush();
+        self.enabled
+    }
+
+    pub fn flush(&mut self) {
+        let _ = self.out.flush();
+    }
+
+}
+
+pub const DEFAULT_HISTORY: usize = 16;
+
+/// Splits a trace line into its KEY:VALUE fields
+fn fields(line: &str) -> Vec<(&str, &str)> {
+    line.split_whitespace().filter_map(|f| f.split_once(':')).collect()
+}
+
+/// What the trace diff has to say about an instruction
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiffEvent {
+    /// The state differs from the reference, with a report of how
+    Diverged(String),
+    /// The reference ran out after this many lines without differing
+    Ended(usize),
+}
+
+/*
+ *  Trace Diff
+ *  Reads a known-good trace alongside the running one and reports the first
+ *  line where they disagree. Only fields present in both lines are compared,
+ *  so references without PCMEM still work
+ */
+pub struct TraceDiff {
+    lines: Lines<Box<dyn BufRead>>,
+    pub history_len: usize,
+    history: VecDeque<(String, String)>,
+    line_number: usize,
+    pub finished: bool,
+}
+
+impl TraceDiff {
+
+    pub fn new(reference: Box<dyn BufRead>, history_len: usize) -> Self {
+        Self {
+            lines: reference.lines(),
+            history_len,
+            history: VecDeque::new(),
+            line_number: 0,
+            finished: false,
+        }
+    }
+
+    pub fn open(path: &str, history_len: usize) -> io::Result<Self> {
+        Ok(Self::new(Box::new(BufReader::new(File::open(path)?)), history_len))
+    }
+
+    /*
+     *  Compares the cpu's state before an instruction against the next line
+     *  of the reference. Returns a report if they differ, or the line count
+     *  once the reference runs out
+     */
+    pub fn check(&mut self, cpu: &Cpu, symbols: &Symbols) -> Option<DiffEvent> {
+        if self.finished {
+            return None;
+        }
+
+        let expected = match self.lines.next() {
+            Some(Ok(line)) => line,
+            _ => {
+                self.finished = true;
+                return Some(DiffEvent::Ended(self.line_number));
+            },
+        };
+        self.line_number += 1;
+
+        let actual = doctor_line(cpu);
+        let expected_fields = fields(&expected);
+        let mismatches: Vec<String> = fields(&actual).into_iter()
+            .filter_map(|(key, value)| {
+                let (_, wanted) = expected_fields.iter().find(|(k, _)| *k == key)?;
+                if *wanted != value {
+                    Some(format!("{} expected {} got {}", key, wanted, value))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        if mismatches.is_empty() {
+            let text = instruction_string(cpu, cpu.registers.pc, symbols);
+            self.history.push_back((actual, text));
+            if self.history.len() > self.history_len {
+                self.history.pop_front();
+            }
+            return None;
+        }
+
+        self.finished = true;
+        Some(DiffEvent::Diverged(self.report(cpu, symbols, &expected, &actual, &mismatches)))
+    }
+
+    fn report(&self, cpu: &Cpu, symbols: &Symbols, expected: &str, actual: &str, mismatches: &[String]) -> String {
+        let mut out = format!("Trace diverged at line {}\n", self.line_number);
+        out.push_str(&format!("  expected: {}\n  actual:   {}\n", expected, actual));
+        for mismatch in mismatches {
+            out.push_str(&format!("  {}\n", mismatch));
+        }
+
+        out.push_str(&format!("Previous {} instructions:\n", self.history.len()));
+        for (line, text) in &self.history {
+            out.push_str(&format!("  {}  {}\n", line, text));
+        }
+
+        out.push_str("Disassembly at PC:\n");
+        let mut addr = cpu.registers.pc;
+        for i in 0..6 {
+            let marker = if i == 0 { "=>" } else { "  " };
+            out.push_str(&format!("{} {}: {}\n", marker, symbols.describe(addr), instruction_string(cpu, addr, symbols)));
+            addr = decode_at(cpu, addr).map_or(addr.wrapping_add(1), |d| d.next());
+        }
+        out
+    }
+
+}